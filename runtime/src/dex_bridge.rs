@@ -0,0 +1,150 @@
+//! # Dex Bridge Module
+//!
+//! Lets Wasm contracts quote and trade on the dex and move liquidity, and see
+//! what came of it.
+//!
+//! A contract reaches runtime modules by dispatching calls with
+//! `ext_dispatch_call`, which run once the contract has returned and hand
+//! nothing back to it.  It dispatches the calls of this module instead, naming
+//! a `selector` and a `gas_limit`: the quote or operation is made as the
+//! contract, and the contract is then called back with the selector followed
+//! by the SCALE encoded result as its input.  An ink! message with that
+//! selector, taking an `Outcome` or, for quotes, an `Option<Balance>`, is
+//! handed the result typed.
+//!
+//! The reply is a contract call made by the contract itself: the contract buys
+//! its `gas_limit` at the contracts gas price before the reply and is refunded
+//! what is left, and the gas counts towards the contracts block gas limit.
+//! `gas_limit` is capped at `MaxReplyGas`, and the calls weigh their share of
+//! the contracts block gas limit.  An operation whose reply fails, because the
+//! contract trapped, ran out of gas or could not pay for it, is undone, so a
+//! contract can refuse an outcome it does not like.
+
+use frame_support::{
+	decl_module, decl_event, dispatch, ensure,
+	traits::Get,
+	weights::{ClassifyDispatch, DispatchClass, PaysFee, WeighData, Weight},
+};
+use codec::{Encode, Decode};
+use system::{ensure_signed, RawOrigin};
+use sp_runtime::traits::{StaticLookup, Zero};
+use sp_std::{marker::PhantomData, prelude::*};
+use crate::moonbeam::{self, Operation, Outcome};
+
+/// A price asked of the dex.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum Quote<Balance> {
+	/// The tokens `glmr_value` glmr would buy, see `quote_glmr_to_token`.
+	GlmrToToken { glmr_value: Balance },
+	/// The glmr `token_value` tokens would buy, see `quote_token_to_glmr`.
+	TokenToGlmr { token_value: Balance },
+}
+
+/// The weight of a quote or dex operation, a single dex call, plus the share
+/// of the weight available to a block that the reply gas limit is of the
+/// contracts block gas limit.
+pub struct ReplyWeight<T>(pub PhantomData<T>);
+
+impl<T: Trait> ReplyWeight<T> {
+	fn weigh_reply(gas_limit: contracts::Gas) -> Weight {
+		let block_gas_limit = <T as contracts::Trait>::BlockGasLimit::get().max(1);
+		let available = T::AvailableBlockRatio::get() * T::MaximumBlockWeight::get();
+		let reply = available as u64 * gas_limit.min(block_gas_limit) / block_gas_limit;
+		(reply as Weight).saturating_add(10_000)
+	}
+}
+
+impl<'a, T: Trait> WeighData<(&'a Quote<T::Balance>, &'a Vec<u8>, &'a contracts::Gas)> for ReplyWeight<T> {
+	fn weigh_data(&self, (_, _, gas_limit): (&'a Quote<T::Balance>, &'a Vec<u8>, &'a contracts::Gas)) -> Weight {
+		Self::weigh_reply(*gas_limit)
+	}
+}
+
+impl<'a, T: Trait> WeighData<(&'a Operation<T::Balance>, &'a Vec<u8>, &'a contracts::Gas)> for ReplyWeight<T> {
+	fn weigh_data(&self, (_, _, gas_limit): (&'a Operation<T::Balance>, &'a Vec<u8>, &'a contracts::Gas)) -> Weight {
+		Self::weigh_reply(*gas_limit)
+	}
+}
+
+impl<T, A> ClassifyDispatch<A> for ReplyWeight<T> {
+	fn classify_dispatch(&self, _: A) -> DispatchClass {
+		DispatchClass::Normal
+	}
+}
+
+impl<T> PaysFee for ReplyWeight<T> {
+	fn pays_fee(&self) -> bool {
+		true
+	}
+}
+
+pub trait Trait: moonbeam::Trait + contracts::Trait {
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+	/// The most gas a reply may be given.
+	type MaxReplyGas: Get<contracts::Gas>;
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event() = default;
+
+		/// Answers `quote` and calls the sender back with `selector` and the
+		/// encoded `Option<Balance>` answer, none if the pool can not price it.
+		#[weight = ReplyWeight::<T>(PhantomData)]
+		fn quote(origin, quote: Quote<T::Balance>, selector: Vec<u8>, gas_limit: contracts::Gas) -> dispatch::Result {
+			let contract = ensure_signed(origin)?;
+			ensure!(gas_limit <= T::MaxReplyGas::get(), "Reply gas limit is too high");
+
+			let price = match quote {
+				Quote::GlmrToToken { glmr_value } => <moonbeam::Module<T>>::quote_glmr_to_token(glmr_value),
+				Quote::TokenToGlmr { token_value } => <moonbeam::Module<T>>::quote_token_to_glmr(token_value),
+			};
+			Self::reply(&contract, selector, price, gas_limit)
+		}
+
+		/// Makes a dex operation as the sender and calls it back with `selector`
+		/// and the encoded `Outcome`.  The operation is undone if the reply
+		/// fails.
+		#[weight = ReplyWeight::<T>(PhantomData)]
+		fn execute(origin, operation: Operation<T::Balance>, selector: Vec<u8>, gas_limit: contracts::Gas) -> dispatch::Result {
+			let contract = ensure_signed(origin)?;
+			ensure!(gas_limit <= T::MaxReplyGas::get(), "Reply gas limit is too high");
+
+			let outcome = <moonbeam::Module<T>>::execute_then(&contract, operation, |outcome| {
+				Self::reply(&contract, selector, outcome, gas_limit).map(|_| outcome)
+			})?;
+			Self::deposit_event(RawEvent::Executed(contract, outcome));
+
+			Ok(())
+		}
+	}
+}
+
+decl_event!(
+	pub enum Event<T>
+	where
+		AccountId = <T as system::Trait>::AccountId,
+		Balance = <T as balances::Trait>::Balance
+	{
+		/// A contract made a dex operation and accepted its outcome.
+		Executed(AccountId, Outcome<Balance>),
+	}
+);
+
+impl<T: Trait> Module<T> {
+	/// Has `contract` call itself with `selector` followed by the encoded
+	/// `result`, buying the gas as any contract call does.
+	fn reply<R: Encode>(contract: &T::AccountId, selector: Vec<u8>, result: R, gas_limit: contracts::Gas) -> dispatch::Result {
+		let mut input = selector;
+		result.encode_to(&mut input);
+
+		<contracts::Module<T>>::call(
+			RawOrigin::Signed(contract.clone()).into(),
+			T::Lookup::unlookup(contract.clone()),
+			Zero::zero(),
+			gas_limit,
+			input,
+		).map_err(|_| "Contract failed to take the reply")
+	}
+}
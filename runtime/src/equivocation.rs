@@ -0,0 +1,375 @@
+//! # Equivocation Module
+//!
+//! Lets anyone report an authority that signed two conflicting messages, so
+//! that it is slashed.
+//!
+//! An Aura authority equivocates by sealing two different blocks for the same
+//! slot, and a GRANDPA voter by casting two different prevotes, or two
+//! different precommits, in the same round.  A report carries both signed
+//! messages and a proof, from the historical sessions, of the validator that
+//! owns the signing key.  The validator is reported to `ReportEquivocation`,
+//! the offences module in the runtime, for staking to slash at least
+//! `EquivocationSlash` of its stake, and more when a larger part of the
+//! validator set equivocates at the same time.
+//!
+//! To tell which session an equivocation happened in, the module notes the
+//! first slot and the number of validators of every session, and the session
+//! every GRANDPA authority set started in.  The key ownership proof has to be
+//! from that session, which for GRANDPA is the one its authority set started
+//! in, and is made at a block of it with the `EquivocationApi` runtime API.
+//! Sessions more than `ReportableSessions` old, and the authority sets that
+//! ended before them, are forgotten, and can no longer be reported.
+
+use frame_support::{
+	decl_module, decl_storage, dispatch, ensure,
+	traits::{Get, KeyOwnerProofSystem},
+	Parameter,
+};
+use codec::{Encode, Decode};
+use system::ensure_signed;
+use sp_core::crypto::KeyTypeId;
+use sp_consensus_aura::AURA_ENGINE_ID;
+use sp_runtime::{
+	Perbill, RuntimeAppPublic,
+	generic::DigestItem,
+	traits::{Header as HeaderT, Member},
+};
+use sp_staking::{SessionIndex, offence::{Kind, Offence, ReportOffence}};
+use session::historical::{IdentificationTuple, Proof};
+use grandpa::fg_primitives::SetId;
+use sp_std::prelude::*;
+
+pub mod runtime_api;
+#[cfg(test)]
+mod mock;
+#[cfg(test)]
+mod tests;
+
+/// A GRANDPA vote, which encodes as the GRANDPA message it is signed as.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum GrandpaVote<Hash, BlockNumber> {
+	/// A prevote for a block hash and number.
+	Prevote(Hash, BlockNumber),
+	/// A precommit for a block hash and number.
+	Precommit(Hash, BlockNumber),
+}
+
+/// Two different votes of the same kind a GRANDPA voter signed in one round.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct GrandpaEquivocation<Hash, BlockNumber, AuthorityId, Signature> {
+	/// The authority set the votes were cast in.
+	pub set_id: SetId,
+	/// The round the votes were cast in.
+	pub round: u64,
+	/// The voter.
+	pub offender: AuthorityId,
+	/// The first vote and its signature.
+	pub first: (GrandpaVote<Hash, BlockNumber>, Signature),
+	/// The second vote and its signature.
+	pub second: (GrandpaVote<Hash, BlockNumber>, Signature),
+}
+
+/// Two different headers an Aura authority sealed for one slot.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct AuraEquivocation<Header, AuthorityId> {
+	/// The session the slot is in.
+	pub session_index: SessionIndex,
+	/// The slot both headers were sealed for.
+	pub slot: u64,
+	/// The authority that sealed them.
+	pub offender: AuthorityId,
+	/// The first header, with its seal.
+	pub first_header: Header,
+	/// The second header, with its seal.
+	pub second_header: Header,
+}
+
+pub type GrandpaEquivocationOf<T> = GrandpaEquivocation<
+	<T as system::Trait>::Hash,
+	<T as system::Trait>::BlockNumber,
+	<T as Trait>::GrandpaId,
+	<<T as Trait>::GrandpaId as RuntimeAppPublic>::Signature,
+>;
+
+pub type AuraEquivocationOf<T> = AuraEquivocation<<T as system::Trait>::Header, <T as Trait>::AuraId>;
+
+pub trait Trait: session::historical::Trait {
+	/// The key Aura authorities seal blocks with.
+	type AuraId: Member + Parameter + RuntimeAppPublic;
+	/// The key GRANDPA voters sign votes with.
+	type GrandpaId: Member + Parameter + RuntimeAppPublic;
+	/// The current GRANDPA authority set.
+	type GrandpaSetId: Get<SetId>;
+	/// Where equivocating validators are reported.
+	type ReportEquivocation:
+		ReportOffence<Self::AccountId, IdentificationTuple<Self>, AuraEquivocationOffence<Self>> +
+		ReportOffence<Self::AccountId, IdentificationTuple<Self>, GrandpaEquivocationOffence<Self>>;
+	/// The least part of its stake a validator equivocating alone is slashed.
+	type EquivocationSlash: Get<Perbill>;
+	/// How many sessions back equivocations can be reported, as long as the
+	/// stake of the session's validators can still be slashed.
+	type ReportableSessions: Get<SessionIndex>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Equivocation {
+		/// The first slot and the number of validators of each session.
+		Sessions get(session_info): map SessionIndex => Option<(u64, u32)>;
+		/// The session each GRANDPA authority set started in.
+		GrandpaSetSessions get(grandpa_set_session): map SetId => Option<SessionIndex>;
+		/// The oldest GRANDPA authority set in `GrandpaSetSessions`.
+		EarliestGrandpaSet get(earliest_grandpa_set): Option<SetId>;
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		/// Reports an Aura authority that sealed two different headers for the
+		/// same slot, with a proof of the validator owning its key.
+		fn report_aura_equivocation(
+			origin,
+			equivocation: AuraEquivocationOf<T>,
+			key_owner_proof: Proof
+		) -> dispatch::Result {
+			let reporter = ensure_signed(origin)?;
+			let AuraEquivocation { session_index, slot, offender, first_header, second_header } = equivocation;
+			ensure!(
+				proof_session(&key_owner_proof) == Some(session_index),
+				"Key ownership proof is not for the session"
+			);
+
+			let (first_slot, first_hash) = sealed_slot::<T>(&first_header, &offender)?;
+			let (second_slot, second_hash) = sealed_slot::<T>(&second_header, &offender)?;
+			ensure!(first_slot == slot && second_slot == slot, "Headers are not for the slot");
+			// a block resealed is not an equivocation
+			ensure!(first_hash != second_hash, "Headers are not an equivocation");
+
+			let (session_start, validator_set_count) = Self::session_info(session_index)
+				.ok_or("Unknown session")?;
+			let session_end = Self::session_info(session_index + 1).map(|(next_start, _)| next_start);
+			ensure!(
+				session_start <= slot && session_end.map_or(true, |end| slot < end),
+				"Slot is not in the session"
+			);
+
+			let offender = Self::key_owner(<T::AuraId as RuntimeAppPublic>::ID, &offender, key_owner_proof)?;
+			T::ReportEquivocation::report_offence(vec![reporter], AuraEquivocationOffence {
+				slot,
+				session_index,
+				validator_set_count,
+				offender,
+			});
+
+			Ok(())
+		}
+
+		/// Reports a GRANDPA voter that cast two different votes of the same
+		/// kind in one round, with a proof of the validator owning its key.
+		fn report_grandpa_equivocation(
+			origin,
+			equivocation: GrandpaEquivocationOf<T>,
+			key_owner_proof: Proof
+		) -> dispatch::Result {
+			let reporter = ensure_signed(origin)?;
+			let GrandpaEquivocation { set_id, round, offender, first, second } = equivocation;
+
+			let same_kind = match (&first.0, &second.0) {
+				(GrandpaVote::Prevote(..), GrandpaVote::Prevote(..)) => true,
+				(GrandpaVote::Precommit(..), GrandpaVote::Precommit(..)) => true,
+				_ => false,
+			};
+			ensure!(same_kind && first.0 != second.0, "Votes are not an equivocation");
+			for (vote, signature) in [&first, &second].iter() {
+				let signed = (vote, round, set_id).using_encoded(|payload| offender.verify(&payload, signature));
+				ensure!(signed, "Invalid vote signature");
+			}
+
+			let session_index = Self::grandpa_set_session(set_id).ok_or("Unknown authority set")?;
+			let (_, validator_set_count) = Self::session_info(session_index).ok_or("Unknown session")?;
+			ensure!(
+				proof_session(&key_owner_proof) == Some(session_index),
+				"Key ownership proof is not for the session"
+			);
+
+			let offender = Self::key_owner(<T::GrandpaId as RuntimeAppPublic>::ID, &offender, key_owner_proof)?;
+			T::ReportEquivocation::report_offence(vec![reporter], GrandpaEquivocationOffence {
+				set_id,
+				round,
+				session_index,
+				validator_set_count,
+				offender,
+			});
+
+			Ok(())
+		}
+
+		fn on_finalize() {
+			let session_index = <session::Module<T>>::current_index();
+			if !<Sessions>::exists(session_index) {
+				if let Some(slot) = Self::current_slot() {
+					let validator_count = <session::Module<T>>::validators().len() as u32;
+					<Sessions>::insert(session_index, (slot, validator_count));
+					Self::prune(session_index);
+				}
+			}
+
+			let set_id = T::GrandpaSetId::get();
+			if !<GrandpaSetSessions>::exists(set_id) {
+				<GrandpaSetSessions>::insert(set_id, session_index);
+				if !EarliestGrandpaSet::exists() {
+					EarliestGrandpaSet::put(set_id);
+				}
+			}
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// The slot of the block being built.
+	fn current_slot() -> Option<u64> {
+		aura_slot(<system::Module<T>>::digest().logs())
+	}
+
+	/// Forgets the sessions more than `ReportableSessions` before
+	/// `session_index`, and the GRANDPA authority sets that ended before them.
+	fn prune(session_index: SessionIndex) {
+		let oldest = match session_index.checked_sub(T::ReportableSessions::get()) {
+			Some(oldest) => oldest,
+			None => return,
+		};
+		if let Some(pruned) = oldest.checked_sub(1) {
+			<Sessions>::remove(pruned);
+		}
+
+		if let Some(mut earliest) = Self::earliest_grandpa_set() {
+			// a set lasts until the session the next one starts in
+			while Self::grandpa_set_session(earliest + 1).map_or(false, |next_start| next_start <= oldest) {
+				<GrandpaSetSessions>::remove(earliest);
+				earliest += 1;
+			}
+			EarliestGrandpaSet::put(earliest);
+		}
+	}
+
+	/// The validator that owns `key`, checked against `proof`.
+	fn key_owner<K: Encode>(
+		key_type: KeyTypeId,
+		key: &K,
+		proof: Proof,
+	) -> Result<IdentificationTuple<T>, &'static str> {
+		<session::historical::Module<T> as KeyOwnerProofSystem<(KeyTypeId, Vec<u8>)>>::check_proof(
+			(key_type, key.encode()),
+			proof,
+		).ok_or("Invalid key ownership proof")
+	}
+}
+
+/// The session a key ownership proof was made in, which `Proof` keeps private
+/// but encodes first.
+fn proof_session(proof: &Proof) -> Option<SessionIndex> {
+	SessionIndex::decode(&mut &proof.encode()[..]).ok()
+}
+
+/// The slot in an Aura pre-runtime digest.
+fn aura_slot<Hash>(logs: &[DigestItem<Hash>]) -> Option<u64> {
+	logs.iter()
+		.filter_map(DigestItem::as_pre_runtime)
+		.find(|(engine_id, _)| *engine_id == AURA_ENGINE_ID)
+		.and_then(|(_, mut slot)| u64::decode(&mut slot).ok())
+}
+
+/// The slot `header` was sealed for and its hash without the seal, if `author`
+/// sealed it.
+fn sealed_slot<T: Trait>(header: &T::Header, author: &T::AuraId) -> Result<(u64, T::Hash), &'static str> {
+	let mut header = header.clone();
+	let seal = match header.digest_mut().pop() {
+		Some(DigestItem::Seal(engine_id, seal)) if engine_id == AURA_ENGINE_ID => seal,
+		_ => return Err("Header is not sealed by Aura"),
+	};
+	let signature = <T::AuraId as RuntimeAppPublic>::Signature::decode(&mut &seal[..])
+		.map_err(|_| "Invalid Aura seal")?;
+
+	let pre_hash = header.hash();
+	ensure!(author.verify(&pre_hash, &signature), "Invalid Aura seal");
+	let slot = aura_slot(header.digest().logs()).ok_or("Header has no Aura slot")?;
+
+	Ok((slot, pre_hash))
+}
+
+/// The part of their stake equivocating validators are slashed: the square
+/// of three times the share of the validator set equivocating, so that a
+/// third of the set is slashed all of its stake, and at least `minimum`.
+fn equivocation_slash(minimum: Perbill, offenders_count: u32, validator_set_count: u32) -> Perbill {
+	let share = Perbill::from_rational_approximation(3 * offenders_count, validator_set_count.max(1));
+	share.square().max(minimum)
+}
+
+/// An Aura authority sealed two blocks for one slot.
+pub struct AuraEquivocationOffence<T: Trait> {
+	pub slot: u64,
+	pub session_index: SessionIndex,
+	pub validator_set_count: u32,
+	pub offender: IdentificationTuple<T>,
+}
+
+impl<T: Trait> Offence<IdentificationTuple<T>> for AuraEquivocationOffence<T> {
+	const ID: Kind = *b"aura:equivocatio";
+	type TimeSlot = u64;
+
+	fn offenders(&self) -> Vec<IdentificationTuple<T>> {
+		vec![self.offender.clone()]
+	}
+
+	fn session_index(&self) -> SessionIndex {
+		self.session_index
+	}
+
+	fn validator_set_count(&self) -> u32 {
+		self.validator_set_count
+	}
+
+	fn time_slot(&self) -> Self::TimeSlot {
+		self.slot
+	}
+
+	fn slash_fraction(offenders_count: u32, validator_set_count: u32) -> Perbill {
+		equivocation_slash(T::EquivocationSlash::get(), offenders_count, validator_set_count)
+	}
+}
+
+/// A GRANDPA voter cast two different votes of the same kind in one round.
+pub struct GrandpaEquivocationOffence<T: Trait> {
+	pub set_id: SetId,
+	pub round: u64,
+	pub session_index: SessionIndex,
+	pub validator_set_count: u32,
+	pub offender: IdentificationTuple<T>,
+}
+
+impl<T: Trait> Offence<IdentificationTuple<T>> for GrandpaEquivocationOffence<T> {
+	const ID: Kind = *b"grandpa:equivoca";
+	type TimeSlot = (SetId, u64);
+
+	fn offenders(&self) -> Vec<IdentificationTuple<T>> {
+		vec![self.offender.clone()]
+	}
+
+	fn session_index(&self) -> SessionIndex {
+		self.session_index
+	}
+
+	fn validator_set_count(&self) -> u32 {
+		self.validator_set_count
+	}
+
+	fn time_slot(&self) -> Self::TimeSlot {
+		(self.set_id, self.round)
+	}
+
+	fn slash_fraction(offenders_count: u32, validator_set_count: u32) -> Perbill {
+		equivocation_slash(T::EquivocationSlash::get(), offenders_count, validator_set_count)
+	}
+}
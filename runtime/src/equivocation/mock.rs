@@ -0,0 +1,161 @@
+//! Test runtime for the equivocation module.
+
+use std::cell::RefCell;
+use codec::Encode;
+use frame_support::{impl_outer_origin, parameter_types, traits::Get, weights::Weight};
+use sp_core::{H256, crypto::KeyTypeId};
+use sp_runtime::{
+	Perbill,
+	testing::{Header, UintAuthorityId},
+	traits::{BlakeTwo256, ConvertInto, IdentityLookup, OpaqueKeys},
+};
+use sp_staking::{SessionIndex, offence::{Kind, Offence, ReportOffence}};
+use super::{Module, Trait};
+
+impl_outer_origin! {
+	pub enum Origin for Test {}
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Test;
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: Weight = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::one();
+}
+
+impl system::Trait for Test {
+	type Origin = Origin;
+	type Call = ();
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = ();
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+}
+
+/// Keeps no state for the session keys.
+pub struct TestSessionHandler;
+impl session::SessionHandler<u64> for TestSessionHandler {
+	const KEY_TYPE_IDS: &'static [KeyTypeId] = &[sp_runtime::key_types::DUMMY];
+
+	fn on_genesis_session<Ks: OpaqueKeys>(_validators: &[(u64, Ks)]) {}
+
+	fn on_new_session<Ks: OpaqueKeys>(_changed: bool, _validators: &[(u64, Ks)], _queued_validators: &[(u64, Ks)]) {}
+
+	fn on_disabled(_validator_index: usize) {}
+}
+
+parameter_types! {
+	pub const Period: u64 = 10;
+	pub const Offset: u64 = 0;
+	pub const DisabledValidatorsThreshold: Perbill = Perbill::from_percent(33);
+}
+
+impl session::Trait for Test {
+	type OnSessionEnding = ();
+	type SessionHandler = TestSessionHandler;
+	type ShouldEndSession = session::PeriodicSessions<Period, Offset>;
+	type Event = ();
+	type Keys = UintAuthorityId;
+	type ValidatorId = u64;
+	type ValidatorIdOf = ConvertInto;
+	type SelectInitialValidators = ();
+	type DisabledValidatorsThreshold = DisabledValidatorsThreshold;
+}
+
+impl session::historical::Trait for Test {
+	type FullIdentification = u64;
+	type FullIdentificationOf = ConvertInto;
+}
+
+pub type IdentificationTuple = session::historical::IdentificationTuple<Test>;
+
+/// An offence reported to `OffenceHandler`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Report {
+	pub kind: Kind,
+	pub reporters: Vec<u64>,
+	pub offenders: Vec<IdentificationTuple>,
+	pub session_index: SessionIndex,
+	pub validator_set_count: u32,
+	pub time_slot: Vec<u8>,
+}
+
+thread_local! {
+	pub static OFFENCES: RefCell<Vec<Report>> = RefCell::new(vec![]);
+}
+
+/// Records the offences reported.
+pub struct OffenceHandler;
+impl<O: Offence<IdentificationTuple>> ReportOffence<u64, IdentificationTuple, O> for OffenceHandler {
+	fn report_offence(reporters: Vec<u64>, offence: O) {
+		OFFENCES.with(|offences| offences.borrow_mut().push(Report {
+			kind: O::ID,
+			reporters,
+			offenders: offence.offenders(),
+			session_index: offence.session_index(),
+			validator_set_count: offence.validator_set_count(),
+			time_slot: offence.time_slot().encode(),
+		}));
+	}
+}
+
+pub fn offences() -> Vec<Report> {
+	OFFENCES.with(|offences| offences.borrow().clone())
+}
+
+thread_local! {
+	pub static GRANDPA_SET_ID: RefCell<u64> = RefCell::new(3);
+}
+
+/// The current GRANDPA authority set, 3 unless changed by `set_grandpa_set_id`.
+pub struct GrandpaSetId;
+impl Get<u64> for GrandpaSetId {
+	fn get() -> u64 {
+		GRANDPA_SET_ID.with(|set_id| *set_id.borrow())
+	}
+}
+
+pub fn set_grandpa_set_id(set_id: u64) {
+	GRANDPA_SET_ID.with(|current| *current.borrow_mut() = set_id);
+}
+
+parameter_types! {
+	pub const EquivocationSlash: Perbill = Perbill::from_percent(10);
+	pub const ReportableSessions: u32 = 2;
+}
+
+impl Trait for Test {
+	type AuraId = UintAuthorityId;
+	type GrandpaId = UintAuthorityId;
+	type GrandpaSetId = GrandpaSetId;
+	type ReportEquivocation = OffenceHandler;
+	type EquivocationSlash = EquivocationSlash;
+	type ReportableSessions = ReportableSessions;
+}
+
+pub type System = system::Module<Test>;
+pub type Session = session::Module<Test>;
+pub type Historical = session::historical::Module<Test>;
+pub type Equivocation = Module<Test>;
+
+/// Build test externalities with validators 1, 2 and 3, whose keys are the
+/// `UintAuthorityId` of the same number.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	let mut storage = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	session::GenesisConfig::<Test> {
+		keys: (1..=3).map(|v| (v, UintAuthorityId(v))).collect(),
+	}.assimilate_storage(&mut storage).unwrap();
+	storage.into()
+}
@@ -0,0 +1,16 @@
+//! Runtime API for the key ownership proofs equivocation reports carry.
+
+use sp_core::crypto::KeyTypeId;
+use sp_std::prelude::*;
+use session::historical::Proof;
+
+sp_api::decl_runtime_apis! {
+	/// Proofs of the validators owning session keys.
+	pub trait EquivocationApi {
+		/// A proof of the validator of this block's session that owns `key` of
+		/// `key_type`, none if no validator of the session owns it.  A report
+		/// needs the proof from the session the equivocation happened in, so
+		/// this is called at a block of that session.
+		fn prove_key_owner(key_type: KeyTypeId, key: Vec<u8>) -> Option<Proof>;
+	}
+}
@@ -0,0 +1,205 @@
+//! Tests for the equivocation module.
+
+use super::*;
+use super::mock::*;
+use frame_support::assert_ok;
+use sp_core::H256;
+use sp_runtime::{generic::Digest, key_types::DUMMY, testing::{Header, UintAuthorityId}, traits::OnFinalize};
+
+/// Starts a block at `slot` and notes its session.
+fn finalize_block_at_slot(slot: u64) {
+	System::deposit_log(DigestItem::PreRuntime(AURA_ENGINE_ID, slot.encode()));
+	Equivocation::on_finalize(System::block_number());
+}
+
+fn key_owner_proof(authority: u64) -> Proof {
+	Historical::prove((DUMMY, UintAuthorityId(authority).encode())).unwrap()
+}
+
+/// A header for `slot` sealed by `author`, told apart from others by `state_root`.
+fn sealed_header(slot: u64, author: u64, state_root: u8) -> Header {
+	let mut header = Header::new(
+		1,
+		Default::default(),
+		H256::repeat_byte(state_root),
+		Default::default(),
+		Digest { logs: vec![DigestItem::PreRuntime(AURA_ENGINE_ID, slot.encode())] },
+	);
+	let seal = UintAuthorityId(author).sign(&header.hash()).unwrap();
+	header.digest_mut().push(DigestItem::Seal(AURA_ENGINE_ID, seal.encode()));
+	header
+}
+
+fn aura_equivocation(slot: u64, author: u64, first_header: Header, second_header: Header) -> AuraEquivocationOf<Test> {
+	AuraEquivocation { session_index: 0, slot, offender: UintAuthorityId(author), first_header, second_header }
+}
+
+fn signed_vote(voter: u64, round: u64, vote: GrandpaVote<H256, u64>) -> (GrandpaVote<H256, u64>, <UintAuthorityId as RuntimeAppPublic>::Signature) {
+	let signature = UintAuthorityId(voter).sign(&(&vote, round, GrandpaSetId::get()).encode()).unwrap();
+	(vote, signature)
+}
+
+fn grandpa_equivocation(voter: u64, first: GrandpaVote<H256, u64>, second: GrandpaVote<H256, u64>) -> GrandpaEquivocationOf<Test> {
+	GrandpaEquivocation {
+		set_id: GrandpaSetId::get(),
+		round: 7,
+		offender: UintAuthorityId(voter),
+		first: signed_vote(voter, 7, first),
+		second: signed_vote(voter, 7, second),
+	}
+}
+
+#[test]
+fn aura_authorities_sealing_two_blocks_for_a_slot_are_reported() {
+	new_test_ext().execute_with(|| {
+		finalize_block_at_slot(10);
+		assert_eq!(Equivocation::session_info(0), Some((10, 3)));
+
+		let equivocation = aura_equivocation(12, 2, sealed_header(12, 2, 1), sealed_header(12, 2, 2));
+		assert_ok!(Equivocation::report_aura_equivocation(Origin::signed(9), equivocation, key_owner_proof(2)));
+		assert_eq!(offences(), vec![Report {
+			kind: *b"aura:equivocatio",
+			reporters: vec![9],
+			offenders: vec![(2, 2)],
+			session_index: 0,
+			validator_set_count: 3,
+			time_slot: 12u64.encode(),
+		}]);
+	});
+}
+
+#[test]
+fn aura_reports_need_two_blocks_sealed_by_the_offender_in_the_session() {
+	new_test_ext().execute_with(|| {
+		finalize_block_at_slot(10);
+		let report = |equivocation| {
+			Equivocation::report_aura_equivocation(Origin::signed(9), equivocation, key_owner_proof(2))
+		};
+
+		let header = sealed_header(12, 2, 1);
+		assert_eq!(report(aura_equivocation(12, 2, header.clone(), header)), Err("Headers are not an equivocation"));
+		assert_eq!(
+			report(aura_equivocation(12, 2, sealed_header(12, 2, 1), sealed_header(13, 2, 2))),
+			Err("Headers are not for the slot"),
+		);
+		assert_eq!(
+			report(aura_equivocation(12, 2, sealed_header(12, 2, 1), sealed_header(12, 3, 2))),
+			Err("Invalid Aura seal"),
+		);
+		assert_eq!(
+			report(aura_equivocation(8, 2, sealed_header(8, 2, 1), sealed_header(8, 2, 2))),
+			Err("Slot is not in the session"),
+		);
+		// a key that no validator owns
+		assert_eq!(
+			report(aura_equivocation(12, 5, sealed_header(12, 5, 1), sealed_header(12, 5, 2))),
+			Err("Invalid key ownership proof"),
+		);
+		assert!(offences().is_empty());
+	});
+}
+
+#[test]
+fn grandpa_voters_casting_two_votes_of_a_kind_in_a_round_are_reported() {
+	new_test_ext().execute_with(|| {
+		let report = |equivocation| {
+			Equivocation::report_grandpa_equivocation(Origin::signed(9), equivocation, key_owner_proof(1))
+		};
+		let (a, b) = (H256::repeat_byte(1), H256::repeat_byte(2));
+
+		assert_eq!(
+			report(grandpa_equivocation(1, GrandpaVote::Prevote(a, 5), GrandpaVote::Prevote(b, 5))),
+			Err("Unknown authority set"),
+		);
+		finalize_block_at_slot(10);
+		assert_eq!(Equivocation::grandpa_set_session(GrandpaSetId::get()), Some(0));
+
+		assert_eq!(
+			report(grandpa_equivocation(1, GrandpaVote::Prevote(a, 5), GrandpaVote::Prevote(a, 5))),
+			Err("Votes are not an equivocation"),
+		);
+		assert_eq!(
+			report(grandpa_equivocation(1, GrandpaVote::Prevote(a, 5), GrandpaVote::Precommit(b, 5))),
+			Err("Votes are not an equivocation"),
+		);
+		let mut forged = grandpa_equivocation(1, GrandpaVote::Precommit(a, 5), GrandpaVote::Precommit(b, 6));
+		forged.second = signed_vote(2, 7, GrandpaVote::Precommit(b, 6));
+		assert_eq!(report(forged), Err("Invalid vote signature"));
+
+		assert_ok!(report(grandpa_equivocation(1, GrandpaVote::Precommit(a, 5), GrandpaVote::Precommit(b, 6))));
+		assert_eq!(offences(), vec![Report {
+			kind: *b"grandpa:equivoca",
+			reporters: vec![9],
+			offenders: vec![(1, 1)],
+			session_index: 0,
+			validator_set_count: 3,
+			time_slot: (GrandpaSetId::get(), 7u64).encode(),
+		}]);
+	});
+}
+
+#[test]
+fn key_ownership_proofs_must_be_from_the_session_of_the_equivocation() {
+	new_test_ext().execute_with(|| {
+		finalize_block_at_slot(10);
+		Session::rotate_session();
+		finalize_block_at_slot(20);
+		assert_eq!(Session::current_index(), 1);
+
+		// the keys of session 0 proven in session 1
+		let equivocation = aura_equivocation(12, 2, sealed_header(12, 2, 1), sealed_header(12, 2, 2));
+		assert_eq!(
+			Equivocation::report_aura_equivocation(Origin::signed(9), equivocation, key_owner_proof(2)),
+			Err("Key ownership proof is not for the session"),
+		);
+
+		// the authority set started in session 0
+		let (a, b) = (H256::repeat_byte(1), H256::repeat_byte(2));
+		let equivocation = grandpa_equivocation(1, GrandpaVote::Prevote(a, 5), GrandpaVote::Prevote(b, 5));
+		assert_eq!(
+			Equivocation::report_grandpa_equivocation(Origin::signed(9), equivocation, key_owner_proof(1)),
+			Err("Key ownership proof is not for the session"),
+		);
+		assert!(offences().is_empty());
+	});
+}
+
+#[test]
+fn sessions_and_authority_sets_too_old_to_report_are_pruned() {
+	new_test_ext().execute_with(|| {
+		// a new authority set every session
+		finalize_block_at_slot(10);
+		for session in 1..=3 {
+			Session::rotate_session();
+			set_grandpa_set_id(3 + u64::from(session));
+			finalize_block_at_slot(10);
+		}
+		assert_eq!(Session::current_index(), 3);
+
+		// reports can go two sessions back, to session 1
+		assert_eq!(Equivocation::session_info(0), None);
+		assert!(Equivocation::session_info(1).is_some());
+		assert_eq!(Equivocation::grandpa_set_session(3), None);
+		assert_eq!(Equivocation::grandpa_set_session(4), Some(1));
+		assert_eq!(Equivocation::grandpa_set_session(6), Some(3));
+		assert_eq!(Equivocation::earliest_grandpa_set(), Some(4));
+
+		// an authority set that lasts is kept while it does
+		for _ in 4..=6 {
+			Session::rotate_session();
+			finalize_block_at_slot(10);
+		}
+		assert_eq!(Equivocation::session_info(3), None);
+		assert_eq!(Equivocation::grandpa_set_session(5), None);
+		assert_eq!(Equivocation::grandpa_set_session(6), Some(3));
+	});
+}
+
+#[test]
+fn equivocation_slashes_grow_with_the_share_of_validators_equivocating() {
+	let slash = |offenders| <AuraEquivocationOffence<Test> as Offence<_>>::slash_fraction(offenders, 30);
+	assert_eq!(slash(1), EquivocationSlash::get());
+	assert_eq!(slash(5), Perbill::from_percent(25));
+	assert_eq!(slash(10), Perbill::one());
+	assert_eq!(slash(20), Perbill::one());
+}
@@ -0,0 +1,199 @@
+//! # Ethereum Module
+//!
+//! Keeps the Ethereum view of each block, so Ethereum tooling can follow the
+//! chain: an Ethereum block with the logs bloom of the block, and a receipt for
+//! every evm execution in it.
+//!
+//! The evm reports its executions to this module as they happen, and the
+//! Ethereum block is built from their receipts when the block is finalized.
+//! Only the latest block and its receipts are kept in storage, the node
+//! indexes them as blocks are imported.
+//!
+//! Ethereum blocks are hashed the way Ethereum hashes headers, with what
+//! Moonbeam has no use for, like ommers and proof of work, left empty.  The
+//! transactions root is the keccak hash of the RLP list of the transaction
+//! hashes and the receipts root the keccak hash of the SCALE encoded
+//! receipts, rather than the trie roots Ethereum uses.
+
+use frame_support::{decl_module, decl_storage, traits::Get, StorageValue};
+use codec::{Encode, Decode};
+use ethbloom::{Bloom, Input as BloomInput};
+use rlp::RlpStream;
+use sp_core::{H160, H256, U256};
+use sp_io::hashing::{blake2_256, keccak_256};
+use sp_runtime::traits::UniqueSaturatedInto;
+use sp_std::prelude::*;
+use crate::evm::{self, Execution, ExitStatus, Log, OnExecuted};
+
+pub mod runtime_api;
+#[cfg(test)]
+mod mock;
+#[cfg(test)]
+mod tests;
+
+/// The Ethereum block of a substrate block.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct EthereumBlock {
+	/// The keccak hash of the RLP encoded header.
+	pub hash: H256,
+	pub parent_hash: H256,
+	pub number: U256,
+	/// Seconds since the epoch.
+	pub timestamp: u64,
+	pub transactions_root: H256,
+	pub receipts_root: H256,
+	/// The bloom of every log emitted in the block.
+	pub logs_bloom: Bloom,
+	pub gas_limit: U256,
+	pub gas_used: U256,
+	/// The hashes of the evm transactions of the block, in order.
+	pub transactions: Vec<H256>,
+}
+
+impl EthereumBlock {
+	/// The keccak hash of the header, RLP encoded as Ethereum encodes it.
+	fn header_hash(&self) -> H256 {
+		let mut stream = RlpStream::new_list(15);
+		stream.append(&self.parent_hash.as_bytes().to_vec());
+		// the hash of an empty list of ommers
+		stream.append(&keccak_256(&[0xc0]).to_vec());
+		stream.append(&H160::zero().as_bytes().to_vec());
+		// no state root
+		stream.append(&H256::zero().as_bytes().to_vec());
+		stream.append(&self.transactions_root.as_bytes().to_vec());
+		stream.append(&self.receipts_root.as_bytes().to_vec());
+		stream.append(&self.logs_bloom.as_bytes().to_vec());
+		// no difficulty
+		append_quantity(&mut stream, U256::zero());
+		append_quantity(&mut stream, self.number);
+		append_quantity(&mut stream, self.gas_limit);
+		append_quantity(&mut stream, self.gas_used);
+		stream.append(&self.timestamp);
+		// no extra data, mix hash or nonce
+		stream.append(&Vec::<u8>::new());
+		stream.append(&H256::zero().as_bytes().to_vec());
+		stream.append(&vec![0u8; 8]);
+		H256::from(keccak_256(&stream.out()))
+	}
+}
+
+/// What an evm transaction did.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Receipt {
+	/// The Ethereum hash of an Ethereum signed transaction, and the extrinsic
+	/// hash of any other.
+	pub transaction_hash: H256,
+	/// The index of the extrinsic in the block.
+	pub transaction_index: u32,
+	pub from: H160,
+	/// The contract called, none for a create.
+	pub to: Option<H160>,
+	/// The contract created, none for a call.
+	pub contract_address: Option<H160>,
+	pub status: ExitStatus,
+	pub used_gas: U256,
+	/// The gas used by the evm transactions of the block up to and including
+	/// this one.
+	pub cumulative_gas_used: U256,
+	pub logs: Vec<Log>,
+	/// The bloom of the logs.
+	pub logs_bloom: Bloom,
+}
+
+pub trait Trait: evm::Trait {}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Ethereum {
+		/// The receipts of the executions so far in this block.
+		Pending: Vec<Receipt>;
+		/// The Ethereum block of the latest block, none before the first block
+		/// is finalized.
+		CurrentBlock get(current_block): Option<EthereumBlock>;
+		/// The receipts of the latest block.
+		CurrentReceipts get(current_receipts): Vec<Receipt>;
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn on_finalize(n: T::BlockNumber) {
+			let receipts = Pending::take();
+
+			let mut logs_bloom = Bloom::default();
+			for receipt in &receipts {
+				logs_bloom.accrue_bloom(&receipt.logs_bloom);
+			}
+			let transactions: Vec<H256> = receipts.iter().map(|receipt| receipt.transaction_hash).collect();
+			let mut stream = RlpStream::new_list(transactions.len());
+			for hash in &transactions {
+				stream.append(&hash.as_bytes().to_vec());
+			}
+
+			let now = UniqueSaturatedInto::<u64>::unique_saturated_into(<timestamp::Module<T>>::now());
+			let mut block = EthereumBlock {
+				hash: H256::zero(),
+				parent_hash: Self::current_block().map(|block| block.hash).unwrap_or_default(),
+				number: U256::from(UniqueSaturatedInto::<u128>::unique_saturated_into(n)),
+				// the timestamp module counts in milliseconds
+				timestamp: now / 1000,
+				transactions_root: H256::from(keccak_256(&stream.out())),
+				receipts_root: H256::from(keccak_256(&receipts.encode())),
+				logs_bloom,
+				gas_limit: U256::from(T::BlockGasLimit::get()),
+				gas_used: receipts.last().map(|receipt| receipt.cumulative_gas_used).unwrap_or_default(),
+				transactions,
+			};
+			block.hash = block.header_hash();
+
+			CurrentBlock::put(block);
+			CurrentReceipts::put(receipts);
+		}
+	}
+}
+
+impl<T: Trait> OnExecuted for Module<T> {
+	fn on_executed(execution: Execution) {
+		let transaction_index = <system::Module<T>>::extrinsic_index().unwrap_or_default();
+		let transaction_hash = execution.transaction_hash.unwrap_or_else(|| {
+			H256::from(blake2_256(&<system::Module<T>>::extrinsic_data(transaction_index)))
+		});
+
+		let mut logs_bloom = Bloom::default();
+		for log in &execution.logs {
+			logs_bloom.accrue(BloomInput::Raw(log.address.as_bytes()));
+			for topic in &log.topics {
+				logs_bloom.accrue(BloomInput::Raw(topic.as_bytes()));
+			}
+		}
+
+		Pending::mutate(|receipts| {
+			let cumulative_gas_used = receipts.last()
+				.map(|receipt| receipt.cumulative_gas_used)
+				.unwrap_or_default()
+				.saturating_add(execution.used_gas);
+			receipts.push(Receipt {
+				transaction_hash,
+				transaction_index,
+				from: execution.from,
+				to: execution.to,
+				contract_address: execution.contract_address,
+				status: execution.status,
+				used_gas: execution.used_gas,
+				cumulative_gas_used,
+				logs: execution.logs,
+				logs_bloom,
+			});
+		});
+	}
+}
+
+/// Appends a number the way RLP encodes numbers, big endian without leading
+/// zeros.
+fn append_quantity(stream: &mut RlpStream, value: U256) {
+	let mut bytes = [0u8; 32];
+	value.to_big_endian(&mut bytes);
+	let start = bytes.iter().position(|byte| *byte != 0).unwrap_or(32);
+	stream.append(&bytes[start..].to_vec());
+}
@@ -0,0 +1,16 @@
+//! Runtime API for the Ethereum view of the chain, which the node indexes to
+//! serve Ethereum blocks, receipts and logs.
+
+use sp_std::prelude::*;
+use super::{EthereumBlock, Receipt};
+
+sp_api::decl_runtime_apis! {
+	/// The Ethereum view of a block.
+	pub trait EthereumApi {
+		/// The Ethereum block of this block, none for the genesis block.
+		fn current_block() -> Option<EthereumBlock>;
+
+		/// The receipts of the evm transactions in this block.
+		fn current_receipts() -> Vec<Receipt>;
+	}
+}
@@ -0,0 +1,98 @@
+//! Tests for the ethereum module.
+
+use super::*;
+use super::mock::*;
+use frame_support::assert_ok;
+use sp_runtime::traits::OnFinalize;
+
+/// Emits a log of the word 42 with the topic 7, and deploys nothing.
+const LOGGING_CODE: [u8; 13] = [
+	0x60, 0x2a, 0x60, 0x00, 0x52, // mstore(0, 42)
+	0x60, 0x07, 0x60, 0x20, 0x60, 0x00, 0xa1, // log1(0, 32, 7)
+	0x00, // stop
+];
+
+const GAS_LIMIT: u32 = 100_000;
+
+fn execution(transaction_hash: Option<H256>, used_gas: u64) -> Execution {
+	Execution {
+		transaction_hash,
+		from: H160::from_low_u64_be(1),
+		to: Some(H160::from_low_u64_be(2)),
+		contract_address: None,
+		status: ExitStatus::Succeeded,
+		used_gas: U256::from(used_gas),
+		logs: vec![],
+	}
+}
+
+fn finalize(n: u64) -> EthereumBlock {
+	<Ethereum as OnFinalize<u64>>::on_finalize(n);
+	Ethereum::current_block().unwrap()
+}
+
+#[test]
+fn receipts_are_recorded_for_every_execution() {
+	new_test_ext().execute_with(|| {
+		let ethereum_hash = H256::repeat_byte(1);
+		Ethereum::on_executed(execution(Some(ethereum_hash), 21_000));
+		Ethereum::on_executed(execution(None, 30_000));
+
+		let block = finalize(1);
+		assert_eq!(block.transactions, vec![ethereum_hash, H256::from(blake2_256(&[]))]);
+		assert_eq!(block.gas_used, U256::from(51_000));
+		assert_eq!(block.gas_limit, U256::from(1_000_000));
+
+		let receipts = Ethereum::current_receipts();
+		assert_eq!(receipts.len(), 2);
+		assert_eq!(receipts[0].used_gas, U256::from(21_000));
+		assert_eq!(receipts[0].cumulative_gas_used, U256::from(21_000));
+		assert_eq!(receipts[1].used_gas, U256::from(30_000));
+		assert_eq!(receipts[1].cumulative_gas_used, U256::from(51_000));
+		assert!(Pending::get().is_empty());
+	});
+}
+
+#[test]
+fn logs_are_kept_with_their_receipt_and_in_the_blooms() {
+	new_test_ext().execute_with(|| {
+		let contract = Evm::dry_run_create(H160::from_low_u64_be(1), LOGGING_CODE.to_vec(), U256::zero(), GAS_LIMIT, U256::one()).value;
+		assert_ok!(Evm::create(Origin::signed(1), LOGGING_CODE.to_vec(), U256::zero(), GAS_LIMIT, U256::one()));
+
+		let block = finalize(1);
+		let receipts = Ethereum::current_receipts();
+		let receipt = &receipts[0];
+		assert_eq!(receipt.contract_address, Some(contract));
+		assert_eq!(receipt.logs, vec![Log {
+			address: contract,
+			topics: vec![H256::from_low_u64_be(7)],
+			data: H256::from_low_u64_be(42).as_bytes().to_vec(),
+		}]);
+
+		for bloom in &[receipt.logs_bloom, block.logs_bloom] {
+			assert!(bloom.contains_input(BloomInput::Raw(contract.as_bytes())));
+			assert!(bloom.contains_input(BloomInput::Raw(H256::from_low_u64_be(7).as_bytes())));
+		}
+		assert!(!block.logs_bloom.contains_input(BloomInput::Raw(H256::from_low_u64_be(8).as_bytes())));
+	});
+}
+
+#[test]
+fn blocks_are_chained_by_their_ethereum_hash() {
+	new_test_ext().execute_with(|| {
+		Timestamp::set_timestamp(6_000);
+		let first = finalize(1);
+		assert_eq!(first.parent_hash, H256::zero());
+		assert_eq!(first.number, U256::one());
+		assert_eq!(first.timestamp, 6);
+		assert!(first.transactions.is_empty());
+		assert_eq!(first.logs_bloom, Bloom::default());
+		assert_eq!(first.hash, first.header_hash());
+
+		Ethereum::on_executed(execution(None, 21_000));
+		let second = finalize(2);
+		assert_eq!(second.parent_hash, first.hash);
+		assert_ne!(second.hash, first.hash);
+		assert_ne!(second.transactions_root, first.transactions_root);
+	});
+}
@@ -0,0 +1,679 @@
+//! # EVM Module
+//!
+//! Runs Ethereum contracts, so Solidity contracts can be deployed on Moonbeam
+//! as they are.
+//!
+//! Every evm address is backed by a substrate account, given by the
+//! `AddressMapping`, and the glmr free balance of that account is the balance
+//! of the address in the evm.  A substrate account acts in the evm as the
+//! address the `AddressMapping` gives it, and funds that address by
+//! transferring glmr to the account backing it.
+//!
+//! Gas is paid in glmr from the caller's address at the gas price it offers,
+//! which has to be at least `MinGasPrice`.  The whole gas limit is withdrawn
+//! up front and what is left over refunded once the execution is done.  Gas
+//! spent goes to `OnGasPayment`.  A call or create that fails in the evm still
+//! pays for its gas.
+//!
+//! The executions of a block use no more than `BlockGasLimit` gas together.  As
+//! in Ethereum, an execution only starts if its whole gas limit fits in the gas
+//! left in the block, and then takes up the gas it used.
+//!
+//! Ethereum wallets can also sign legacy Ethereum transactions, which are
+//! submitted RLP encoded as unsigned `transact` extrinsics.  They act as the
+//! address recovered from their signature, must be signed for `ChainId` with
+//! EIP-155 replay protection and must carry the next nonce of that address.
+//! The transaction pool orders them by gas price.
+//!
+//! Contracts can call the `Precompiles` at their addresses, which act for the
+//! contract calling them.  What a precompile writes natively is put back if
+//! that contract reverts, and writes it can not put back, such as staking,
+//! are dispatched once the execution has succeeded, with a `Dispatched` event
+//! saying whether they succeeded.  A transaction calling a precompile
+//! directly, without value, runs it natively as its sender, paying the base
+//! transaction gas plus what the precompile charges.  Dry runs of such calls
+//! run the precompile the same way.
+//!
+//! An execution weighs the share of the weight available to a block that its
+//! gas limit is of `BlockGasLimit`, so a block full of gas is full of weight.
+//!
+//! Every execution kept on chain is reported to `OnExecuted`, with the logs it
+//! emitted.
+//!
+//! Executions can also be dry run through the `EvmApi` runtime API, which
+//! neither charges gas nor changes any state.
+
+use frame_support::{
+	decl_module, decl_storage, decl_event, dispatch, ensure, StorageMap,
+	traits::{Currency, ExistenceRequirement, Get, Imbalance, OnUnbalanced, WithdrawReason},
+	weights::{ClassifyDispatch, DispatchClass, PaysFee, WeighData, Weight},
+};
+use frame_support::unsigned::ValidateUnsigned;
+use codec::{Encode, Decode};
+use system::{ensure_none, ensure_signed};
+use sp_core::{H160, H256, U256};
+use sp_runtime::{
+	traits::{UniqueSaturatedInto, Zero},
+	transaction_validity::{
+		InvalidTransaction, TransactionLongevity, TransactionPriority, TransactionValidity,
+		ValidTransaction,
+	},
+};
+use sp_std::{convert::TryInto, marker::PhantomData, prelude::*};
+use rust_evm::{Config, CreateScheme, ExitError, ExitReason, Transfer};
+use rust_evm::backend::ApplyBackend;
+use self::backend::{Backend, Vicinity};
+use self::executor::Executor;
+pub use self::precompiles::{EthereumPrecompiles, PrecompileOutput, Precompiles};
+pub use self::transaction::Transaction;
+
+mod backend;
+mod executor;
+pub mod precompiles;
+pub mod runtime_api;
+mod transaction;
+#[cfg(test)]
+mod mock;
+#[cfg(test)]
+mod tests;
+
+type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+type NegativeImbalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::NegativeImbalance;
+
+/// The gas every transaction pays before it runs anything.
+pub const TRANSACTION_GAS: usize = 21_000;
+
+/// Maps evm addresses to the substrate accounts that back them and substrate
+/// accounts to the addresses they act as.
+pub trait AddressMapping<AccountId> {
+	/// The account holding the glmr of `address`.
+	fn into_account_id(address: H160) -> AccountId;
+	/// The address `who` acts as in the evm.
+	fn into_address(who: &AccountId) -> H160;
+}
+
+/// Backs each address with the account whose id is the hash of the address,
+/// and has each account act as the first 20 bytes of its id.
+pub struct HashedAddressMapping;
+
+impl<AccountId: From<[u8; 32]> + AsRef<[u8]>> AddressMapping<AccountId> for HashedAddressMapping {
+	fn into_account_id(address: H160) -> AccountId {
+		let mut data = [0u8; 24];
+		data[0..4].copy_from_slice(b"evm:");
+		data[4..24].copy_from_slice(address.as_bytes());
+		AccountId::from(sp_io::hashing::blake2_256(&data))
+	}
+
+	fn into_address(who: &AccountId) -> H160 {
+		H160::from_slice(&who.as_ref()[0..20])
+	}
+}
+
+/// How an execution ended.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum ExitStatus {
+	/// The execution succeeded and its changes are kept.
+	Succeeded,
+	/// The contract reverted.  Its changes are dropped.
+	Reverted,
+	/// The execution failed, for example by running out of gas.  Its changes
+	/// are dropped.
+	Failed,
+}
+
+impl From<ExitReason> for ExitStatus {
+	fn from(reason: ExitReason) -> Self {
+		match reason {
+			ExitReason::Succeed(_) => ExitStatus::Succeeded,
+			ExitReason::Revert(_) => ExitStatus::Reverted,
+			ExitReason::Error(_) | ExitReason::Fatal(_) => ExitStatus::Failed,
+		}
+	}
+}
+
+/// The result of an execution.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct ExecutionInfo<Value> {
+	pub status: ExitStatus,
+	/// The output of a call, or the address of a created contract.
+	pub value: Value,
+	pub used_gas: U256,
+	/// The logs emitted, none if the execution did not succeed.
+	pub logs: Vec<Log>,
+}
+
+/// A log emitted by a contract.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Log {
+	pub address: H160,
+	pub topics: Vec<H256>,
+	pub data: Vec<u8>,
+}
+
+/// An execution that was kept on chain.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Execution {
+	/// The Ethereum hash of the transaction, if it was Ethereum signed.
+	pub transaction_hash: Option<H256>,
+	pub from: H160,
+	/// The contract called, none for a create.
+	pub to: Option<H160>,
+	/// The contract created, none for a call.
+	pub contract_address: Option<H160>,
+	pub status: ExitStatus,
+	pub used_gas: U256,
+	pub logs: Vec<Log>,
+}
+
+/// Told about every execution that is kept on chain.
+pub trait OnExecuted {
+	fn on_executed(execution: Execution);
+}
+
+impl OnExecuted for () {
+	fn on_executed(_: Execution) {}
+}
+
+/// The weight of an execution with the given gas limit: the same share of the
+/// weight available to a block as the gas limit is of `BlockGasLimit`, and no
+/// more than all of it.  `transact` weighs the gas limit of its transaction, or
+/// the block gas limit if that can not be read.
+pub struct GasWeight<T>(pub PhantomData<T>);
+
+impl<T: Trait> GasWeight<T> {
+	fn weigh_gas(gas_limit: u32) -> Weight {
+		let block_gas_limit = T::BlockGasLimit::get().max(1);
+		let available = T::AvailableBlockRatio::get() * T::MaximumBlockWeight::get();
+		(available as u64 * gas_limit.min(block_gas_limit) as u64 / block_gas_limit as u64) as Weight
+	}
+}
+
+impl<'a, T: Trait> WeighData<(&'a H160, &'a Vec<u8>, &'a U256, &'a u32, &'a U256)> for GasWeight<T> {
+	fn weigh_data(&self, (_, _, _, gas_limit, _): (&'a H160, &'a Vec<u8>, &'a U256, &'a u32, &'a U256)) -> Weight {
+		Self::weigh_gas(*gas_limit)
+	}
+}
+
+impl<'a, T: Trait> WeighData<(&'a Vec<u8>, &'a U256, &'a u32, &'a U256)> for GasWeight<T> {
+	fn weigh_data(&self, (_, _, gas_limit, _): (&'a Vec<u8>, &'a U256, &'a u32, &'a U256)) -> Weight {
+		Self::weigh_gas(*gas_limit)
+	}
+}
+
+impl<'a, T: Trait> WeighData<(&'a Vec<u8>,)> for GasWeight<T> {
+	fn weigh_data(&self, (transaction,): (&'a Vec<u8>,)) -> Weight {
+		let gas_limit = match Transaction::gas_limit_of(transaction) {
+			Ok(gas_limit) if gas_limit <= U256::from(u32::max_value()) => gas_limit.low_u32(),
+			_ => T::BlockGasLimit::get(),
+		};
+		Self::weigh_gas(gas_limit)
+	}
+}
+
+impl<T, A> ClassifyDispatch<A> for GasWeight<T> {
+	fn classify_dispatch(&self, _: A) -> DispatchClass {
+		DispatchClass::Normal
+	}
+}
+
+impl<T> PaysFee for GasWeight<T> {
+	fn pays_fee(&self) -> bool {
+		true
+	}
+}
+
+pub trait Trait: system::Trait + timestamp::Trait {
+	type Event: From<Event> + Into<<Self as system::Trait>::Event>;
+	/// The currency gas and evm balances are held in.
+	type Currency: Currency<Self::AccountId>;
+	/// Maps between evm addresses and substrate accounts.
+	type AddressMapping: AddressMapping<Self::AccountId>;
+	/// Where the glmr paid for gas goes.
+	type OnGasPayment: OnUnbalanced<NegativeImbalanceOf<Self>>;
+	/// Told about every execution that is kept on chain.
+	type OnExecuted: OnExecuted;
+	/// The precompiled contracts.
+	type Precompiles: Precompiles;
+	/// The lowest gas price accepted, in glmr per unit of gas.
+	type MinGasPrice: Get<BalanceOf<Self>>;
+	/// The most gas the executions of a block may use together.
+	type BlockGasLimit: Get<u32>;
+	/// The chain id contracts see.
+	type ChainId: Get<u64>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Evm {
+		/// The nonce of each address.
+		Nonces get(nonce_of): map H160 => U256;
+		/// The code of each contract.
+		AccountCodes get(code_of): map H160 => Vec<u8>;
+		/// The storage of each contract.
+		AccountStorages get(storage_at): double_map H160, blake2_256(H256) => H256;
+		/// The gas used by the executions of the current block so far.
+		BlockGasUsed get(block_gas_used): u32;
+	}
+}
+
+decl_event!(
+	pub enum Event {
+		/// A contract emitted a log.
+		Log(Log),
+		/// A contract was created at the address, using the gas.
+		Created(H160, U256),
+		/// Creating a contract at the address failed after using the gas.
+		CreatedFailed(H160, U256),
+		/// The contract at the address was called, using the gas.
+		Executed(H160, U256),
+		/// Calling the contract at the address failed after using the gas.
+		ExecutedFailed(H160, U256),
+		/// A write a precompile deferred for the contract at the address was
+		/// dispatched, and whether it succeeded.
+		Dispatched(H160, bool),
+	}
+);
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event() = default;
+
+		fn on_finalize() {
+			BlockGasUsed::kill();
+		}
+
+		/// Calls the contract at `target` with `input`, sending it `value` glmr.
+		/// Gas is paid from the sender's evm address.
+		#[weight = GasWeight::<T>(PhantomData)]
+		fn call(origin, target: H160, input: Vec<u8>, value: U256, gas_limit: u32, gas_price: U256) -> dispatch::Result {
+			let who = ensure_signed(origin)?;
+			let source = T::AddressMapping::into_address(&who);
+
+			Self::record_call(None, source, target, input, value, gas_limit, gas_price)
+		}
+
+		/// Creates a contract by running the `init` code, sending it `value`
+		/// glmr.  Gas is paid from the sender's evm address.
+		#[weight = GasWeight::<T>(PhantomData)]
+		fn create(origin, init: Vec<u8>, value: U256, gas_limit: u32, gas_price: U256) -> dispatch::Result {
+			let who = ensure_signed(origin)?;
+			let source = T::AddressMapping::into_address(&who);
+
+			Self::record_create(None, source, init, value, gas_limit, gas_price)
+		}
+
+		/// Executes an RLP encoded legacy Ethereum transaction as the address
+		/// that signed it.
+		#[weight = GasWeight::<T>(PhantomData)]
+		fn transact(origin, transaction: Vec<u8>) -> dispatch::Result {
+			ensure_none(origin)?;
+			let transaction = Self::check_transaction(&transaction).map_err(<&'static str>::from)?;
+			ensure!(transaction.nonce == Nonces::get(&transaction.sender), "Invalid transaction nonce");
+
+			// checked against the block gas limit, so no more than a u32
+			let gas_limit = transaction.gas_limit.low_u32();
+			let Transaction { nonce, sender, to, input, value, gas_price, hash, .. } = transaction;
+			match to {
+				Some(target) => Self::record_call(Some(hash), sender, target, input, value, gas_limit, gas_price)?,
+				None => Self::record_create(Some(hash), sender, input, value, gas_limit, gas_price)?,
+			}
+			// the nonce is used up, whatever the executor did with it
+			Nonces::mutate(&sender, |next| if *next == nonce { *next = nonce + 1 });
+
+			Ok(())
+		}
+	}
+}
+
+impl<T: Trait> ValidateUnsigned for Module<T> {
+	type Call = Call<T>;
+
+	fn validate_unsigned(call: &Self::Call) -> TransactionValidity {
+		let transaction = match call {
+			Call::transact(transaction) => Self::check_transaction(transaction)?,
+			_ => return InvalidTransaction::Call.into(),
+		};
+
+		let nonce = Nonces::get(&transaction.sender);
+		if transaction.nonce < nonce {
+			return InvalidTransaction::Stale.into()
+		}
+		// a later nonce waits in the pool for the transactions before it
+		let requires = if transaction.nonce > nonce {
+			vec![(transaction.sender, transaction.nonce - 1).encode()]
+		} else {
+			vec![]
+		};
+
+		Ok(ValidTransaction {
+			priority: if transaction.gas_price > U256::from(TransactionPriority::max_value()) {
+				TransactionPriority::max_value()
+			} else {
+				transaction.gas_price.low_u64()
+			},
+			requires,
+			provides: vec![(transaction.sender, transaction.nonce).encode()],
+			longevity: TransactionLongevity::max_value(),
+			propagate: true,
+		})
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// The gas the executions of the current block can still use.
+	pub fn block_gas_left() -> u32 {
+		T::BlockGasLimit::get().saturating_sub(Self::block_gas_used())
+	}
+
+	/// The glmr balance of `address`.
+	pub fn balance_of(address: H160) -> U256 {
+		let account = T::AddressMapping::into_account_id(address);
+		U256::from(UniqueSaturatedInto::<u128>::unique_saturated_into(T::Currency::free_balance(&account)))
+	}
+
+	/// Calls the contract at `target` from `source`, paying for gas.
+	pub fn execute_call(
+		source: H160,
+		target: H160,
+		input: Vec<u8>,
+		value: U256,
+		gas_limit: u32,
+		gas_price: U256,
+	) -> Result<ExecutionInfo<Vec<u8>>, &'static str> {
+		let payment = Self::withdraw_gas(source, gas_limit, gas_price)?;
+		let native = if value.is_zero() {
+			Self::run_precompile(source, target, &input, gas_limit, true)
+		} else {
+			None
+		};
+		let info = match native {
+			Some(info) => info,
+			None => Self::run(source, gas_limit, gas_price, true, |executor| {
+				executor.transact_call(source, target, value, input, gas_limit as usize)
+			}),
+		};
+		Self::refund_gas(source, payment, gas_limit, info.used_gas, gas_price);
+
+		Ok(info)
+	}
+
+	/// Creates a contract from `source` by running `init`, paying for gas.
+	pub fn execute_create(
+		source: H160,
+		init: Vec<u8>,
+		value: U256,
+		gas_limit: u32,
+		gas_price: U256,
+	) -> Result<ExecutionInfo<H160>, &'static str> {
+		let payment = Self::withdraw_gas(source, gas_limit, gas_price)?;
+		let info = Self::run(source, gas_limit, gas_price, true, |executor| {
+			let address = executor.create_address(CreateScheme::Legacy { caller: source });
+			(executor.transact_create(source, value, init, gas_limit as usize), address)
+		});
+		Self::refund_gas(source, payment, gas_limit, info.used_gas, gas_price);
+
+		Ok(info)
+	}
+
+	/// What calling the contract at `target` would do, without charging gas or
+	/// keeping the evm state.  No more than the block gas limit is used.  What
+	/// the precompiles it calls write is put back, and what they defer is not
+	/// dispatched.
+	///
+	/// A precompile called without value runs natively as `source`, as it
+	/// would in a transaction, so what it writes is only dropped with the rest
+	/// of the `EvmApi` call that dry runs it.
+	pub fn dry_run_call(
+		source: H160,
+		target: H160,
+		input: Vec<u8>,
+		value: U256,
+		gas_limit: u32,
+		gas_price: U256,
+	) -> ExecutionInfo<Vec<u8>> {
+		let gas_limit = gas_limit.min(T::BlockGasLimit::get());
+		if value.is_zero() {
+			if let Some(info) = Self::run_precompile(source, target, &input, gas_limit, false) {
+				return info
+			}
+		}
+		Self::run(source, gas_limit, gas_price, false, |executor| {
+			executor.transact_call(source, target, value, input, gas_limit as usize)
+		})
+	}
+
+	/// What creating a contract would do, without charging gas or changing any
+	/// state.  No more than the block gas limit is used.
+	pub fn dry_run_create(
+		source: H160,
+		init: Vec<u8>,
+		value: U256,
+		gas_limit: u32,
+		gas_price: U256,
+	) -> ExecutionInfo<H160> {
+		let gas_limit = gas_limit.min(T::BlockGasLimit::get());
+		Self::run(source, gas_limit, gas_price, false, |executor| {
+			let address = executor.create_address(CreateScheme::Legacy { caller: source });
+			(executor.transact_create(source, value, init, gas_limit as usize), address)
+		})
+	}
+
+	/// Calls the contract at `target` from `source`, and records what the call
+	/// did in events and with `OnExecuted`.
+	fn record_call(
+		transaction_hash: Option<H256>,
+		source: H160,
+		target: H160,
+		input: Vec<u8>,
+		value: U256,
+		gas_limit: u32,
+		gas_price: U256,
+	) -> dispatch::Result {
+		let info = Self::execute_call(source, target, input, value, gas_limit, gas_price)?;
+		match info.status {
+			ExitStatus::Succeeded => Self::deposit_event(Event::Executed(target, info.used_gas)),
+			_ => Self::deposit_event(Event::ExecutedFailed(target, info.used_gas)),
+		}
+		T::OnExecuted::on_executed(Execution {
+			transaction_hash,
+			from: source,
+			to: Some(target),
+			contract_address: None,
+			status: info.status,
+			used_gas: info.used_gas,
+			logs: info.logs,
+		});
+
+		Ok(())
+	}
+
+	/// Creates a contract from `source`, and records what the create did in
+	/// events and with `OnExecuted`.
+	fn record_create(
+		transaction_hash: Option<H256>,
+		source: H160,
+		init: Vec<u8>,
+		value: U256,
+		gas_limit: u32,
+		gas_price: U256,
+	) -> dispatch::Result {
+		let info = Self::execute_create(source, init, value, gas_limit, gas_price)?;
+		match info.status {
+			ExitStatus::Succeeded => Self::deposit_event(Event::Created(info.value, info.used_gas)),
+			_ => Self::deposit_event(Event::CreatedFailed(info.value, info.used_gas)),
+		}
+		T::OnExecuted::on_executed(Execution {
+			transaction_hash,
+			from: source,
+			to: None,
+			contract_address: Some(info.value),
+			status: info.status,
+			used_gas: info.used_gas,
+			logs: info.logs,
+		});
+
+		Ok(())
+	}
+
+	/// Decodes an Ethereum transaction and checks everything about it but its
+	/// nonce: its signature and chain id, and that its sender can pay for the
+	/// gas and value.
+	fn check_transaction(transaction: &[u8]) -> Result<Transaction, InvalidTransaction> {
+		let transaction = Transaction::decode(transaction).map_err(|_| InvalidTransaction::BadProof)?;
+		if transaction.chain_id != Some(T::ChainId::get()) {
+			return Err(InvalidTransaction::BadProof)
+		}
+		if transaction.gas_limit > U256::from(Self::block_gas_left()) {
+			return Err(InvalidTransaction::ExhaustsResources)
+		}
+
+		let min_gas_price = UniqueSaturatedInto::<u128>::unique_saturated_into(T::MinGasPrice::get());
+		let cost = transaction.gas_limit.checked_mul(transaction.gas_price)
+			.and_then(|fee| fee.checked_add(transaction.value));
+		match cost {
+			Some(cost) if transaction.gas_price >= U256::from(min_gas_price)
+				&& cost <= Self::balance_of(transaction.sender) => Ok(transaction),
+			_ => Err(InvalidTransaction::Payment),
+		}
+	}
+
+	/// Runs the precompile at `target`, if there is one, natively as `source`,
+	/// and deposits its logs if `apply` is set.  A precompile that fails uses
+	/// all the gas, like a failed evm execution.
+	fn run_precompile(
+		source: H160,
+		target: H160,
+		input: &[u8],
+		gas_limit: u32,
+		apply: bool,
+	) -> Option<ExecutionInfo<Vec<u8>>> {
+		// the evm fails calls with too little gas to start
+		let target_gas = (gas_limit as usize).checked_sub(TRANSACTION_GAS)?;
+		let result = T::Precompiles::execute(Some(source), target, input, Some(target_gas))?
+			.and_then(Self::settle);
+
+		Some(match result {
+			Ok(output) => {
+				if apply {
+					for log in &output.logs {
+						Self::deposit_event(Event::Log(log.clone()));
+					}
+				}
+				ExecutionInfo {
+					status: ExitStatus::Succeeded,
+					value: output.output,
+					used_gas: U256::from(TRANSACTION_GAS + output.used_gas),
+					logs: output.logs,
+				}
+			},
+			Err(_) => ExecutionInfo {
+				status: ExitStatus::Failed,
+				value: Vec::new(),
+				used_gas: U256::from(gas_limit),
+				logs: Vec::new(),
+			},
+		})
+	}
+
+	/// Does what a precompile run natively leaves to its caller: moves the glmr
+	/// it moves and dispatches its deferred writes.  What it wrote is put back
+	/// if moving the glmr fails.
+	fn settle(output: PrecompileOutput) -> Result<PrecompileOutput, ExitError> {
+		let PrecompileOutput { output, used_gas, logs, transfers, undo, deferred } = output;
+		for Transfer { source, target, value } in transfers {
+			let moved = Self::balance_from(value).ok().and_then(|value| T::Currency::withdraw(
+				&T::AddressMapping::into_account_id(source),
+				value,
+				WithdrawReason::Transfer.into(),
+				ExistenceRequirement::AllowDeath,
+			).ok());
+			match moved {
+				Some(imbalance) => T::Currency::resolve_creating(&T::AddressMapping::into_account_id(target), imbalance),
+				None => {
+					if let Some(undo) = undo {
+						undo();
+					}
+					return Err(ExitError::OutOfFund)
+				},
+			}
+		}
+		for write in deferred {
+			write().map_err(|e| ExitError::Other(e.into()))?;
+		}
+
+		Ok(PrecompileOutput { output, used_gas, logs, transfers: Vec::new(), undo: None, deferred: Vec::new() })
+	}
+
+	/// Runs `f` on an executor over the chain state, and keeps its changes if
+	/// `apply` is set.  The writes precompiles deferred for contracts are only
+	/// dispatched then.
+	fn run<R, F>(source: H160, gas_limit: u32, gas_price: U256, apply: bool, f: F) -> ExecutionInfo<R> where
+		F: FnOnce(&mut Executor<T, Backend<T>>) -> (ExitReason, R),
+	{
+		let vicinity = Vicinity { gas_price, origin: source };
+		let mut backend = Backend::<T>::new(&vicinity);
+		let config = Config::istanbul();
+		let mut executor = Executor::new(&backend, gas_limit as usize, &config);
+
+		let (reason, value) = f(&mut executor);
+		let used_gas = U256::from(executor.used_gas());
+		if !apply {
+			executor.discard();
+		}
+
+		let (values, logs, deferred) = executor.deconstruct();
+		if apply {
+			backend.apply(values, Vec::new(), true);
+			for log in &logs {
+				Self::deposit_event(Event::Log(log.clone()));
+			}
+			for (contract, write) in deferred {
+				Self::deposit_event(Event::Dispatched(contract, write().is_ok()));
+			}
+		}
+
+		ExecutionInfo { status: reason.into(), value, used_gas, logs }
+	}
+
+	/// Withdraws the glmr for the whole `gas_limit` from `source`.
+	fn withdraw_gas(source: H160, gas_limit: u32, gas_price: U256) -> Result<NegativeImbalanceOf<T>, &'static str> {
+		ensure!(gas_limit <= Self::block_gas_left(), "Gas limit is above the gas left in the block");
+		let min_gas_price = UniqueSaturatedInto::<u128>::unique_saturated_into(T::MinGasPrice::get());
+		ensure!(gas_price >= U256::from(min_gas_price), "Gas price is below the minimum");
+
+		let fee = match U256::from(gas_limit).checked_mul(gas_price) {
+			Some(val) => Self::balance_from(val)?,
+			None => return Err("Gas fee overflow"),
+		};
+
+		let account = T::AddressMapping::into_account_id(source);
+		T::Currency::withdraw(&account, fee, WithdrawReason::Fee.into(), ExistenceRequirement::AllowDeath)
+			.map_err(|_| "Not enough glmr to pay for gas")
+	}
+
+	/// Refunds the unused part of `payment` to `source` and pays out the rest.
+	/// The gas used is taken from what is left in the block.
+	fn refund_gas(source: H160, payment: NegativeImbalanceOf<T>, gas_limit: u32, used_gas: U256, gas_price: U256) {
+		// no more than `gas_limit`
+		BlockGasUsed::mutate(|block_gas_used| *block_gas_used = block_gas_used.saturating_add(used_gas.low_u32()));
+
+		// no more than `payment`, which was checked not to overflow
+		let unused = U256::from(gas_limit).saturating_sub(used_gas).saturating_mul(gas_price);
+		let refund = Self::balance_from(unused).unwrap_or_else(|_| Zero::zero());
+
+		let (refund, fee) = payment.split(refund);
+		T::Currency::resolve_creating(&T::AddressMapping::into_account_id(source), refund);
+		T::OnGasPayment::on_unbalanced(fee);
+	}
+
+	/// Converts an evm amount of glmr to a balance.
+	fn balance_from(value: U256) -> Result<BalanceOf<T>, &'static str> {
+		if value > U256::from(u128::max_value()) {
+			return Err("Balance overflow")
+		}
+		value.low_u128().try_into().map_err(|_| "Balance overflow")
+	}
+}
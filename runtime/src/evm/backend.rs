@@ -0,0 +1,136 @@
+//! The chain state as the evm sees it.
+
+use sp_core::{H160, H256, U256};
+use sp_runtime::traits::UniqueSaturatedInto;
+use sp_std::{marker::PhantomData, prelude::*};
+use frame_support::{StorageMap, StorageDoubleMap, traits::{Currency, Get}};
+use rust_evm::backend::{Apply, ApplyBackend, Backend as BackendT, Basic, Log};
+use super::{AccountCodes, AccountStorages, AddressMapping, Module, Nonces, Trait};
+
+/// The parts of the environment that depend on the transaction.
+pub struct Vicinity {
+	pub gas_price: U256,
+	pub origin: H160,
+}
+
+/// Reads the evm state from storage, and writes back the changes of an
+/// execution when it is applied.
+pub struct Backend<'vicinity, T> {
+	vicinity: &'vicinity Vicinity,
+	_marker: PhantomData<T>,
+}
+
+impl<'vicinity, T> Backend<'vicinity, T> {
+	pub fn new(vicinity: &'vicinity Vicinity) -> Self {
+		Backend { vicinity, _marker: PhantomData }
+	}
+}
+
+impl<'vicinity, T: Trait> BackendT for Backend<'vicinity, T> {
+	fn gas_price(&self) -> U256 { self.vicinity.gas_price }
+	fn origin(&self) -> H160 { self.vicinity.origin }
+
+	fn block_hash(&self, number: U256) -> H256 {
+		if number > U256::from(u32::max_value()) {
+			return H256::default()
+		}
+		let number = T::BlockNumber::from(number.as_u32());
+		H256::from_slice(<system::Module<T>>::block_hash(number).as_ref())
+	}
+
+	fn block_number(&self) -> U256 {
+		let number = <system::Module<T>>::block_number();
+		U256::from(UniqueSaturatedInto::<u128>::unique_saturated_into(number))
+	}
+
+	fn block_coinbase(&self) -> H160 { H160::default() }
+
+	fn block_timestamp(&self) -> U256 {
+		// the evm counts in seconds, the timestamp module in milliseconds
+		let now = UniqueSaturatedInto::<u128>::unique_saturated_into(<timestamp::Module<T>>::now());
+		U256::from(now / 1000)
+	}
+
+	fn block_difficulty(&self) -> U256 { U256::zero() }
+	fn block_gas_limit(&self) -> U256 { U256::from(T::BlockGasLimit::get()) }
+	fn chain_id(&self) -> U256 { U256::from(T::ChainId::get()) }
+
+	fn exists(&self, _address: H160) -> bool { true }
+
+	fn basic(&self, address: H160) -> Basic {
+		Basic {
+			balance: <Module<T>>::balance_of(address),
+			nonce: Nonces::get(&address),
+		}
+	}
+
+	fn code_hash(&self, address: H160) -> H256 {
+		H256::from(sp_io::hashing::keccak_256(&AccountCodes::get(&address)))
+	}
+
+	fn code_size(&self, address: H160) -> usize {
+		AccountCodes::get(&address).len()
+	}
+
+	fn code(&self, address: H160) -> Vec<u8> {
+		AccountCodes::get(&address)
+	}
+
+	fn storage(&self, address: H160, index: H256) -> H256 {
+		AccountStorages::get(&address, &index)
+	}
+}
+
+impl<'vicinity, T: Trait> ApplyBackend for Backend<'vicinity, T> {
+	/// Writes back the changed accounts.  The logs are deposited as events by
+	/// the module, which also reports them to `OnExecuted`.
+	fn apply<A, I, L>(&mut self, values: A, _logs: L, _delete_empty: bool) where
+		A: IntoIterator<Item=Apply<I>>,
+		I: IntoIterator<Item=(H256, H256)>,
+		L: IntoIterator<Item=Log>,
+	{
+		for apply in values {
+			match apply {
+				Apply::Modify { address, basic, code, storage, reset_storage } => {
+					Nonces::insert(&address, basic.nonce);
+					Self::set_balance(address, basic.balance);
+
+					if let Some(code) = code {
+						AccountCodes::insert(&address, code);
+					}
+
+					if reset_storage {
+						AccountStorages::remove_prefix(&address);
+					}
+					for (index, value) in storage {
+						if value == H256::default() {
+							AccountStorages::remove(&address, &index);
+						} else {
+							AccountStorages::insert(&address, &index, value);
+						}
+					}
+				},
+				Apply::Delete { address } => {
+					Nonces::remove(&address);
+					AccountCodes::remove(&address);
+					AccountStorages::remove_prefix(&address);
+				},
+			}
+		}
+	}
+}
+
+impl<'vicinity, T: Trait> Backend<'vicinity, T> {
+	/// Sets the glmr balance of `address`.  The executor only moves glmr
+	/// between addresses, so this never changes the total issuance overall.
+	fn set_balance(address: H160, balance: U256) {
+		let account = T::AddressMapping::into_account_id(address);
+		let balance = match <Module<T>>::balance_from(balance) {
+			Ok(balance) => balance,
+			Err(_) => return,
+		};
+		if T::Currency::free_balance(&account) != balance {
+			let _ = T::Currency::make_free_balance_be(&account, balance);
+		}
+	}
+}
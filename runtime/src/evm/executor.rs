@@ -0,0 +1,638 @@
+//! The executor contracts run on.
+//!
+//! It runs calls and creates as the stack executor of rust-evm does, one frame
+//! per call, each with its own copy of the evm state that is merged into its
+//! caller's when it returns.  The stack executor calls precompiles without the
+//! context of the call, so this one calls the `Precompiles` itself, with the
+//! contract calling them, and keeps what they hand back with the frame that
+//! called them: their logs, the glmr they move, which it moves through the
+//! evm state, what puts back their native writes, which it runs if the frame
+//! reverts or fails, and their deferred writes, which are only kept if it
+//! succeeds.
+
+use sp_core::{H160, H256, U256};
+use sp_io::hashing::keccak_256;
+use sp_std::{
+	cmp::min,
+	collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+	convert::Infallible,
+	marker::PhantomData,
+	prelude::*,
+	rc::Rc,
+};
+use frame_support::StorageValue;
+use rlp::RlpStream;
+use rust_evm::{
+	Capture, Config, Context, CreateScheme, ExitError, ExitReason, ExitSucceed, ExternalOpcode,
+	Handler, Opcode, Runtime, Stack, Transfer,
+};
+use rust_evm::backend::{Apply, Backend, Basic};
+use rust_evm::gasometer::{self, Gasometer};
+use self::system_events::{EventCount, Events};
+use super::{Log, PrecompileOutput, Precompiles, Trait};
+use super::precompiles::DeferredWrite;
+
+/// The events of the system module, declared again here so that the events a
+/// precompile deposited can be taken back along with what it wrote.
+mod system_events {
+	use frame_support::decl_storage;
+	use sp_std::{marker::PhantomData, prelude::*};
+	use system::{EventIndex, EventRecord, Trait};
+
+	pub struct Module<T: Trait>(PhantomData<T>);
+
+	decl_storage! {
+		trait Store for Module<T: Trait> as System {
+			pub Events: Vec<EventRecord<T::Event, T::Hash>>;
+			pub EventCount: EventIndex;
+		}
+	}
+}
+
+/// A write a precompile deferred for the contract at the address.
+pub type Deferred = (H160, DeferredWrite);
+
+/// What puts back the native writes of a precompile, with the number of
+/// events there were before it ran.
+struct Undo {
+	undo: Box<dyn FnOnce()>,
+	event_count: u32,
+}
+
+/// An account as the executor sees it.
+#[derive(Clone)]
+struct Account {
+	basic: Basic,
+	/// The code, if it has been read or written.
+	code: Option<Vec<u8>>,
+	/// The storage written.
+	storage: BTreeMap<H256, H256>,
+	/// Whether the storage in the backend is wiped, by a contract created at
+	/// the address.
+	reset_storage: bool,
+}
+
+/// A frame of an execution.
+pub struct Executor<'backend, 'config, T, B> {
+	backend: &'backend B,
+	config: &'config Config,
+	gasometer: Gasometer<'config>,
+	state: BTreeMap<H160, Account>,
+	deleted: BTreeSet<H160>,
+	logs: Vec<Log>,
+	/// What puts back the native writes of the precompiles called, in the
+	/// order they were called.
+	undo: Vec<Undo>,
+	deferred: Vec<Deferred>,
+	is_static: bool,
+	/// None for the frame of the transaction.
+	depth: Option<usize>,
+	_marker: PhantomData<T>,
+}
+
+/// All but a 64th of `gas`.
+fn l64(gas: usize) -> usize {
+	gas - gas / 64
+}
+
+impl<'backend, 'config, T: Trait, B: Backend> Executor<'backend, 'config, T, B> {
+	/// The frame of a transaction, with `gas_limit` gas.
+	pub fn new(backend: &'backend B, gas_limit: usize, config: &'config Config) -> Self {
+		Executor {
+			backend,
+			config,
+			gasometer: Gasometer::new(gas_limit, config),
+			state: BTreeMap::new(),
+			deleted: BTreeSet::new(),
+			logs: Vec::new(),
+			undo: Vec::new(),
+			deferred: Vec::new(),
+			is_static: false,
+			depth: None,
+			_marker: PhantomData,
+		}
+	}
+
+	/// The gas used, less the refund.
+	pub fn used_gas(&self) -> usize {
+		self.gasometer.total_used_gas()
+			- min(self.gasometer.total_used_gas() / 2, self.gasometer.refunded_gas() as usize)
+	}
+
+	/// Puts back the native writes of the precompiles called, for an
+	/// execution that is not kept.
+	pub fn discard(&mut self) {
+		Self::undo(self);
+	}
+
+	/// The changes to apply to the backend, the logs emitted and the writes
+	/// deferred.
+	pub fn deconstruct(self) -> (Vec<Apply<BTreeMap<H256, H256>>>, Vec<Log>, Vec<Deferred>) {
+		let mut applies = Vec::new();
+		for (address, account) in self.state {
+			if self.deleted.contains(&address) {
+				continue
+			}
+			applies.push(Apply::Modify {
+				address,
+				basic: account.basic,
+				code: account.code,
+				storage: account.storage,
+				reset_storage: account.reset_storage,
+			});
+		}
+		for address in self.deleted {
+			applies.push(Apply::Delete { address });
+		}
+
+		(applies, self.logs, self.deferred)
+	}
+
+	/// Calls the contract at `address` from `caller`, as a transaction.
+	pub fn transact_call(
+		&mut self,
+		caller: H160,
+		address: H160,
+		value: U256,
+		data: Vec<u8>,
+		gas_limit: usize,
+	) -> (ExitReason, Vec<u8>) {
+		let transaction_cost = gasometer::call_transaction_cost(&data);
+		if let Err(e) = self.gasometer.record_transaction(transaction_cost) {
+			return (e.into(), Vec::new())
+		}
+		self.account_mut(caller).basic.nonce += U256::one();
+
+		let context = Context { caller, address, apparent_value: value };
+		let transfer = Transfer { source: caller, target: address, value };
+		match self.call_inner(address, Some(transfer), data, Some(gas_limit), false, false, false, context) {
+			Capture::Exit(exit) => exit,
+			Capture::Trap(never) => match never {},
+		}
+	}
+
+	/// Creates a contract from `caller` by running `init_code`, as a
+	/// transaction.
+	pub fn transact_create(&mut self, caller: H160, value: U256, init_code: Vec<u8>, gas_limit: usize) -> ExitReason {
+		let transaction_cost = gasometer::create_transaction_cost(&init_code);
+		if let Err(e) = self.gasometer.record_transaction(transaction_cost) {
+			return e.into()
+		}
+
+		match self.create_inner(caller, CreateScheme::Legacy { caller }, value, init_code, Some(gas_limit), false) {
+			Capture::Exit((reason, _, _)) => reason,
+			Capture::Trap(never) => match never {},
+		}
+	}
+
+	/// The address a create by `scheme` makes its contract at.
+	pub fn create_address(&self, scheme: CreateScheme) -> H160 {
+		match scheme {
+			CreateScheme::Legacy { caller } => {
+				let mut stream = RlpStream::new_list(2);
+				stream.append(&caller);
+				stream.append(&self.nonce(caller));
+				H256::from(keccak_256(&stream.out())).into()
+			},
+			CreateScheme::Create2 { caller, code_hash, salt } => {
+				let mut preimage = vec![0xff];
+				preimage.extend_from_slice(caller.as_bytes());
+				preimage.extend_from_slice(salt.as_bytes());
+				preimage.extend_from_slice(code_hash.as_bytes());
+				H256::from(keccak_256(&preimage)).into()
+			},
+			CreateScheme::Fixed(address) => address,
+		}
+	}
+
+	/// The frame of a call or create from this one, with `gas_limit` gas.
+	fn substate(&self, gas_limit: usize, is_static: bool) -> Self {
+		Executor {
+			backend: self.backend,
+			config: self.config,
+			gasometer: Gasometer::new(gas_limit, self.config),
+			state: self.state.clone(),
+			deleted: self.deleted.clone(),
+			logs: Vec::new(),
+			undo: Vec::new(),
+			deferred: Vec::new(),
+			is_static: is_static || self.is_static,
+			depth: Some(self.depth.map_or(0, |depth| depth + 1)),
+			_marker: PhantomData,
+		}
+	}
+
+	/// Keeps everything `substate` did, and takes back the gas it left.
+	fn merge_succeed(&mut self, mut substate: Self) -> Result<(), ExitError> {
+		self.logs.append(&mut substate.logs);
+		self.deleted.append(&mut substate.deleted);
+		self.undo.append(&mut substate.undo);
+		self.deferred.append(&mut substate.deferred);
+		self.state = substate.state;
+
+		self.gasometer.record_stipend(substate.gasometer.gas())?;
+		self.gasometer.record_refund(substate.gasometer.refunded_gas())?;
+		Ok(())
+	}
+
+	/// Drops everything `substate` did, but takes back the gas it left.
+	fn merge_revert(&mut self, mut substate: Self) -> Result<(), ExitError> {
+		Self::undo(&mut substate);
+		self.gasometer.record_stipend(substate.gasometer.gas())?;
+		Ok(())
+	}
+
+	/// Drops everything `substate` did, along with the gas it had.
+	fn merge_fail(&mut self, mut substate: Self) -> Result<(), ExitError> {
+		Self::undo(&mut substate);
+		Ok(())
+	}
+
+	/// Puts back the native writes of the precompiles `substate` called, the
+	/// last first.
+	fn undo(substate: &mut Self) {
+		while let Some(Undo { undo, event_count }) = substate.undo.pop() {
+			undo();
+			<Events<T>>::mutate(|events| events.truncate(event_count as usize));
+			EventCount::put(event_count);
+		}
+	}
+
+	/// Runs `runtime` on this frame.
+	fn execute(&mut self, runtime: &mut Runtime) -> ExitReason {
+		match runtime.run(self) {
+			Capture::Exit(reason) => reason,
+			Capture::Trap(_) => unreachable!("the interrupts of the executor are infallible"),
+		}
+	}
+
+	fn account_mut(&mut self, address: H160) -> &mut Account {
+		let backend = self.backend;
+		self.state.entry(address).or_insert_with(|| Account {
+			basic: backend.basic(address),
+			code: None,
+			storage: BTreeMap::new(),
+			reset_storage: false,
+		})
+	}
+
+	fn nonce(&self, address: H160) -> U256 {
+		self.state.get(&address)
+			.map(|account| account.basic.nonce)
+			.unwrap_or_else(|| self.backend.basic(address).nonce)
+	}
+
+	/// Moves glmr between two addresses.
+	fn transfer(&mut self, transfer: Transfer) -> Result<(), ExitError> {
+		{
+			let source = self.account_mut(transfer.source);
+			if source.basic.balance < transfer.value {
+				return Err(ExitError::OutOfFund)
+			}
+			source.basic.balance -= transfer.value;
+		}
+		let target = self.account_mut(transfer.target);
+		target.basic.balance = target.basic.balance.saturating_add(transfer.value);
+		Ok(())
+	}
+
+	fn create_inner(
+		&mut self,
+		caller: H160,
+		scheme: CreateScheme,
+		value: U256,
+		init_code: Vec<u8>,
+		target_gas: Option<usize>,
+		take_l64: bool,
+	) -> Capture<(ExitReason, Option<H160>, Vec<u8>), Infallible> {
+		macro_rules! try_or_fail {
+			($e:expr) => {
+				match $e {
+					Ok(v) => v,
+					Err(e) => return Capture::Exit((e.into(), None, Vec::new())),
+				}
+			}
+		}
+
+		if let Some(depth) = self.depth {
+			if depth + 1 > self.config.call_stack_limit {
+				return Capture::Exit((ExitError::CallTooDeep.into(), None, Vec::new()))
+			}
+		}
+		if self.balance(caller) < value {
+			return Capture::Exit((ExitError::OutOfFund.into(), None, Vec::new()))
+		}
+
+		let after_gas = if take_l64 && self.config.call_l64_after_gas {
+			l64(self.gasometer.gas())
+		} else {
+			self.gasometer.gas()
+		};
+		let gas_limit = min(after_gas, target_gas.unwrap_or(after_gas));
+		try_or_fail!(self.gasometer.record_cost(gas_limit));
+
+		let address = self.create_address(scheme);
+		self.account_mut(caller).basic.nonce += U256::one();
+
+		let mut substate = self.substate(gas_limit, false);
+		let code = substate.code(address);
+		if !code.is_empty() || !substate.nonce(address).is_zero() {
+			let _ = self.merge_fail(substate);
+			return Capture::Exit((ExitError::CreateCollision.into(), None, Vec::new()))
+		}
+		{
+			let account = substate.account_mut(address);
+			account.code = Some(code);
+			account.storage = BTreeMap::new();
+			account.reset_storage = true;
+		}
+
+		if let Err(e) = substate.transfer(Transfer { source: caller, target: address, value }) {
+			let _ = self.merge_revert(substate);
+			return Capture::Exit((ExitReason::Error(e), None, Vec::new()))
+		}
+		if self.config.create_increase_nonce {
+			substate.account_mut(address).basic.nonce += U256::one();
+		}
+
+		let context = Context { address, caller, apparent_value: value };
+		let mut runtime = Runtime::new(Rc::new(init_code), Rc::new(Vec::new()), context, self.config);
+		match substate.execute(&mut runtime) {
+			ExitReason::Succeed(s) => {
+				let out = runtime.machine().return_value();
+				if let Some(limit) = self.config.create_contract_limit {
+					if out.len() > limit {
+						let _ = self.merge_fail(substate);
+						return Capture::Exit((ExitError::CreateContractLimit.into(), None, Vec::new()))
+					}
+				}
+				match substate.gasometer.record_deposit(out.len()) {
+					Ok(()) => {
+						let merged = self.merge_succeed(substate);
+						self.account_mut(address).code = Some(out);
+						try_or_fail!(merged);
+						Capture::Exit((ExitReason::Succeed(s), Some(address), Vec::new()))
+					},
+					Err(e) => {
+						let _ = self.merge_fail(substate);
+						Capture::Exit((ExitReason::Error(e), None, Vec::new()))
+					},
+				}
+			},
+			ExitReason::Error(e) => {
+				let _ = self.merge_fail(substate);
+				Capture::Exit((ExitReason::Error(e), None, Vec::new()))
+			},
+			ExitReason::Revert(e) => {
+				let _ = self.merge_revert(substate);
+				Capture::Exit((ExitReason::Revert(e), None, runtime.machine().return_value()))
+			},
+			ExitReason::Fatal(e) => {
+				self.gasometer.fail();
+				let _ = self.merge_fail(substate);
+				Capture::Exit((ExitReason::Fatal(e), None, Vec::new()))
+			},
+		}
+	}
+
+	fn call_inner(
+		&mut self,
+		code_address: H160,
+		transfer: Option<Transfer>,
+		input: Vec<u8>,
+		target_gas: Option<usize>,
+		is_static: bool,
+		take_l64: bool,
+		take_stipend: bool,
+		context: Context,
+	) -> Capture<(ExitReason, Vec<u8>), Infallible> {
+		let after_gas = if take_l64 && self.config.call_l64_after_gas {
+			l64(self.gasometer.gas())
+		} else {
+			self.gasometer.gas()
+		};
+		let mut gas_limit = min(after_gas, target_gas.unwrap_or(after_gas));
+		if let Err(e) = self.gasometer.record_cost(gas_limit) {
+			return Capture::Exit((e.into(), Vec::new()))
+		}
+		if let Some(transfer) = transfer.as_ref() {
+			if take_stipend && !transfer.value.is_zero() {
+				gas_limit = gas_limit.saturating_add(self.config.call_stipend);
+			}
+		}
+
+		let code = self.code(code_address);
+		let mut substate = self.substate(gas_limit, is_static);
+		substate.account_mut(context.address);
+
+		if let Some(depth) = self.depth {
+			if depth + 1 > self.config.call_stack_limit {
+				let _ = self.merge_revert(substate);
+				return Capture::Exit((ExitError::CallTooDeep.into(), Vec::new()))
+			}
+		}
+		if let Some(transfer) = transfer {
+			if let Err(e) = substate.transfer(transfer) {
+				let _ = self.merge_revert(substate);
+				return Capture::Exit((ExitReason::Error(e), Vec::new()))
+			}
+		}
+
+		// code delegate calling a precompile runs it in its own context, so
+		// the precompile only acts for whoever calls it at its own address
+		let caller = if substate.is_static || context.address != code_address {
+			None
+		} else {
+			Some(context.caller)
+		};
+		let event_count = EventCount::get();
+		if let Some(result) = T::Precompiles::execute(caller, code_address, &input, Some(gas_limit)) {
+			return match result.and_then(|output| substate.keep_precompile_output(context.caller, event_count, output)) {
+				Ok(output) => {
+					let _ = self.merge_succeed(substate);
+					Capture::Exit((ExitReason::Succeed(ExitSucceed::Returned), output))
+				},
+				Err(e) => {
+					let _ = self.merge_fail(substate);
+					Capture::Exit((ExitReason::Error(e), Vec::new()))
+				},
+			}
+		}
+
+		let mut runtime = Runtime::new(Rc::new(code), Rc::new(input), context, self.config);
+		match substate.execute(&mut runtime) {
+			ExitReason::Succeed(s) => {
+				let _ = self.merge_succeed(substate);
+				Capture::Exit((ExitReason::Succeed(s), runtime.machine().return_value()))
+			},
+			ExitReason::Error(e) => {
+				let _ = self.merge_fail(substate);
+				Capture::Exit((ExitReason::Error(e), Vec::new()))
+			},
+			ExitReason::Revert(e) => {
+				let _ = self.merge_revert(substate);
+				Capture::Exit((ExitReason::Revert(e), runtime.machine().return_value()))
+			},
+			ExitReason::Fatal(e) => {
+				self.gasometer.fail();
+				let _ = self.merge_fail(substate);
+				Capture::Exit((ExitReason::Fatal(e), Vec::new()))
+			},
+		}
+	}
+
+	/// Keeps what a precompile called by `caller` handed back in the frame of
+	/// the call, and returns its output.  What it wrote is kept to be put back
+	/// first, so that the frame puts it back if the rest fails.
+	fn keep_precompile_output(
+		&mut self,
+		caller: H160,
+		event_count: u32,
+		output: PrecompileOutput,
+	) -> Result<Vec<u8>, ExitError> {
+		if let Some(undo) = output.undo {
+			self.undo.push(Undo { undo, event_count });
+		}
+		self.gasometer.record_cost(output.used_gas)?;
+		for transfer in output.transfers {
+			self.transfer(transfer)?;
+		}
+		self.logs.extend(output.logs);
+		self.deferred.extend(output.deferred.into_iter().map(|write| (caller, write)));
+
+		Ok(output.output)
+	}
+}
+
+impl<'backend, 'config, T: Trait, B: Backend> Handler for Executor<'backend, 'config, T, B> {
+	type CreateInterrupt = Infallible;
+	type CreateFeedback = Infallible;
+	type CallInterrupt = Infallible;
+	type CallFeedback = Infallible;
+
+	fn balance(&self, address: H160) -> U256 {
+		self.state.get(&address)
+			.map(|account| account.basic.balance)
+			.unwrap_or_else(|| self.backend.basic(address).balance)
+	}
+
+	fn code_size(&self, address: H160) -> U256 {
+		U256::from(self.state.get(&address)
+			.and_then(|account| account.code.as_ref().map(|code| code.len()))
+			.unwrap_or_else(|| self.backend.code_size(address)))
+	}
+
+	fn code_hash(&self, address: H160) -> H256 {
+		if !self.exists(address) {
+			return H256::default()
+		}
+		self.state.get(&address)
+			.and_then(|account| account.code.as_ref().map(|code| H256::from(keccak_256(code))))
+			.unwrap_or_else(|| self.backend.code_hash(address))
+	}
+
+	fn code(&self, address: H160) -> Vec<u8> {
+		self.state.get(&address)
+			.and_then(|account| account.code.clone())
+			.unwrap_or_else(|| self.backend.code(address))
+	}
+
+	fn storage(&self, address: H160, index: H256) -> H256 {
+		match self.state.get(&address) {
+			Some(account) => match account.storage.get(&index) {
+				Some(value) => *value,
+				None if account.reset_storage => H256::default(),
+				None => self.backend.storage(address, index),
+			},
+			None => self.backend.storage(address, index),
+		}
+	}
+
+	fn original_storage(&self, address: H160, index: H256) -> H256 {
+		match self.state.get(&address) {
+			Some(account) if account.reset_storage => H256::default(),
+			_ => self.backend.storage(address, index),
+		}
+	}
+
+	fn gas_left(&self) -> U256 { U256::from(self.gasometer.gas()) }
+	fn gas_price(&self) -> U256 { self.backend.gas_price() }
+	fn origin(&self) -> H160 { self.backend.origin() }
+	fn block_hash(&self, number: U256) -> H256 { self.backend.block_hash(number) }
+	fn block_number(&self) -> U256 { self.backend.block_number() }
+	fn block_coinbase(&self) -> H160 { self.backend.block_coinbase() }
+	fn block_timestamp(&self) -> U256 { self.backend.block_timestamp() }
+	fn block_difficulty(&self) -> U256 { self.backend.block_difficulty() }
+	fn block_gas_limit(&self) -> U256 { self.backend.block_gas_limit() }
+	fn chain_id(&self) -> U256 { self.backend.chain_id() }
+
+	fn exists(&self, address: H160) -> bool {
+		if self.config.empty_considered_exists {
+			self.state.contains_key(&address) || self.backend.exists(address)
+		} else {
+			!self.balance(address).is_zero() || !self.nonce(address).is_zero() || !self.code_size(address).is_zero()
+		}
+	}
+
+	fn deleted(&self, address: H160) -> bool {
+		self.deleted.contains(&address)
+	}
+
+	fn set_storage(&mut self, address: H160, index: H256, value: H256) -> Result<(), ExitError> {
+		self.account_mut(address).storage.insert(index, value);
+		Ok(())
+	}
+
+	fn log(&mut self, address: H160, topics: Vec<H256>, data: Vec<u8>) -> Result<(), ExitError> {
+		self.logs.push(Log { address, topics, data });
+		Ok(())
+	}
+
+	fn mark_delete(&mut self, address: H160, target: H160) -> Result<(), ExitError> {
+		let balance = self.balance(address);
+		self.transfer(Transfer { source: address, target, value: balance })?;
+		self.account_mut(address).basic.balance = U256::zero();
+		self.deleted.insert(address);
+		Ok(())
+	}
+
+	fn create(
+		&mut self,
+		caller: H160,
+		scheme: CreateScheme,
+		value: U256,
+		init_code: Vec<u8>,
+		target_gas: Option<usize>,
+	) -> Capture<(ExitReason, Option<H160>, Vec<u8>), Self::CreateInterrupt> {
+		self.create_inner(caller, scheme, value, init_code, target_gas, true)
+	}
+
+	fn call(
+		&mut self,
+		code_address: H160,
+		transfer: Option<Transfer>,
+		input: Vec<u8>,
+		target_gas: Option<usize>,
+		is_static: bool,
+		context: Context,
+	) -> Capture<(ExitReason, Vec<u8>), Self::CallInterrupt> {
+		self.call_inner(code_address, transfer, input, target_gas, is_static, true, true, context)
+	}
+
+	fn pre_validate(
+		&mut self,
+		context: &Context,
+		opcode: Result<Opcode, ExternalOpcode>,
+		stack: &Stack,
+	) -> Result<(), ExitError> {
+		let (gas_cost, memory_cost) = gasometer::opcode_cost(
+			context.address,
+			opcode,
+			stack,
+			self.is_static,
+			self.config,
+			self,
+		)?;
+		self.gasometer.record_opcode(gas_cost, memory_cost)
+	}
+}
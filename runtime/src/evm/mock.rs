@@ -0,0 +1,116 @@
+//! Test runtime for the evm module.
+
+use frame_support::{impl_outer_origin, parameter_types, weights::Weight};
+use sp_core::{H160, H256};
+use sp_runtime::{
+	Perbill, testing::Header, traits::{BlakeTwo256, IdentityLookup},
+};
+use super::{AddressMapping, EthereumPrecompiles, Module, Trait};
+
+impl_outer_origin! {
+	pub enum Origin for Test {}
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Test;
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: Weight = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::one();
+}
+
+impl system::Trait for Test {
+	type Origin = Origin;
+	type Call = ();
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = ();
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+}
+
+parameter_types! {
+	pub const ExistentialDeposit: u128 = 0;
+	pub const TransferFee: u128 = 0;
+	pub const CreationFee: u128 = 0;
+}
+
+impl balances::Trait for Test {
+	type Balance = u128;
+	type OnFreeBalanceZero = ();
+	type OnNewAccount = ();
+	type Event = ();
+	type DustRemoval = ();
+	type TransferPayment = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type TransferFee = TransferFee;
+	type CreationFee = CreationFee;
+}
+
+parameter_types! {
+	pub const MinimumPeriod: u64 = 1;
+}
+
+impl timestamp::Trait for Test {
+	type Moment = u64;
+	type OnTimestampSet = ();
+	type MinimumPeriod = MinimumPeriod;
+}
+
+/// Account `n` acts as address `n`, which is backed by account `n + 1_000`.
+pub struct NumericAddressMapping;
+
+impl AddressMapping<u64> for NumericAddressMapping {
+	fn into_account_id(address: H160) -> u64 {
+		address.to_low_u64_be().wrapping_add(1_000)
+	}
+
+	fn into_address(who: &u64) -> H160 {
+		H160::from_low_u64_be(*who)
+	}
+}
+
+parameter_types! {
+	pub const MinGasPrice: u128 = 1;
+	pub const BlockGasLimit: u32 = 1_000_000;
+	pub const ChainId: u64 = 42;
+}
+
+impl Trait for Test {
+	type Event = ();
+	type Currency = balances::Module<Test>;
+	type AddressMapping = NumericAddressMapping;
+	type OnGasPayment = ();
+	type OnExecuted = ();
+	type Precompiles = EthereumPrecompiles;
+	type MinGasPrice = MinGasPrice;
+	type BlockGasLimit = BlockGasLimit;
+	type ChainId = ChainId;
+}
+
+pub type Evm = Module<Test>;
+pub type Balances = balances::Module<Test>;
+
+/// The glmr each test account's evm address starts with.
+pub const INITIAL_BALANCE: u128 = 1_000_000_000;
+
+/// Build test externalities where the evm addresses of accounts 1 and 2 hold
+/// `INITIAL_BALANCE`.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	let mut storage = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	balances::GenesisConfig::<Test> {
+		balances: vec![(1_001, INITIAL_BALANCE), (1_002, INITIAL_BALANCE)],
+		vesting: vec![],
+	}.assimilate_storage(&mut storage).unwrap();
+	storage.into()
+}
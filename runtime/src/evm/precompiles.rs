@@ -0,0 +1,220 @@
+//! Precompiled contracts: native code contracts call at fixed addresses, with
+//! ABI encoded input and output.
+//!
+//! A precompile acts for whoever calls it: the sender of a transaction
+//! calling it directly, or the contract calling it inside the evm.  What it
+//! writes natively is not part of the evm state, so it hands back what the
+//! executor needs to keep it in step with the evm: the glmr it moves, which
+//! the executor moves through the evm state, a way to put back what it wrote,
+//! run if the contract that called it reverts, and writes that can not be put
+//! back, which wait until the execution has succeeded.
+
+use sp_core::{H160, U256};
+use sp_io::hashing::{keccak_256, sha2_256};
+use sp_std::prelude::*;
+use rust_evm::{ExitError, Transfer};
+use ripemd160::Digest;
+use super::Log;
+
+/// A write a precompile defers until the execution calling it has succeeded.
+pub type DeferredWrite = Box<dyn FnOnce() -> Result<(), &'static str>>;
+
+/// What a precompile returned.
+pub struct PrecompileOutput {
+	pub output: Vec<u8>,
+	pub used_gas: usize,
+	/// Logs to keep with the call.
+	pub logs: Vec<Log>,
+	/// Glmr the precompile moves between addresses, which is moved for it once
+	/// it has returned, so contracts see it in their balances.
+	pub transfers: Vec<Transfer>,
+	/// Puts back what the precompile wrote, should the contract that called it
+	/// revert.
+	pub undo: Option<Box<dyn FnOnce()>>,
+	/// Writes that can not be put back, run once the execution that called the
+	/// precompile has succeeded.  A transaction calling the precompile
+	/// directly runs them straight away.
+	pub deferred: Vec<DeferredWrite>,
+}
+
+impl PrecompileOutput {
+	/// An output using `used_gas`, with no logs and nothing left to do.
+	pub fn new(output: Vec<u8>, used_gas: usize) -> Self {
+		PrecompileOutput {
+			output,
+			used_gas,
+			logs: Vec::new(),
+			transfers: Vec::new(),
+			undo: None,
+			deferred: Vec::new(),
+		}
+	}
+}
+
+/// A set of precompiles.
+pub trait Precompiles {
+	/// Runs the precompile at `address`, none if there is no precompile there.
+	///
+	/// `caller` is the sender of a transaction calling the precompile
+	/// directly, or the contract calling it.  It is none for static calls and
+	/// for code delegate calling the precompile, which must not act for the
+	/// caller of that code.  The gas used has to be checked against
+	/// `target_gas` before anything is written, and a precompile that fails
+	/// must leave no changes behind.
+	fn execute(
+		caller: Option<H160>,
+		address: H160,
+		input: &[u8],
+		target_gas: Option<usize>,
+	) -> Option<Result<PrecompileOutput, ExitError>>;
+}
+
+impl Precompiles for () {
+	fn execute(_: Option<H160>, _: H160, _: &[u8], _: Option<usize>) -> Option<Result<PrecompileOutput, ExitError>> {
+		None
+	}
+}
+
+/// Tries the first set, then the second.
+impl<A: Precompiles, B: Precompiles> Precompiles for (A, B) {
+	fn execute(
+		caller: Option<H160>,
+		address: H160,
+		input: &[u8],
+		target_gas: Option<usize>,
+	) -> Option<Result<PrecompileOutput, ExitError>> {
+		A::execute(caller, address, input, target_gas)
+			.or_else(|| B::execute(caller, address, input, target_gas))
+	}
+}
+
+/// The precompiles at the same addresses as in Ethereum: ecrecover at 1,
+/// sha256 at 2, ripemd160 at 3 and identity at 4.
+pub struct EthereumPrecompiles;
+
+impl Precompiles for EthereumPrecompiles {
+	fn execute(
+		_caller: Option<H160>,
+		address: H160,
+		input: &[u8],
+		target_gas: Option<usize>,
+	) -> Option<Result<PrecompileOutput, ExitError>> {
+		if address > H160::from_low_u64_be(u64::max_value()) {
+			return None
+		}
+		let result = match address.to_low_u64_be() {
+			1 => charge(3_000, target_gas).map(|used_gas| PrecompileOutput::new(ecrecover(input), used_gas)),
+			2 => charge(linear_cost(60, 12, input), target_gas)
+				.map(|used_gas| PrecompileOutput::new(sha2_256(input).to_vec(), used_gas)),
+			3 => charge(linear_cost(600, 120, input), target_gas).map(|used_gas| {
+				let mut output = vec![0u8; 12];
+				output.extend_from_slice(&ripemd160::Ripemd160::digest(input));
+				PrecompileOutput::new(output, used_gas)
+			}),
+			4 => charge(linear_cost(15, 3, input), target_gas)
+				.map(|used_gas| PrecompileOutput::new(input.to_vec(), used_gas)),
+			_ => return None,
+		};
+		Some(result)
+	}
+}
+
+/// The address that signed a hash, left padded to a word, or nothing if the
+/// signature is not valid.
+fn ecrecover(input: &[u8]) -> Vec<u8> {
+	let mut input = input.to_vec();
+	input.resize(128, 0);
+
+	// v is a word holding 27 or 28
+	if input[32..63].iter().any(|byte| *byte != 0) || (input[63] != 27 && input[63] != 28) {
+		return Vec::new()
+	}
+	let mut message = [0u8; 32];
+	message.copy_from_slice(&input[0..32]);
+	let mut signature = [0u8; 65];
+	signature[0..64].copy_from_slice(&input[64..128]);
+	signature[64] = input[63] - 27;
+
+	match sp_io::crypto::secp256k1_ecdsa_recover(&signature, &message) {
+		Ok(public) => {
+			let mut output = vec![0u8; 12];
+			output.extend_from_slice(&keccak_256(&public)[12..]);
+			output
+		},
+		Err(_) => Vec::new(),
+	}
+}
+
+/// `base` plus `per_word` for every word of `input`, rounding up.
+fn linear_cost(base: usize, per_word: usize, input: &[u8]) -> usize {
+	base.saturating_add(per_word.saturating_mul((input.len() + 31) / 32))
+}
+
+/// Checks that `cost` fits in `target_gas`, and returns it as the gas used.
+pub fn charge(cost: usize, target_gas: Option<usize>) -> Result<usize, ExitError> {
+	match target_gas {
+		Some(target_gas) if cost > target_gas => Err(ExitError::OutOfGas),
+		_ => Ok(cost),
+	}
+}
+
+/// The ABI selector of a function signature such as `transfer(address,uint256)`.
+pub fn selector(signature: &str) -> [u8; 4] {
+	let mut selector = [0u8; 4];
+	selector.copy_from_slice(&keccak_256(signature.as_bytes())[0..4]);
+	selector
+}
+
+/// Splits ABI encoded input into its selector and arguments.
+pub fn split_selector(input: &[u8]) -> Result<([u8; 4], &[u8]), ExitError> {
+	if input.len() < 4 {
+		return Err(ExitError::Other("Missing function selector".into()))
+	}
+	let mut selector = [0u8; 4];
+	selector.copy_from_slice(&input[0..4]);
+	Ok((selector, &input[4..]))
+}
+
+/// The `index`th word of ABI encoded arguments.
+pub fn read_u256(arguments: &[u8], index: usize) -> Result<U256, ExitError> {
+	let start = index * 32;
+	if arguments.len() < start + 32 {
+		return Err(ExitError::Other("Missing function argument".into()))
+	}
+	Ok(U256::from_big_endian(&arguments[start..start + 32]))
+}
+
+/// The `index`th word of ABI encoded arguments, as an address.
+pub fn read_address(arguments: &[u8], index: usize) -> Result<H160, ExitError> {
+	let word = read_u256(arguments, index)?;
+	if word > U256::from_big_endian(H160::repeat_byte(0xff).as_bytes()) {
+		return Err(ExitError::Other("Invalid address argument".into()))
+	}
+	let mut bytes = [0u8; 32];
+	word.to_big_endian(&mut bytes);
+	Ok(H160::from_slice(&bytes[12..]))
+}
+
+/// The `index`th argument of ABI encoded arguments, as a dynamic array of
+/// words.
+pub fn read_words(arguments: &[u8], index: usize) -> Result<Vec<U256>, ExitError> {
+	let offset = read_u256(arguments, index)?;
+	if offset > U256::from(arguments.len()) || offset.low_u64() % 32 != 0 {
+		return Err(ExitError::Other("Invalid array argument".into()))
+	}
+	let start = offset.low_u64() as usize / 32;
+	let length = read_u256(arguments, start)?;
+	if length > U256::from(arguments.len() / 32) {
+		return Err(ExitError::Other("Invalid array argument".into()))
+	}
+	(0..length.low_u64() as usize).map(|item| read_u256(arguments, start + 1 + item)).collect()
+}
+
+/// ABI encodes words.
+pub fn encode_words(words: &[U256]) -> Vec<u8> {
+	let mut output = vec![0u8; words.len() * 32];
+	for (word, bytes) in words.iter().zip(output.chunks_mut(32)) {
+		word.to_big_endian(bytes);
+	}
+	output
+}
@@ -0,0 +1,56 @@
+//! Runtime API for dry running evm executions, as `eth_call` and
+//! `eth_estimateGas` do.
+
+use codec::Codec;
+use sp_core::{H160, U256};
+use sp_std::prelude::*;
+use super::ExecutionInfo;
+
+sp_api::decl_runtime_apis! {
+	/// Read-only access to the evm.
+	pub trait EvmApi<AccountId> where
+		AccountId: Codec,
+	{
+		/// The account holding the glmr of `address`.
+		fn account_of(address: H160) -> AccountId;
+
+		/// The address `who` acts as in the evm.
+		fn address_of(who: AccountId) -> H160;
+
+		/// The chain id contracts see.
+		fn chain_id() -> u64;
+
+		/// The most gas the executions of a block may use together, and so the
+		/// most a dry run may use.
+		fn block_gas_limit() -> u32;
+
+		/// The glmr balance of `address`.
+		fn balance_of(address: H160) -> U256;
+
+		/// The nonce of `address`, which its next signed transaction must have.
+		fn nonce_of(address: H160) -> U256;
+
+		/// What calling the contract at `target` would do.  Nothing is charged
+		/// and no state is changed.  `gas_limit` is capped at the block gas
+		/// limit.
+		fn call(
+			source: H160,
+			target: H160,
+			input: Vec<u8>,
+			value: U256,
+			gas_limit: u32,
+			gas_price: U256,
+		) -> ExecutionInfo<Vec<u8>>;
+
+		/// What creating a contract would do, and the address it would be
+		/// created at.  Nothing is charged and no state is changed.  `gas_limit`
+		/// is capped at the block gas limit.
+		fn create(
+			source: H160,
+			init: Vec<u8>,
+			value: U256,
+			gas_limit: u32,
+			gas_price: U256,
+		) -> ExecutionInfo<H160>;
+	}
+}
@@ -0,0 +1,353 @@
+//! Tests for the evm module.
+
+use super::*;
+use super::mock::*;
+use frame_support::{assert_ok, StorageValue, weights::{DispatchClass, GetDispatchInfo}};
+use sp_runtime::traits::OnFinalize;
+use rlp::RlpStream;
+use sp_io::hashing::{keccak_256, sha2_256};
+
+/// Stores the first word of its input and returns it.
+const RUNTIME_CODE: [u8; 17] = [
+	0x60, 0x00, 0x35, // calldataload(0)
+	0x60, 0x00, 0x55, // sstore(0, _)
+	0x60, 0x00, 0x54, // sload(0)
+	0x60, 0x00, 0x52, // mstore(0, _)
+	0x60, 0x20, 0x60, 0x00, 0xf3, // return(0, 32)
+];
+
+/// Deploys `RUNTIME_CODE`.
+fn init_code() -> Vec<u8> {
+	let mut code = vec![
+		0x60, 0x11, 0x80, // push the code size twice
+		0x60, 0x0b, 0x60, 0x00, 0x39, // codecopy(0, 11, size)
+		0x60, 0x00, 0xf3, // return(0, size)
+	];
+	code.extend_from_slice(&RUNTIME_CODE);
+	code
+}
+
+const GAS_LIMIT: u32 = 100_000;
+const GAS_PRICE: u64 = 2;
+
+fn address(who: u64) -> H160 {
+	NumericAddressMapping::into_address(&who)
+}
+
+fn gas_price() -> U256 {
+	U256::from(GAS_PRICE)
+}
+
+fn deploy() -> H160 {
+	let info = Evm::execute_create(address(1), init_code(), U256::zero(), GAS_LIMIT, gas_price()).unwrap();
+	assert_eq!(info.status, ExitStatus::Succeeded);
+	info.value
+}
+
+#[test]
+fn contracts_can_be_created_and_called() {
+	new_test_ext().execute_with(|| {
+		let contract = Evm::dry_run_create(address(1), init_code(), U256::zero(), GAS_LIMIT, gas_price()).value;
+		assert_ok!(Evm::create(Origin::signed(1), init_code(), U256::zero(), GAS_LIMIT, gas_price()));
+		assert_eq!(Evm::code_of(contract), RUNTIME_CODE.to_vec());
+
+		let word = H256::from_low_u64_be(42);
+		assert_ok!(Evm::call(Origin::signed(2), contract, word.as_bytes().to_vec(), U256::zero(), GAS_LIMIT, gas_price()));
+		assert_eq!(Evm::storage_at(contract, H256::zero()), word);
+
+		let word = H256::from_low_u64_be(7);
+		let info = Evm::execute_call(address(2), contract, word.as_bytes().to_vec(), U256::zero(), GAS_LIMIT, gas_price()).unwrap();
+		assert_eq!(info.status, ExitStatus::Succeeded);
+		assert_eq!(info.value, word.as_bytes().to_vec());
+		assert_eq!(Evm::storage_at(contract, H256::zero()), word);
+	});
+}
+
+#[test]
+fn gas_is_paid_for_what_is_used() {
+	new_test_ext().execute_with(|| {
+		let info = Evm::execute_create(address(1), init_code(), U256::zero(), GAS_LIMIT, gas_price()).unwrap();
+		assert!(info.used_gas > U256::zero() && info.used_gas < U256::from(GAS_LIMIT));
+		let fee = info.used_gas.low_u128() * u128::from(GAS_PRICE);
+		assert_eq!(Balances::free_balance(1_001), INITIAL_BALANCE - fee);
+	});
+}
+
+#[test]
+fn glmr_can_be_sent_to_an_address() {
+	new_test_ext().execute_with(|| {
+		let info = Evm::execute_call(address(1), address(3), vec![], U256::from(500), GAS_LIMIT, gas_price()).unwrap();
+		assert_eq!(info.status, ExitStatus::Succeeded);
+		assert_eq!(info.used_gas, U256::from(21_000));
+		assert_eq!(Balances::free_balance(1_003), 500);
+		assert_eq!(Evm::balance_of(address(3)), U256::from(500));
+		assert_eq!(Balances::free_balance(1_001), INITIAL_BALANCE - 500 - 21_000 * u128::from(GAS_PRICE));
+	});
+}
+
+#[test]
+fn failed_executions_still_pay_for_gas() {
+	new_test_ext().execute_with(|| {
+		let contract = deploy();
+		let before = Balances::free_balance(1_002);
+
+		// enough for the transaction itself but not for the storage write
+		let word = H256::from_low_u64_be(42);
+		let info = Evm::execute_call(address(2), contract, word.as_bytes().to_vec(), U256::zero(), 30_000, gas_price()).unwrap();
+		assert_eq!(info.status, ExitStatus::Failed);
+		assert_eq!(Evm::storage_at(contract, H256::zero()), H256::zero());
+		assert_eq!(Balances::free_balance(1_002), before - info.used_gas.low_u128() * u128::from(GAS_PRICE));
+	});
+}
+
+#[test]
+fn gas_must_be_affordable_and_within_limits() {
+	new_test_ext().execute_with(|| {
+		assert_eq!(
+			Evm::execute_create(address(1), init_code(), U256::zero(), GAS_LIMIT, U256::zero()),
+			Err("Gas price is below the minimum"),
+		);
+		assert_eq!(
+			Evm::execute_create(address(1), init_code(), U256::zero(), 2_000_000, gas_price()),
+			Err("Gas limit is above the gas left in the block"),
+		);
+		assert_eq!(
+			Evm::execute_create(address(3), init_code(), U256::zero(), GAS_LIMIT, gas_price()),
+			Err("Not enough glmr to pay for gas"),
+		);
+		assert_eq!(Balances::free_balance(1_001), INITIAL_BALANCE);
+	});
+}
+
+#[test]
+fn executions_of_a_block_share_the_block_gas_limit() {
+	new_test_ext().execute_with(|| {
+		let info = Evm::execute_call(address(1), address(3), vec![], U256::zero(), GAS_LIMIT, gas_price()).unwrap();
+		assert_eq!(Evm::block_gas_used(), info.used_gas.low_u32());
+
+		BlockGasUsed::put(BlockGasLimit::get() - GAS_LIMIT + 1);
+		assert_eq!(
+			Evm::execute_call(address(1), address(3), vec![], U256::zero(), GAS_LIMIT, gas_price()),
+			Err("Gas limit is above the gas left in the block"),
+		);
+		assert_ok!(Evm::execute_call(address(1), address(3), vec![], U256::zero(), GAS_LIMIT - 1, gas_price()));
+
+		Evm::on_finalize(1);
+		assert_eq!(Evm::block_gas_left(), BlockGasLimit::get());
+	});
+}
+
+#[test]
+fn dry_runs_use_no_more_than_the_block_gas_limit() {
+	new_test_ext().execute_with(|| {
+		// jumps back to the start forever
+		let init = vec![0x5b, 0x60, 0x00, 0x56];
+		let info = Evm::dry_run_create(address(1), init, U256::zero(), u32::max_value(), gas_price());
+		assert_eq!(info.status, ExitStatus::Failed);
+		assert_eq!(info.used_gas, U256::from(BlockGasLimit::get()));
+	});
+}
+
+#[test]
+fn dry_runs_change_nothing() {
+	new_test_ext().execute_with(|| {
+		let info = Evm::dry_run_create(address(1), init_code(), U256::zero(), GAS_LIMIT, gas_price());
+		assert_eq!(info.status, ExitStatus::Succeeded);
+		assert!(Evm::code_of(info.value).is_empty());
+		assert_eq!(Evm::nonce_of(address(1)), U256::zero());
+		assert_eq!(Balances::free_balance(1_001), INITIAL_BALANCE);
+
+		let contract = deploy();
+		let word = H256::from_low_u64_be(42);
+		let info = Evm::dry_run_call(address(2), contract, word.as_bytes().to_vec(), U256::zero(), GAS_LIMIT, gas_price());
+		assert_eq!(info.value, word.as_bytes().to_vec());
+		assert_eq!(Evm::storage_at(contract, H256::zero()), H256::zero());
+		assert_eq!(Balances::free_balance(1_002), INITIAL_BALANCE);
+	});
+}
+
+/// Encodes a legacy transaction whose last three items are `tail`, which is
+/// the chain id and two empty items while signing, and the signature after.
+fn rlp_transaction(nonce: u64, to: Option<H160>, input: &[u8], tail: [Vec<u8>; 3]) -> Vec<u8> {
+	let mut stream = RlpStream::new_list(9);
+	stream.append(&nonce);
+	stream.append(&GAS_PRICE);
+	stream.append(&GAS_LIMIT);
+	stream.append(&to.map(|to| to.as_bytes().to_vec()).unwrap_or_default());
+	stream.append(&0u8);
+	stream.append(&input.to_vec());
+	for item in tail.iter() {
+		stream.append(item);
+	}
+	stream.out()
+}
+
+fn secret_key() -> secp256k1::SecretKey {
+	secp256k1::SecretKey::parse(&[7; 32]).unwrap()
+}
+
+/// The address that signs with `secret_key()`.
+fn signer() -> H160 {
+	let public = secp256k1::PublicKey::from_secret_key(&secret_key()).serialize();
+	H160::from_slice(&keccak_256(&public[1..])[12..])
+}
+
+/// A transaction signed by `signer()` for `chain_id`, as an Ethereum wallet
+/// signs it.
+fn signed_transaction(nonce: u64, to: Option<H160>, input: &[u8], chain_id: u8) -> Vec<u8> {
+	let message = keccak_256(&rlp_transaction(nonce, to, input, [vec![chain_id], vec![], vec![]]));
+	let (signature, recovery_id) = secp256k1::sign(&secp256k1::Message::parse(&message), &secret_key());
+	let signature = signature.serialize();
+	let v = chain_id * 2 + 35 + recovery_id.serialize();
+	rlp_transaction(nonce, to, input, [vec![v], signature[..32].to_vec(), signature[32..].to_vec()])
+}
+
+fn fund_signer() {
+	let _ = Balances::deposit_creating(&NumericAddressMapping::into_account_id(signer()), INITIAL_BALANCE);
+}
+
+#[test]
+fn ethereum_transactions_run_as_their_signer() {
+	new_test_ext().execute_with(|| {
+		fund_signer();
+		let transaction = Transaction::decode(&signed_transaction(0, None, &init_code(), 42)).unwrap();
+		assert_eq!(transaction.sender, signer());
+		assert_eq!(transaction.chain_id, Some(42));
+		assert_eq!(transaction.to, None);
+
+		let contract = Evm::dry_run_create(signer(), init_code(), U256::zero(), GAS_LIMIT, gas_price()).value;
+		assert_ok!(Evm::transact(Origin::NONE, signed_transaction(0, None, &init_code(), 42)));
+		assert_eq!(Evm::code_of(contract), RUNTIME_CODE.to_vec());
+		assert_eq!(Evm::nonce_of(signer()), U256::from(1));
+
+		let word = H256::from_low_u64_be(42);
+		assert_ok!(Evm::transact(Origin::NONE, signed_transaction(1, Some(contract), word.as_bytes(), 42)));
+		assert_eq!(Evm::storage_at(contract, H256::zero()), word);
+		assert_eq!(Evm::nonce_of(signer()), U256::from(2));
+		assert!(Evm::balance_of(signer()) < U256::from(INITIAL_BALANCE));
+
+		assert!(Evm::transact(Origin::signed(1), signed_transaction(2, Some(contract), &[], 42)).is_err());
+	});
+}
+
+#[test]
+fn ethereum_transactions_are_validated_before_they_are_pooled() {
+	new_test_ext().execute_with(|| {
+		let transact = |nonce, chain_id| Call::transact(signed_transaction(nonce, Some(address(1)), &[], chain_id));
+		assert_eq!(Evm::validate_unsigned(&transact(0, 42)), InvalidTransaction::Payment.into());
+
+		fund_signer();
+		assert_eq!(Evm::validate_unsigned(&transact(0, 43)), InvalidTransaction::BadProof.into());
+		assert_eq!(Evm::validate_unsigned(&Call::transact(vec![1, 2, 3])), InvalidTransaction::BadProof.into());
+
+		let valid = Evm::validate_unsigned(&transact(0, 42)).unwrap();
+		assert_eq!(valid.priority, GAS_PRICE);
+		assert!(valid.requires.is_empty());
+		assert_eq!(valid.provides, vec![(signer(), U256::zero()).encode()]);
+
+		// a later nonce is valid, but waits for the one before it
+		let valid = Evm::validate_unsigned(&transact(1, 42)).unwrap();
+		assert_eq!(valid.requires, vec![(signer(), U256::zero()).encode()]);
+		assert_eq!(Evm::transact(Origin::NONE, signed_transaction(1, Some(address(1)), &[], 42)), Err("Invalid transaction nonce"));
+
+		assert_ok!(Evm::transact(Origin::NONE, signed_transaction(0, Some(address(1)), &[], 42)));
+		assert_eq!(Evm::validate_unsigned(&transact(0, 42)), InvalidTransaction::Stale.into());
+	});
+}
+
+#[test]
+fn executions_weigh_their_share_of_the_block_gas_limit() {
+	// the whole block weight is available in the mock, 1024 for a million gas
+	let call = |gas_limit| Call::<Test>::call(address(1), vec![], U256::zero(), gas_limit, gas_price()).get_dispatch_info();
+	assert_eq!(call(GAS_LIMIT).weight, 102);
+	assert_eq!(call(GAS_LIMIT).class, DispatchClass::Normal);
+	assert_eq!(call(1_000_000).weight, 1024);
+	assert_eq!(call(u32::max_value()).weight, 1024);
+
+	let create = Call::<Test>::create(init_code(), U256::zero(), 500_000, gas_price()).get_dispatch_info();
+	assert_eq!(create.weight, 512);
+
+	// a transaction weighs its own gas limit, or the block gas limit if it can
+	// not be read
+	assert_eq!(Call::<Test>::transact(signed_transaction(0, None, &init_code(), 42)).get_dispatch_info().weight, 102);
+	assert_eq!(Call::<Test>::transact(vec![1, 2, 3]).get_dispatch_info().weight, 1024);
+}
+
+#[test]
+fn malleable_signatures_are_rejected() {
+	let message = keccak_256(&rlp_transaction(0, Some(address(1)), &[], [vec![42], vec![], vec![]]));
+	let (signature, recovery_id) = secp256k1::sign(&secp256k1::Message::parse(&message), &secret_key());
+	let signature = signature.serialize();
+	let v = 42 * 2 + 35 + recovery_id.serialize();
+	let transaction = |v: u8, r: &[u8], s: &[u8]| rlp_transaction(0, Some(address(1)), &[], [vec![v], r.to_vec(), s.to_vec()]);
+	assert_eq!(Transaction::decode(&transaction(v, &signature[..32], &signature[32..])).unwrap().sender, signer());
+
+	// the high s form of the same signature recovers the same signer with the
+	// other recovery id
+	let order = U256::from_big_endian(&[
+		0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+		0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+	]);
+	let mut high_s = [0u8; 32];
+	(order - U256::from_big_endian(&signature[32..])).to_big_endian(&mut high_s);
+	assert_eq!(Transaction::decode(&transaction(v ^ 1, &signature[..32], &high_s)), Err("Invalid signature"));
+
+	assert_eq!(Transaction::decode(&transaction(v, &[], &signature[32..])), Err("Invalid signature"));
+	assert_eq!(Transaction::decode(&transaction(v, &signature[..32], &[])), Err("Invalid signature"));
+}
+
+fn precompile(index: u64) -> H160 {
+	H160::from_low_u64_be(index)
+}
+
+#[test]
+fn ethereum_precompiles_are_at_their_ethereum_addresses() {
+	new_test_ext().execute_with(|| {
+		let call = |index, input: &[u8]| {
+			let info = Evm::dry_run_call(address(1), precompile(index), input.to_vec(), U256::zero(), GAS_LIMIT, gas_price());
+			assert_eq!(info.status, ExitStatus::Succeeded);
+			info.value
+		};
+
+		let message = keccak_256(b"moonbeam");
+		let (signature, recovery_id) = secp256k1::sign(&secp256k1::Message::parse(&message), &secret_key());
+		let mut input = message.to_vec();
+		input.extend_from_slice(H256::from_low_u64_be(27 + u64::from(recovery_id.serialize())).as_bytes());
+		input.extend_from_slice(&signature.serialize());
+		assert_eq!(call(1, &input), H256::from(signer()).as_bytes().to_vec());
+		// an invalid v recovers nothing
+		input[63] = 29;
+		assert!(call(1, &input).is_empty());
+
+		assert_eq!(call(2, b"moonbeam"), sha2_256(b"moonbeam").to_vec());
+
+		let mut ripemd160_of_nothing = vec![0u8; 12];
+		ripemd160_of_nothing.extend_from_slice(&[
+			0x9c, 0x11, 0x85, 0xa5, 0xc5, 0xe9, 0xfc, 0x54, 0x61, 0x28,
+			0x08, 0x97, 0x7e, 0xe8, 0xf5, 0x48, 0xb2, 0x25, 0x8d, 0x31,
+		]);
+		assert_eq!(call(3, &[]), ripemd160_of_nothing);
+
+		assert_eq!(call(4, b"moonbeam"), b"moonbeam".to_vec());
+	});
+}
+
+#[test]
+fn transactions_calling_a_precompile_run_it_natively() {
+	new_test_ext().execute_with(|| {
+		let info = Evm::execute_call(address(1), precompile(2), b"moonbeam".to_vec(), U256::zero(), GAS_LIMIT, gas_price()).unwrap();
+		assert_eq!(info.status, ExitStatus::Succeeded);
+		assert_eq!(info.value, sha2_256(b"moonbeam").to_vec());
+		// the base transaction gas, plus 60 and 12 for the one word hashed
+		assert_eq!(info.used_gas, U256::from(21_072));
+		assert_eq!(Balances::free_balance(1_001), INITIAL_BALANCE - 21_072 * u128::from(GAS_PRICE));
+		// and so do dry runs, which estimate the same gas
+		let info = Evm::dry_run_call(address(1), precompile(2), b"moonbeam".to_vec(), U256::zero(), GAS_LIMIT, gas_price());
+		assert_eq!(info.used_gas, U256::from(21_072));
+
+		let info = Evm::execute_call(address(2), precompile(2), b"moonbeam".to_vec(), U256::zero(), 21_050, gas_price()).unwrap();
+		assert_eq!(info.status, ExitStatus::Failed);
+		assert_eq!(info.used_gas, U256::from(21_050));
+		assert_eq!(Balances::free_balance(1_002), INITIAL_BALANCE - 21_050 * u128::from(GAS_PRICE));
+	});
+}
@@ -0,0 +1,135 @@
+//! Legacy Ethereum transactions, RLP encoded and signed the way Ethereum
+//! wallets sign them.
+
+use rlp::{Rlp, RlpStream};
+use sp_core::{H160, H256, U256};
+use sp_io::{crypto::secp256k1_ecdsa_recover, hashing::keccak_256};
+use sp_std::prelude::*;
+
+/// Half the order of the secp256k1 curve, the largest s of a valid signature.
+const HALF_CURVE_ORDER: [u8; 32] = [
+	0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+	0x5d, 0x57, 0x6e, 0x73, 0x57, 0xa4, 0x50, 0x1d, 0xdf, 0xe9, 0x2f, 0x46, 0x68, 0x1b, 0x20, 0xa0,
+];
+
+/// A legacy Ethereum transaction, with its sender recovered from its
+/// signature.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Transaction {
+	pub nonce: U256,
+	pub gas_price: U256,
+	pub gas_limit: U256,
+	/// The contract called, or none to create a contract.
+	pub to: Option<H160>,
+	pub value: U256,
+	/// The input of a call or the init code of a create.
+	pub input: Vec<u8>,
+	/// The chain the transaction was signed for, none if it was signed
+	/// without EIP-155 replay protection.
+	pub chain_id: Option<u64>,
+	/// The recovery id of the signature, with the chain id folded in under
+	/// EIP-155.
+	pub v: u64,
+	pub r: U256,
+	pub s: U256,
+	/// The address that signed the transaction.
+	pub sender: H160,
+	/// The Ethereum hash of the transaction, the keccak hash of its encoding.
+	pub hash: H256,
+}
+
+impl Transaction {
+	/// Decodes an RLP encoded transaction and recovers its sender.
+	pub fn decode(bytes: &[u8]) -> Result<Self, &'static str> {
+		let rlp = Rlp::new(bytes);
+		if !rlp.is_list() || rlp.item_count() != Ok(9) {
+			return Err("Transaction is not an RLP list of nine items")
+		}
+
+		let v = quantity(item(&rlp, 6)?)?;
+		if v > U256::from(u64::max_value()) {
+			return Err("Invalid signature")
+		}
+		let (chain_id, recovery_id) = match v.low_u64() {
+			v @ 27..=28 => (None, v - 27),
+			v if v >= 35 => (Some((v - 35) / 2), (v - 35) % 2),
+			_ => return Err("Invalid signature"),
+		};
+
+		// what was signed: the first six items, followed by the chain id and
+		// two empty items under EIP-155
+		let mut stream = RlpStream::new_list(if chain_id.is_some() { 9 } else { 6 });
+		for index in 0..6 {
+			let raw = rlp.at(index).map_err(|_| "Invalid transaction encoding")?.as_raw();
+			stream.append_raw(raw, 1);
+		}
+		if let Some(chain_id) = chain_id {
+			stream.append(&chain_id);
+			stream.append(&0u8);
+			stream.append(&0u8);
+		}
+		let message = keccak_256(&stream.out());
+
+		// like Ethereum since Homestead, only the low s form of a signature is
+		// valid, so a transaction has a single hash
+		let (r, s) = (quantity(item(&rlp, 7)?)?, quantity(item(&rlp, 8)?)?);
+		if r.is_zero() || s.is_zero() || s > U256::from_big_endian(&HALF_CURVE_ORDER) {
+			return Err("Invalid signature")
+		}
+		let mut signature = [0u8; 65];
+		r.to_big_endian(&mut signature[0..32]);
+		s.to_big_endian(&mut signature[32..64]);
+		signature[64] = recovery_id as u8;
+		let public = secp256k1_ecdsa_recover(&signature, &message).map_err(|_| "Invalid signature")?;
+
+		let to = match item(&rlp, 3)? {
+			[] => None,
+			address if address.len() == 20 => Some(H160::from_slice(address)),
+			_ => return Err("Invalid transaction encoding"),
+		};
+
+		Ok(Transaction {
+			nonce: quantity(item(&rlp, 0)?)?,
+			gas_price: quantity(item(&rlp, 1)?)?,
+			gas_limit: quantity(item(&rlp, 2)?)?,
+			to,
+			value: quantity(item(&rlp, 4)?)?,
+			input: item(&rlp, 5)?.to_vec(),
+			chain_id,
+			v: v.low_u64(),
+			r,
+			s,
+			sender: H160::from_slice(&keccak_256(&public)[12..]),
+			hash: H256::from(keccak_256(bytes)),
+		})
+	}
+
+	/// Reads the gas limit of an RLP encoded transaction, without checking its
+	/// signature.
+	pub fn gas_limit_of(bytes: &[u8]) -> Result<U256, &'static str> {
+		let rlp = Rlp::new(bytes);
+		if !rlp.is_list() || rlp.item_count() != Ok(9) {
+			return Err("Transaction is not an RLP list of nine items")
+		}
+		quantity(item(&rlp, 2)?)
+	}
+}
+
+/// The bytes of the item at `index` of the list, which must not be a list
+/// itself.
+fn item<'a>(rlp: &Rlp<'a>, index: usize) -> Result<&'a [u8], &'static str> {
+	let item = rlp.at(index).map_err(|_| "Invalid transaction encoding")?;
+	if !item.is_data() {
+		return Err("Invalid transaction encoding")
+	}
+	item.data().map_err(|_| "Invalid transaction encoding")
+}
+
+/// Reads a big endian number of at most 32 bytes.
+fn quantity(bytes: &[u8]) -> Result<U256, &'static str> {
+	if bytes.len() > 32 {
+		return Err("Invalid transaction encoding")
+	}
+	Ok(U256::from_big_endian(bytes))
+}
@@ -13,15 +13,18 @@ use sp_std::prelude::*;
 // added for contracts pallet
 use contracts_rpc_runtime_api::ContractExecResult;
 
-use sp_core::OpaqueMetadata;
+use sp_core::{OpaqueMetadata, H160, U256, crypto::KeyTypeId, u32_trait::{_1, _2, _3, _4}};
 use sp_runtime::{
 	ApplyExtrinsicResult, transaction_validity::TransactionValidity, generic, create_runtime_str,
-	impl_opaque_keys, MultiSignature
+	impl_opaque_keys, MultiSignature, curve::PiecewiseLinear,
 };
 use sp_runtime::traits::{
-	NumberFor, BlakeTwo256, Block as BlockT, StaticLookup, Verify, ConvertInto, IdentifyAccount
+	NumberFor, BlakeTwo256, Block as BlockT, StaticLookup, Verify, ConvertInto, IdentifyAccount,
+	Convert, OpaqueKeys,
 };
 use sp_api::impl_runtime_apis;
+use codec::{Encode, Decode};
+use frame_support::traits::KeyOwnerProofSystem;
 use sp_consensus_aura::sr25519::AuthorityId as AuraId;
 use grandpa::AuthorityList as GrandpaAuthorityList;
 use grandpa::fg_primitives;
@@ -37,9 +40,12 @@ pub use balances::Call as BalancesCall;
 // added for contracts pallet
 pub use contracts::Gas as ContractsGas;
 pub use sp_runtime::{Permill, Perbill};
+pub use staking::StakerStatus;
+pub use im_online::sr25519::AuthorityId as ImOnlineId;
+pub use sp_authority_discovery::AuthorityId as AuthorityDiscoveryId;
 pub use frame_support::{
 	StorageValue, construct_runtime, parameter_types,
-	traits::Randomness,
+	traits::{Randomness, EnsureOrigin, Get, Imbalance, OnUnbalanced},
 	weights::Weight,
 };
 
@@ -72,6 +78,50 @@ pub type DigestItem = generic::DigestItem<Hash>;
 /// Used for the module moonbeam in `./moonbeam.rs`
 mod moonbeam;
 
+pub use moonbeam::{Candle, PositionValue, payment::ChargeFee, runtime_api::DexApi};
+
+/// Used for the module dex bridge in `./dex_bridge.rs`
+mod dex_bridge;
+
+/// Used for the module oracle in `./oracle.rs`
+mod oracle;
+
+pub use oracle::sr25519::AuthorityId as OracleId;
+
+/// Used for the module evm in `./evm.rs`
+mod evm;
+
+pub use evm::{
+	Call as EvmCall, Event as EvmEvent, ExecutionInfo, ExitStatus, Log as EvmLog, runtime_api::EvmApi,
+	Transaction as EvmTransaction,
+};
+
+/// Used for the module ethereum in `./ethereum.rs`
+mod ethereum;
+
+pub use ethereum::{EthereumBlock, Receipt as EthereumReceipt, runtime_api::EthereumApi};
+
+/// Used for the module rewards in `./rewards.rs`
+mod rewards;
+
+pub use rewards::AuthorStats;
+
+/// Used for the module equivocation in `./equivocation.rs`
+mod equivocation;
+
+pub use equivocation::{AuraEquivocation, GrandpaEquivocation, GrandpaVote, runtime_api::EquivocationApi};
+
+/// Used for the Moonbeam precompiles in `./precompiles.rs`
+mod precompiles;
+
+pub use precompiles::{DEX_PRECOMPILE, GLMR_PRECOMPILE, LIQUID_PRECOMPILE, STAKING_PRECOMPILE, TOKEN_PRECOMPILE};
+
+/// Used for the module session upgrade in `./session_upgrade.rs`
+mod session_upgrade;
+
+#[cfg(test)]
+mod tests;
+
 /// Opaque types. These are used by the CLI to instantiate machinery that don't need to know
 /// the specifics of the runtime. They can then be made to be agnostic over specific formats
 /// of data like extrinsics, allowing for them to continue syncing the network through upgrades
@@ -92,6 +142,9 @@ pub mod opaque {
 		pub struct SessionKeys {
 			pub aura: Aura,
 			pub grandpa: Grandpa,
+			pub im_online: ImOnline,
+			pub authority_discovery: AuthorityDiscovery,
+			pub oracle: Oracle,
 		}
 	}
 }
@@ -101,7 +154,7 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("moonbeam"),
 	impl_name: create_runtime_str!("moonbeam"),
 	authoring_version: 1,
-	spec_version: 1,
+	spec_version: 4,
 	impl_version: 1,
 	apis: RUNTIME_API_VERSIONS,
 };
@@ -178,6 +231,195 @@ impl grandpa::Trait for Runtime {
 	type Event = Event;
 }
 
+parameter_types! {
+	pub const UncleGenerations: BlockNumber = 0;
+}
+
+impl authorship::Trait for Runtime {
+	type FindAuthor = session::FindAccountFromAuthorIndex<Self, Aura>;
+	/// Aura has no uncles.
+	type UncleGenerations = UncleGenerations;
+	type FilterUncle = ();
+	/// Authors earn era points towards their staking rewards, and are paid
+	/// the block reward.
+	type EventHandler = (Staking, Rewards);
+}
+
+parameter_types! {
+	pub const SessionPeriod: BlockNumber = 10 * MINUTES;
+	pub const SessionOffset: BlockNumber = 0;
+	pub const DisabledValidatorsThreshold: Perbill = Perbill::from_percent(17);
+}
+
+impl session::Trait for Runtime {
+	type OnSessionEnding = session::historical::NoteHistoricalRoot<Self, Staking>;
+	type SessionHandler = <opaque::SessionKeys as OpaqueKeys>::KeyTypeIdProviders;
+	type ShouldEndSession = session::PeriodicSessions<SessionPeriod, SessionOffset>;
+	type Event = Event;
+	type Keys = opaque::SessionKeys;
+	/// Validators are known by their stash account.
+	type ValidatorId = AccountId;
+	type ValidatorIdOf = staking::StashOf<Self>;
+	type SelectInitialValidators = Staking;
+	type DisabledValidatorsThreshold = DisabledValidatorsThreshold;
+}
+
+impl session::historical::Trait for Runtime {
+	type FullIdentification = staking::Exposure<AccountId, Balance>;
+	type FullIdentificationOf = staking::ExposureOf<Runtime>;
+}
+
+/// The session keys before the heartbeat, authority discovery and oracle keys
+/// were added to them.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct OldSessionKeys {
+	pub aura: AuraId,
+	pub grandpa: fg_primitives::AuthorityId,
+}
+
+/// Gives the keys added to the session keys the Aura key, which is sr25519 like
+/// them, until the validator rotates its keys.
+pub struct UpgradeSessionKeys;
+
+impl Convert<OldSessionKeys, opaque::SessionKeys> for UpgradeSessionKeys {
+	fn convert(old: OldSessionKeys) -> opaque::SessionKeys {
+		let key: &sp_core::sr25519::Public = old.aura.as_ref();
+		opaque::SessionKeys {
+			im_online: key.clone().into(),
+			authority_discovery: key.clone().into(),
+			oracle: key.clone().into(),
+			aura: old.aura,
+			grandpa: old.grandpa,
+		}
+	}
+}
+
+/// The controllers of the validators and the validator candidates.
+pub struct SessionKeyOwners;
+
+impl Get<Vec<AccountId>> for SessionKeyOwners {
+	fn get() -> Vec<AccountId> {
+		let mut stashes = Session::validators();
+		stashes.extend(<staking::Validators<Runtime>>::enumerate().map(|(stash, _)| stash));
+		stashes.sort();
+		stashes.dedup();
+		stashes.into_iter().filter_map(Staking::bonded).collect()
+	}
+}
+
+/// Used for the module session upgrade in `./session_upgrade.rs`
+impl session_upgrade::Trait for Runtime {
+	type OldKeys = OldSessionKeys;
+	type UpgradeKeys = UpgradeSessionKeys;
+	type KeyOwners = SessionKeyOwners;
+}
+
+staking_reward_curve::build! {
+	const REWARD_CURVE: PiecewiseLinear<'static> = curve!(
+		min_inflation: 0_025_000,
+		max_inflation: 0_100_000,
+		ideal_stake: 0_500_000,
+		falloff: 0_050_000,
+		max_piece_count: 40,
+		test_precision: 0_005_000,
+	);
+}
+
+parameter_types! {
+	// an era is an hour
+	pub const SessionsPerEra: sp_staking::SessionIndex = 6;
+	// a week
+	pub const BondingDuration: staking::EraIndex = 24 * 7;
+	// two days, leaving the council time to cancel slashes
+	pub const SlashDeferDuration: staking::EraIndex = 24 * 2;
+	pub const RewardCurve: &'static PiecewiseLinear<'static> = &REWARD_CURVE;
+}
+
+/// Converts balances to the votes used in the validator election and back,
+/// scaled so that the total issuance fits in a `u64`.
+pub struct CurrencyToVoteHandler;
+
+impl CurrencyToVoteHandler {
+	fn factor() -> Balance {
+		(Balances::total_issuance() / u64::max_value() as Balance).max(1)
+	}
+}
+
+impl Convert<Balance, u64> for CurrencyToVoteHandler {
+	fn convert(x: Balance) -> u64 {
+		(x / Self::factor()) as u64
+	}
+}
+
+impl Convert<u128, Balance> for CurrencyToVoteHandler {
+	fn convert(x: u128) -> Balance {
+		x * Self::factor()
+	}
+}
+
+impl staking::Trait for Runtime {
+	type Currency = Balances;
+	type Time = Timestamp;
+	type CurrencyToVote = CurrencyToVoteHandler;
+	/// Era payouts above what the reward curve gives validators go to the treasury.
+	type RewardRemainder = Treasury;
+	type Event = Event;
+	type Slash = Treasury;
+	/// Rewards are minted, which is the inflation.
+	type Reward = ();
+	type SessionsPerEra = SessionsPerEra;
+	type BondingDuration = BondingDuration;
+	type SlashDeferDuration = SlashDeferDuration;
+	/// Three quarters of the council can cancel a deferred slash.
+	type SlashCancelOrigin = collective::EnsureProportionAtLeast<_3, _4, AccountId, CouncilCollective>;
+	type SessionInterface = Self;
+	type RewardCurve = RewardCurve;
+}
+
+impl offences::Trait for Runtime {
+	type Event = Event;
+	type IdentificationTuple = session::historical::IdentificationTuple<Self>;
+	/// Offenders are slashed by staking.
+	type OnOffenceHandler = Staking;
+}
+
+/// The current GRANDPA authority set.
+pub struct GrandpaSetId;
+impl Get<fg_primitives::SetId> for GrandpaSetId {
+	fn get() -> fg_primitives::SetId {
+		Grandpa::current_set_id()
+	}
+}
+
+parameter_types! {
+	pub const EquivocationSlash: Perbill = Perbill::from_percent(10);
+	/// As long as a slash can still reach the stake of the session's validators.
+	pub const ReportableSessions: sp_staking::SessionIndex = BondingDuration::get() * SessionsPerEra::get();
+}
+
+/// Used for the module equivocation in `./equivocation.rs`
+impl equivocation::Trait for Runtime {
+	type AuraId = AuraId;
+	type GrandpaId = fg_primitives::AuthorityId;
+	type GrandpaSetId = GrandpaSetId;
+	type ReportEquivocation = Offences;
+	type EquivocationSlash = EquivocationSlash;
+	type ReportableSessions = ReportableSessions;
+}
+
+impl im_online::Trait for Runtime {
+	type AuthorityId = ImOnlineId;
+	type Call = Call;
+	type Event = Event;
+	type SubmitTransaction = system::offchain::TransactionSubmitter<ImOnlineId, Runtime, UncheckedExtrinsic>;
+	/// Validators that send no heartbeat for a session are reported to staking.
+	type ReportUnresponsiveness = Offences;
+	type SessionDuration = SessionPeriod;
+}
+
+impl authority_discovery::Trait for Runtime {}
+
 impl indices::Trait for Runtime {
 	/// The type for recording indexing into the account enumeration. If this ever overflows, there
 	/// will be problems!
@@ -258,13 +500,32 @@ impl balances::Trait for Runtime {
 	type OnNewAccount = Indices;
 	/// The ubiquitous event type.
 	type Event = Event;
-	type DustRemoval = ();
+	type DustRemoval = Treasury;
 	type TransferPayment = ();
 	type ExistentialDeposit = ExistentialDeposit;
 	type TransferFee = TransferFee;
 	type CreationFee = CreationFee;
 }
 
+/// The percentage of transaction fees paid to the block author.
+const AUTHOR_FEE_PERCENT: u32 = 30;
+/// The percentage of transaction fees paid into the treasury.  The rest is burned.
+const TREASURY_FEE_PERCENT: u32 = 20;
+
+/// Splits transaction fees between the block author, the treasury and the burn.
+pub struct DealWithFees;
+impl OnUnbalanced<balances::NegativeImbalance<Runtime>> for DealWithFees {
+	fn on_unbalanced(fees: balances::NegativeImbalance<Runtime>) {
+		let (to_author, rest) = fees.ration(AUTHOR_FEE_PERCENT, 100 - AUTHOR_FEE_PERCENT);
+		let (to_treasury, _burned) = rest.ration(
+			TREASURY_FEE_PERCENT,
+			100 - AUTHOR_FEE_PERCENT - TREASURY_FEE_PERCENT,
+		);
+		Rewards::on_unbalanced(to_author);
+		Treasury::on_unbalanced(to_treasury);
+	}
+}
+
 parameter_types! {
 	pub const TransactionBaseFee: Balance = 0;
 	pub const TransactionByteFee: Balance = 1;
@@ -272,45 +533,230 @@ parameter_types! {
 
 impl transaction_payment::Trait for Runtime {
 	type Currency = balances::Module<Runtime>;
-	type OnTransactionPayment = ();
+	type OnTransactionPayment = DealWithFees;
 	type TransactionBaseFee = TransactionBaseFee;
 	type TransactionByteFee = TransactionByteFee;
 	type WeightToFee = ConvertInto;
 	type FeeMultiplierUpdate = ();
 }
 
+#[cfg(not(feature = "no-sudo"))]
 impl sudo::Trait for Runtime {
 	type Event = Event;
 	type Proposal = Call;
 }
 
+type CouncilCollective = collective::Instance1;
+impl collective::Trait<CouncilCollective> for Runtime {
+	type Origin = Origin;
+	type Proposal = Call;
+	type Event = Event;
+}
+
+parameter_types! {
+	pub const LaunchPeriod: BlockNumber = 7 * DAYS;
+	pub const VotingPeriod: BlockNumber = 7 * DAYS;
+	pub const EmergencyVotingPeriod: BlockNumber = 3 * HOURS;
+	pub const MinimumDeposit: Balance = 100 * DOLLARS;
+	pub const EnactmentPeriod: BlockNumber = 8 * DAYS;
+	pub const CooloffPeriod: BlockNumber = 7 * DAYS;
+	pub const PreimageByteDeposit: Balance = 1 * CENTS;
+}
+
+impl democracy::Trait for Runtime {
+	type Proposal = Call;
+	type Event = Event;
+	type Currency = Balances;
+	type EnactmentPeriod = EnactmentPeriod;
+	type LaunchPeriod = LaunchPeriod;
+	type VotingPeriod = VotingPeriod;
+	type EmergencyVotingPeriod = EmergencyVotingPeriod;
+	type MinimumDeposit = MinimumDeposit;
+	/// A straight majority of the council can decide what their next motion is.
+	type ExternalOrigin = collective::EnsureProportionAtLeast<_1, _2, AccountId, CouncilCollective>;
+	/// A super-majority can have the next scheduled referendum be a straight majority-carries vote.
+	type ExternalMajorityOrigin = collective::EnsureProportionAtLeast<_3, _4, AccountId, CouncilCollective>;
+	/// A unanimous council can have the next scheduled referendum be a straight default-carries
+	/// (NTB) vote.
+	type ExternalDefaultOrigin = collective::EnsureProportionAtLeast<_1, _1, AccountId, CouncilCollective>;
+	/// Three quarters of the council can have an external majority or default vote tabled
+	/// immediately and with a shorter voting and enactment period.
+	type FastTrackOrigin = collective::EnsureProportionAtLeast<_3, _4, AccountId, CouncilCollective>;
+	/// Two thirds of the council can cancel a proposal which has been passed.
+	type CancellationOrigin = collective::EnsureProportionAtLeast<_2, _3, AccountId, CouncilCollective>;
+	/// Any single council member may veto a coming council proposal, however they can only do
+	/// it once and it lasts only for the cooloff period.
+	type VetoOrigin = collective::EnsureMember<AccountId, CouncilCollective>;
+	type CooloffPeriod = CooloffPeriod;
+	type PreimageByteDeposit = PreimageByteDeposit;
+}
+
+/// Either root, which is what passed referenda dispatch as, or a majority of the council.
+pub struct EnsureRootOrHalfCouncil;
+impl EnsureOrigin<Origin> for EnsureRootOrHalfCouncil {
+	type Success = ();
+
+	fn try_origin(o: Origin) -> Result<Self::Success, Origin> {
+		system::EnsureRoot::<AccountId>::try_origin(o).or_else(|o|
+			collective::EnsureProportionMoreThan::<_1, _2, AccountId, CouncilCollective>::try_origin(o)
+		)
+	}
+}
+
+parameter_types! {
+	pub const ProposalBond: Permill = Permill::from_percent(5);
+	pub const ProposalBondMinimum: Balance = 1 * DOLLARS;
+	pub const SpendPeriod: BlockNumber = 1 * DAYS;
+	pub const Burn: Permill = Permill::from_percent(0);
+}
+
+impl treasury::Trait for Runtime {
+	type Currency = Balances;
+	type ApproveOrigin = EnsureRootOrHalfCouncil;
+	type RejectOrigin = EnsureRootOrHalfCouncil;
+	type Event = Event;
+	type ProposalRejection = ();
+	type ProposalBond = ProposalBond;
+	type ProposalBondMinimum = ProposalBondMinimum;
+	type SpendPeriod = SpendPeriod;
+	type Burn = Burn;
+}
+
+parameter_types! {
+	pub const MaxBatchOperations: u32 = 64;
+	pub const CandlePeriod: BlockNumber = 10 * MINUTES;
+	// a week of candles
+	pub const MaxCandles: u32 = 7 * DAYS / (10 * MINUTES);
+	pub const ReferralShare: Perbill = Perbill::from_percent(20);
+	pub const ProtocolFeeShare: Perbill = Perbill::from_percent(20);
+}
+
 /// Used for the module moonbeam in `./moonbeam.rs`
 impl moonbeam::Trait for Runtime {
 	type Event = Event;
+	type AdminOrigin = EnsureRootOrHalfCouncil;
+	type MaxBatchOperations = MaxBatchOperations;
+	type CandlePeriod = CandlePeriod;
+	type MaxCandles = MaxCandles;
+	type ReferralShare = ReferralShare;
+	type ProtocolFeeShare = ProtocolFeeShare;
+	/// The protocol fees are paid into the treasury.
+	type ProtocolFeeDestination = Treasury;
+}
+
+parameter_types! {
+	// a tenth of the contracts block gas limit
+	pub const MaxReplyGas: contracts::Gas = 1_000_000;
+}
+
+/// Used for the module dex bridge in `./dex_bridge.rs`
+impl dex_bridge::Trait for Runtime {
+	type Event = Event;
+	type MaxReplyGas = MaxReplyGas;
+}
+
+parameter_types! {
+	pub const OracleReportInterval: BlockNumber = 10;
+	pub const OracleMaxPriceAge: BlockNumber = 5 * MINUTES;
+}
+
+/// Used for the module oracle in `./oracle.rs`
+impl oracle::Trait for Runtime {
+	type Event = Event;
+	type Call = Call;
+	type AdminOrigin = EnsureRootOrHalfCouncil;
+	type SubmitTransaction = system::offchain::TransactionSubmitter<OracleId, Runtime, UncheckedExtrinsic>;
+	type AuthorityId = OracleId;
+	type ReportInterval = OracleReportInterval;
+	type MaxPriceAge = OracleMaxPriceAge;
+}
+
+parameter_types! {
+	pub const MinGasPrice: Balance = 1 * MILLICENTS;
+	pub const BlockGasLimit: u32 = 10_000_000;
+	pub const ChainId: u64 = 1281;
+}
+
+/// Used for the module evm in `./evm.rs`
+impl evm::Trait for Runtime {
+	type Event = Event;
+	type Currency = Balances;
+	type AddressMapping = evm::HashedAddressMapping;
+	type OnGasPayment = DealWithFees;
+	type OnExecuted = Ethereum;
+	type Precompiles = (evm::EthereumPrecompiles, precompiles::MoonbeamPrecompiles);
+	type MinGasPrice = MinGasPrice;
+	type BlockGasLimit = BlockGasLimit;
+	type ChainId = ChainId;
+}
+
+impl ethereum::Trait for Runtime {}
+
+/// Used for the module rewards in `./rewards.rs`
+impl rewards::Trait for Runtime {
+	type Event = Event;
+	type Currency = Balances;
+	type AdminOrigin = EnsureRootOrHalfCouncil;
 }
 
-construct_runtime!(
-	pub enum Runtime where
-		Block = Block,
-		NodeBlock = opaque::Block,
-		UncheckedExtrinsic = UncheckedExtrinsic
-	{
-		System: system::{Module, Call, Storage, Config, Event},
-		Timestamp: timestamp::{Module, Call, Storage, Inherent},
-		Aura: aura::{Module, Config<T>, Inherent(Timestamp)},
-		Grandpa: grandpa::{Module, Call, Storage, Config, Event},
-		Indices: indices,
-		Balances: balances::{default, Error},
-		TransactionPayment: transaction_payment::{Module, Storage},
-		Sudo: sudo,
-		// Used for the module Moonbeam in `./moonbeam.rs`
-		Moonbeam: moonbeam::{Module, Call, Storage, Event<T>},
-		//TemplateModule: template::{Module, Call, Storage, Event<T>},
-		RandomnessCollectiveFlip: randomness_collective_flip::{Module, Call, Storage},
-		// added contracts pallet
-		Contracts: contracts,
+/// Declares the runtime, with `Sudo` when the tokens for it are passed in.
+macro_rules! moonbeam_runtime {
+	($( $sudo:tt )*) => {
+		construct_runtime!(
+			pub enum Runtime where
+				Block = Block,
+				NodeBlock = opaque::Block,
+				UncheckedExtrinsic = UncheckedExtrinsic
+			{
+				System: system::{Module, Call, Storage, Config, Event},
+				Timestamp: timestamp::{Module, Call, Storage, Inherent},
+				Authorship: authorship::{Module, Call, Storage, Inherent},
+				Aura: aura::{Module, Config<T>, Inherent(Timestamp)},
+				Grandpa: grandpa::{Module, Call, Storage, Config, Event},
+				Indices: indices,
+				Balances: balances::{default, Error},
+				TransactionPayment: transaction_payment::{Module, Storage},
+				// Staking comes before session, whose genesis takes the validators
+				// staking elects.
+				Staking: staking::{Module, Call, Config<T>, Storage, Event<T>},
+				Session: session::{Module, Call, Storage, Event, Config<T>},
+				// Used for the module SessionUpgrade in `./session_upgrade.rs`
+				SessionUpgrade: session_upgrade::{Module, Storage, Config},
+				// Used for the module Rewards in `./rewards.rs`
+				Rewards: rewards::{Module, Call, Storage, Event<T>, Config<T>},
+				Offences: offences::{Module, Call, Storage, Event},
+				// Used for the module Equivocation in `./equivocation.rs`
+				Equivocation: equivocation::{Module, Call, Storage},
+				ImOnline: im_online::{Module, Call, Storage, Event<T>, ValidateUnsigned, Config<T>},
+				AuthorityDiscovery: authority_discovery::{Module, Call, Config},
+				$( $sudo )*
+				Democracy: democracy::{Module, Call, Storage, Config, Event<T>},
+				Council: collective::<Instance1>::{Module, Call, Storage, Origin<T>, Event<T>, Config<T>},
+				Treasury: treasury::{Module, Call, Storage, Event<T>},
+				// Used for the module Moonbeam in `./moonbeam.rs`
+				Moonbeam: moonbeam::{Module, Call, Storage, Event<T>, Config<T>},
+				// Used for the module Oracle in `./oracle.rs`
+				Oracle: oracle::{Module, Call, Storage, Event<T>, Config<T>, ValidateUnsigned},
+				//TemplateModule: template::{Module, Call, Storage, Event<T>},
+				RandomnessCollectiveFlip: randomness_collective_flip::{Module, Call, Storage},
+				// added contracts pallet
+				Contracts: contracts,
+				// Used for the module DexBridge in `./dex_bridge.rs`
+				DexBridge: dex_bridge::{Module, Call, Event<T>},
+				// Used for the module Evm in `./evm.rs`
+				Evm: evm::{Module, Call, Storage, Event, ValidateUnsigned},
+				Ethereum: ethereum::{Module, Storage},
+			}
+		);
 	}
-);
+}
+
+// The `no-sudo` feature builds the production runtime, in which there is no sudo key and every
+// privileged call goes through the council or a referendum.
+#[cfg(not(feature = "no-sudo"))]
+moonbeam_runtime!(Sudo: sudo,);
+#[cfg(feature = "no-sudo")]
+moonbeam_runtime!();
 
 /// The address format for describing accounts.
 pub type Address = <Indices as StaticLookup>::Source;
@@ -329,7 +775,7 @@ pub type SignedExtra = (
 	system::CheckEra<Runtime>,
 	system::CheckNonce<Runtime>,
 	system::CheckWeight<Runtime>,
-	transaction_payment::ChargeTransactionPayment<Runtime>
+	ChargeFee<Runtime>
 );
 /// Unchecked extrinsic type as expected by this runtime.
 pub type UncheckedExtrinsic = generic::UncheckedExtrinsic<Address, Call, Signature, SignedExtra>;
@@ -418,6 +864,85 @@ impl_runtime_apis! {
 		}
 	}
 
+	impl sp_authority_discovery::AuthorityDiscoveryApi<Block> for Runtime {
+		fn authorities() -> Vec<AuthorityDiscoveryId> {
+			AuthorityDiscovery::authorities()
+		}
+	}
+
+	impl moonbeam::runtime_api::DexApi<Block, AccountId, BlockNumber, Balance> for Runtime {
+		fn candles() -> Vec<Candle<BlockNumber, Balance>> {
+			Moonbeam::candles()
+		}
+
+		fn position_value(who: AccountId) -> Option<PositionValue<Balance>> {
+			Moonbeam::position_value(&who)
+		}
+	}
+
+	impl evm::runtime_api::EvmApi<Block, AccountId> for Runtime {
+		fn account_of(address: H160) -> AccountId {
+			<evm::HashedAddressMapping as evm::AddressMapping<AccountId>>::into_account_id(address)
+		}
+
+		fn address_of(who: AccountId) -> H160 {
+			<evm::HashedAddressMapping as evm::AddressMapping<AccountId>>::into_address(&who)
+		}
+
+		fn chain_id() -> u64 {
+			ChainId::get()
+		}
+
+		fn block_gas_limit() -> u32 {
+			BlockGasLimit::get()
+		}
+
+		fn balance_of(address: H160) -> U256 {
+			Evm::balance_of(address)
+		}
+
+		fn nonce_of(address: H160) -> U256 {
+			Evm::nonce_of(address)
+		}
+
+		fn call(
+			source: H160,
+			target: H160,
+			input: Vec<u8>,
+			value: U256,
+			gas_limit: u32,
+			gas_price: U256,
+		) -> ExecutionInfo<Vec<u8>> {
+			Evm::dry_run_call(source, target, input, value, gas_limit, gas_price)
+		}
+
+		fn create(
+			source: H160,
+			init: Vec<u8>,
+			value: U256,
+			gas_limit: u32,
+			gas_price: U256,
+		) -> ExecutionInfo<H160> {
+			Evm::dry_run_create(source, init, value, gas_limit, gas_price)
+		}
+	}
+
+	impl equivocation::runtime_api::EquivocationApi<Block> for Runtime {
+		fn prove_key_owner(key_type: KeyTypeId, key: Vec<u8>) -> Option<session::historical::Proof> {
+			<session::historical::Module<Runtime> as KeyOwnerProofSystem<_>>::prove((key_type, key))
+		}
+	}
+
+	impl ethereum::runtime_api::EthereumApi<Block> for Runtime {
+		fn current_block() -> Option<EthereumBlock> {
+			Ethereum::current_block()
+		}
+
+		fn current_receipts() -> Vec<EthereumReceipt> {
+			Ethereum::current_receipts()
+		}
+	}
+
 	// added for contracts pallet
 	impl contracts_rpc_runtime_api::ContractsApi<Block, AccountId, Balance> for Runtime {
         fn call(
@@ -11,36 +11,324 @@
 /// 12-24-19
 /// 
 
-use frame_support::{decl_module, decl_storage, decl_event, dispatch, ensure};
-use system::{ensure_signed, ensure_root};
-use sp_runtime::traits::{CheckedAdd, Saturating};
+use frame_support::{
+	decl_module, decl_storage, decl_event, dispatch, ensure,
+	traits::{Currency, ExistenceRequirement, Get, EnsureOrigin, Imbalance, OnUnbalanced, WithdrawReason},
+	weights::{ClassifyDispatch, DispatchClass, PaysFee, WeighData, Weight},
+};
+use codec::{Encode, Decode};
+use system::ensure_signed;
+use sp_runtime::{ModuleId, Perbill, Permill, traits::{AccountIdConversion, Bounded, CheckedAdd, Saturating, Zero, One}};
+use sp_std::prelude::*;
 use sp_std::convert::TryInto;
+use self::math::Rounding;
+
+mod math;
+mod migration;
+pub mod payment;
+pub mod runtime_api;
+#[cfg(test)]
+mod mock;
+#[cfg(test)]
+mod tests;
+
+/// The id of the dex account, which holds the native glmr deposited into the
+/// dex with `deposit_glmr`.  Glmr only leaves the dex as native glmr out of it.
+const MODULE_ID: ModuleId = ModuleId(*b"py/mbdex");
+
+/// Native glmr taken out of the dex.
+pub type NegativeImbalanceOf<T> =
+	<balances::Module<T> as Currency<<T as system::Trait>::AccountId>>::NegativeImbalance;
+
+/// Reserves held by the market and the liquid issued against them.
+#[derive(Encode, Decode, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct PoolInfo<Balance> {
+	/// The glmr pool balance.
+	pub glmr_reserve: Balance,
+	/// The token pool balance.
+	pub token_reserve: Balance,
+	/// The total liquid supply.
+	pub liquid_supply: Balance,
+}
+
+/// The result of a dex operation, as seen by the account that made it.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum Outcome<Balance> {
+	/// `trade_glmr_to_token` succeeded.
+	TokenPurchase { glmr_sold: Balance, tokens_bought: Balance },
+	/// `trade_token_to_glmr` succeeded.
+	GlmrPurchase { tokens_sold: Balance, glmr_bought: Balance },
+	/// `deposit_liquidity` succeeded.
+	DepositLiquidity { glmr_deposited: Balance, tokens_deposited: Balance, liquid_minted: Balance },
+	/// `withdraw_liquidity` succeeded.
+	WithdrawLiquidity { liquid_burned: Balance, glmr_received: Balance, tokens_received: Balance },
+}
+
+/// A single dex operation, as submitted in a `batch`.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum Operation<Balance> {
+	/// See `deposit_liquidity`.
+	DepositLiquidity { glmr_value: Balance, token_value: Balance },
+	/// See `withdraw_liquidity`.
+	WithdrawLiquidity { liquid_value: Balance },
+	/// See `trade_glmr_to_token`.
+	TradeGlmrToToken { glmr_value: Balance },
+	/// See `trade_token_to_glmr`.
+	TradeTokenToGlmr { token_value: Balance },
+}
+
+/// The weight of a `batch`: the given weight, that of a single dex call, for
+/// every operation in it.
+pub struct BatchWeight(pub Weight);
+
+impl<'a, Balance> WeighData<(&'a Vec<Operation<Balance>>,)> for BatchWeight {
+	fn weigh_data(&self, (operations,): (&'a Vec<Operation<Balance>>,)) -> Weight {
+		self.0.saturating_mul(operations.len() as Weight)
+	}
+}
+
+impl<T> ClassifyDispatch<T> for BatchWeight {
+	fn classify_dispatch(&self, _: T) -> DispatchClass {
+		DispatchClass::Normal
+	}
+}
+
+impl PaysFee for BatchWeight {
+	fn pays_fee(&self) -> bool {
+		true
+	}
+}
+
+/// Which dex operations the market currently accepts.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum MarketStatus {
+	/// Everything is allowed.
+	Open,
+	/// Trades and deposits are refused, but liquidity can still be withdrawn so
+	/// that providers can leave the market.
+	Delisted,
+	/// Every operation is refused.
+	Paused,
+}
+
+impl Default for MarketStatus {
+	fn default() -> Self {
+		MarketStatus::Open
+	}
+}
+
+/// An asset held in the dex ledgers that users can transfer and approve
+/// spenders of.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum Asset {
+	/// The token traded against glmr, in `TokenBalances`.
+	Token,
+	/// Liquidity pool shares, in `LiquidBalances`.
+	Liquid,
+}
+
+/// What a liquidity provider put into the pool, kept to value their position.
+///
+/// Each deposit adds the amounts deposited, which are the provider's share of
+/// the reserves at the time, and each withdrawal or transfer of liquid takes
+/// away the same fraction of the position as of the liquid.
+#[derive(Encode, Decode, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct LiquidityPosition<Balance> {
+	/// Glmr deposited.
+	pub glmr_entry: Balance,
+	/// Tokens deposited.
+	pub token_entry: Balance,
+	/// Sum of `sqrt(glmr * tokens)` over the deposits.  The pool invariant per
+	/// liquid only grows through fees, so comparing this with the same figure
+	/// for the amounts redeemable now tells fees apart from price movement.
+	pub root_k_entry: Balance,
+}
+
+/// The value of a liquidity provider's position at the current reserves.
+#[derive(Encode, Decode, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct PositionValue<Balance> {
+	/// Liquid held.
+	pub liquid: Balance,
+	/// Glmr the liquid can be withdrawn for.
+	pub glmr_redeemable: Balance,
+	/// Tokens the liquid can be withdrawn for.
+	pub token_redeemable: Balance,
+	/// The part of `glmr_redeemable` earned from trading fees.
+	pub glmr_fees: Balance,
+	/// The part of `token_redeemable` earned from trading fees.
+	pub token_fees: Balance,
+	/// How much less, in glmr at the current price, the position is worth before
+	/// fees than the deposited amounts would be had they been held instead.
+	pub impermanent_loss: Balance,
+}
+
+/// Price and volume of the market over one candle period.
+///
+/// Prices are `TokenPrice` as it stood at the end of each block in the period,
+/// so they move at block granularity.  Volumes add up both sides of every trade
+/// made in the period.
+#[derive(Encode, Decode, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Candle<BlockNumber, Balance> {
+	/// The first block of the period.
+	pub start: BlockNumber,
+	/// The price at the end of the previous period, or at the end of the first
+	/// block recorded if there was none.
+	pub open: Balance,
+	/// The highest price in the period.
+	pub high: Balance,
+	/// The lowest price in the period.
+	pub low: Balance,
+	/// The price at the end of the last block recorded.
+	pub close: Balance,
+	/// Glmr traded in the period, bought and sold.
+	pub glmr_volume: Balance,
+	/// Tokens traded in the period, bought and sold.
+	pub token_volume: Balance,
+}
+
+impl<BlockNumber, Balance: Copy + Ord + Saturating> Candle<BlockNumber, Balance> {
+	fn opening(start: BlockNumber, price: Balance, volume: (Balance, Balance)) -> Self {
+		Candle {
+			start,
+			open: price,
+			high: price,
+			low: price,
+			close: price,
+			glmr_volume: volume.0,
+			token_volume: volume.1,
+		}
+	}
+
+	fn record(&mut self, price: Balance, (glmr_volume, token_volume): (Balance, Balance)) {
+		self.high = self.high.max(price);
+		self.low = self.low.min(price);
+		self.close = price;
+		self.glmr_volume = self.glmr_volume.saturating_add(glmr_volume);
+		self.token_volume = self.token_volume.saturating_add(token_volume);
+	}
+}
 
 pub trait Trait: balances::Trait {
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+    /// The origin allowed to manage the market and set balances.
+    type AdminOrigin: EnsureOrigin<Self::Origin>;
+    /// The maximum number of operations in a single `batch`.
+    type MaxBatchOperations: Get<u32>;
+    /// The number of blocks covered by each candle.
+    type CandlePeriod: Get<Self::BlockNumber>;
+    /// The number of closed candles kept before the oldest is overwritten.
+    type MaxCandles: Get<u32>;
+    /// The share of the trading fee paid to the referrer of a trade.
+    type ReferralShare: Get<Perbill>;
+    /// The share of the trading fee collected for the protocol, out of what is
+    /// left after any referral reward.
+    type ProtocolFeeShare: Get<Perbill>;
+    /// Receives the protocol fees, in native glmr.
+    type ProtocolFeeDestination: OnUnbalanced<NegativeImbalanceOf<Self>>;
 }
 
 decl_storage! {
 	trait Store for Module<T: Trait> as Moonbeam {
 		/// The glmr balance of each user.
-		GlmrBalances get(glmr_balance_of): map T::AccountId => T::Balance;
-		/// The glmr pool balance
-		GlmrPoolBalance get(glmr_pool_balance): T::Balance;
+		GlmrBalances get(glmr_balance_of) config(): map T::AccountId => T::Balance;
 
 		/// The token balance of each user.
-		TokenBalances get(token_balance_of): map T::AccountId => T::Balance;
-		/// The token pool balance
-		TokenPoolBalance get(token_pool_balance): T::Balance;
+		TokenBalances get(token_balance_of) config(): map T::AccountId => T::Balance;
 
 		/// The liquid balance of each user.
 		LiquidBalances get(liquid_balance_of): map T::AccountId => T::Balance;
-		/// The total liquid supply.
-		TotalLiquidSupply get(total_liquid_supply): T::Balance;
+
+		/// The native glmr each user has deposited into the dex account and not
+		/// withdrawn, the most of their glmr balance they can withdraw.
+		GlmrDeposits get(glmr_deposit_of): map T::AccountId => T::Balance;
+		/// The native glmr in the dex account that users deposited.  The rest
+		/// backs the glmr reserve of the pool.
+		TotalGlmrDeposits get(total_glmr_deposits): T::Balance;
+
+		/// The tokens in existence, whether held by users, in the pool or owed as
+		/// fees.  Only `set_token_balance` and `set_token_issuance` change it,
+		/// and the counting of balances left by version 1 of the storage.
+		TokenIssuance get(token_issuance) build(|config: &GenesisConfig<T>| {
+			config.token_balances.iter()
+				.fold(Zero::zero(), |issuance: T::Balance, (_, value)| issuance.saturating_add(*value))
+		}): T::Balance;
+		/// Whether the tokens of each user are counted in `TokenIssuance`.
+		/// Version 1 of the storage did not track the issuance, so the balances
+		/// it left are counted when they next change, or by `count_token_balances`.
+		CountedTokenBalances get(token_balance_counted) build(|config: &GenesisConfig<T>| {
+			config.token_balances.iter().map(|(account, _)| (account.clone(), true)).collect::<Vec<_>>()
+		}): map T::AccountId => bool;
+
+		/// How much of an owner's asset a spender may still transfer, by owner
+		/// and then by asset and spender.
+		Allowances get(allowance): double_map T::AccountId, blake2_256((Asset, T::AccountId)) => T::Balance;
+
+		/// The pool reserves and total liquid supply.
+		Pool get(pool): PoolInfo<T::Balance>;
+
+		/// Which operations the market accepts.
+		Status get(market_status): MarketStatus;
+		/// The share of every trade kept by the pool.
+		TradingFee get(trading_fee): Permill = Permill::from_parts(3_000);
 
 		/// Current price of 1 token in glmr - replace with callable readonly function
 		TokenPrice get(token_price): T::Balance;
 		/// Current price of 1 glmr in tokens - replace with callable readonly function
 		GlmrPrice get(glmr_price): T::Balance;
+
+		/// The position of each liquidity provider.  Providers whose liquid
+		/// predates position tracking have none until their liquid next changes,
+		/// and are valued as if they entered at the reserves of that time.
+		Positions get(position_of): map T::AccountId => Option<LiquidityPosition<T::Balance>>;
+
+		/// Glmr and tokens earned by each referrer.
+		ReferralTotals get(referral_totals): map T::AccountId => (T::Balance, T::Balance);
+
+		/// Glmr and tokens traded so far in the current block, folded into the
+		/// current candle in `on_finalize`.
+		BlockVolume: (T::Balance, T::Balance);
+
+		/// Glmr and tokens collected as protocol fees so far in the current block,
+		/// paid to the protocol fee destination in `on_finalize`.  Token fees too
+		/// small to buy any glmr, and glmr the dex account does not hold, are
+		/// kept for a later block.
+		ProtocolFees get(protocol_fees): (T::Balance, T::Balance);
+
+		/// The candle for the period in progress.
+		CurrentCandle get(current_candle): Option<Candle<T::BlockNumber, T::Balance>>;
+		/// Ring buffer of closed candles, `MaxCandles` slots long.
+		Candles: map u32 => Option<Candle<T::BlockNumber, T::Balance>>;
+		/// The number of candles ever closed.  The next one goes in slot
+		/// `CandleCount % MaxCandles`.
+		CandleCount get(candle_count): u32;
+
+		/// Version of the storage layout, used to run migrations after a runtime upgrade.
+		StorageVersion get(storage_version) build(|_: &GenesisConfig<T>| migration::CURRENT_VERSION): u32;
+	}
+	add_extra_genesis {
+		// Deposits seeding the pool, as (provider, glmr, tokens).  Providers must
+		// already hold the deposited amounts in `glmr_balances` and `token_balances`.
+		config(liquidity): Vec<(T::AccountId, T::Balance, T::Balance)>;
+		build(|config: &GenesisConfig<T>| {
+			for (provider, glmr_value, token_value) in &config.liquidity {
+				<Module<T>>::add_liquidity(provider, *glmr_value, *token_value)
+					.expect("genesis liquidity deposits are backed by genesis balances; qed");
+			}
+			// the dex account holds native glmr for the glmr seeded into the pool,
+			// which pays out glmr bought from the pool.  Balances is built first.
+			let glmr_reserve = <Module<T>>::glmr_pool_balance();
+			if !glmr_reserve.is_zero() {
+				let _ = <balances::Module<T>>::deposit_creating(&<Module<T>>::account_id(), glmr_reserve);
+			}
+		});
 	}
 }
 
@@ -48,11 +336,22 @@ decl_module! {
 	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
 		// Initializing events
 		fn deposit_event() = default;
+
+		fn on_initialize() {
+			if Self::storage_version() < migration::CURRENT_VERSION {
+				migration::migrate::<T>();
+			}
+		}
+
+		fn on_finalize(now: T::BlockNumber) {
+			Self::pay_protocol_fees();
+			Self::update_candles(now);
+		}
 		
 		/// Convenience function to set glmr balance for an account
-		/// Only callable by root.
+		/// Only callable by the admin origin.
 		fn set_glmr_balance(origin, account: T::AccountId, value: T::Balance) -> dispatch::Result {
-			let _who = ensure_root(origin)?;
+			T::AdminOrigin::ensure_origin(origin)?;
 
 			<GlmrBalances<T>>::insert(account, value);
 
@@ -60,19 +359,89 @@ decl_module! {
 		}
 
 		/// Convenience function to set token balance for an account
-		/// Only callable by root.
+		/// Only callable by the admin origin.
 		fn set_token_balance(origin, account: T::AccountId, value: T::Balance) -> dispatch::Result {
-			let _who = ensure_root(origin)?;
+			T::AdminOrigin::ensure_origin(origin)?;
 
+			Self::count_token_balance(&account);
+			let previous = Self::token_balance_of(&account);
+			<TokenIssuance<T>>::mutate(|issuance| *issuance = issuance.saturating_sub(previous).saturating_add(value));
 			<TokenBalances<T>>::insert(account, value);
 
 			Ok(())
 		}
 
+		/// Sets the tokens in existence, to correct `TokenIssuance` should it
+		/// ever disagree with the balances.
+		/// Only callable by the admin origin.
+		fn set_token_issuance(origin, value: T::Balance) -> dispatch::Result {
+			T::AdminOrigin::ensure_origin(origin)?;
+
+			<TokenIssuance<T>>::put(value);
+
+			Ok(())
+		}
+
+		/// Counts the token balances version 1 of the storage left to `accounts`
+		/// into `TokenIssuance`, for those that have not changed since.
+		/// Only callable by the admin origin.
+		fn count_token_balances(origin, accounts: Vec<T::AccountId>) -> dispatch::Result {
+			T::AdminOrigin::ensure_origin(origin)?;
+			ensure!(accounts.len() <= T::MaxBatchOperations::get() as usize, "Too many accounts to count");
+
+			accounts.iter().for_each(Self::count_token_balance);
+
+			Ok(())
+		}
+
+		/// Moves `value` of the sender's native glmr into the dex account and
+		/// credits it to their glmr balance in the dex.  It can be withdrawn
+		/// again with `withdraw_glmr`.
+		fn deposit_glmr(origin, value: T::Balance) -> dispatch::Result {
+			let sender = ensure_signed(origin)?;
+
+			let imbalance = <balances::Module<T>>::withdraw(
+				&sender,
+				value,
+				WithdrawReason::Transfer.into(),
+				ExistenceRequirement::KeepAlive,
+			).map_err(|_| "Not enough native glmr to deposit")?;
+			<balances::Module<T>>::resolve_creating(&Self::account_id(), imbalance);
+			<GlmrBalances<T>>::mutate(&sender, |balance| *balance = balance.saturating_add(value));
+			<GlmrDeposits<T>>::mutate(&sender, |deposit| *deposit = deposit.saturating_add(value));
+			<TotalGlmrDeposits<T>>::mutate(|total| *total = total.saturating_add(value));
+
+			Ok(())
+		}
+
+		/// Pays `value` of the sender's glmr balance in the dex out to them as
+		/// native glmr.  Only glmr the sender deposited with `deposit_glmr` can
+		/// be withdrawn; glmr credited in the dex in any other way stays there.
+		fn withdraw_glmr(origin, value: T::Balance) -> dispatch::Result {
+			let sender = ensure_signed(origin)?;
+
+			let balance = Self::glmr_balance_of(&sender);
+			ensure!(balance >= value, "Not enough glmr to withdraw");
+			let deposit = Self::glmr_deposit_of(&sender);
+			ensure!(deposit >= value, "Not enough deposited glmr to withdraw");
+			let imbalance = <balances::Module<T>>::withdraw(
+				&Self::account_id(),
+				value,
+				WithdrawReason::Transfer.into(),
+				ExistenceRequirement::AllowDeath,
+			).map_err(|_| "Not enough native glmr in the dex account")?;
+			<GlmrBalances<T>>::insert(&sender, balance - value);
+			<GlmrDeposits<T>>::insert(&sender, deposit - value);
+			<TotalGlmrDeposits<T>>::mutate(|total| *total = total.saturating_sub(value));
+			<balances::Module<T>>::resolve_creating(&sender, imbalance);
+
+			Ok(())
+		}
+
 		/// Convenience function to transfer glmr balances between accounts
-		/// Only callable by root.
+		/// Only callable by the admin origin.
 		fn transfer_glmr(origin, from: T::AccountId, to: T::AccountId, amount: T::Balance) -> dispatch::Result {
-			let _who = ensure_root(origin)?;
+			T::AdminOrigin::ensure_origin(origin)?;
 
 			ensure!(<GlmrBalances<T>>::exists(&from), "Glmr from account does not exist");
 			let from_balance = Self::glmr_balance_of(&from);
@@ -87,9 +456,9 @@ decl_module! {
 		}
 
 		/// Convenience function to transfer glmr balances between accounts
-		/// Only callable by root.
+		/// Only callable by the admin origin.
 		fn transfer_token(origin, from: T::AccountId, to: T::AccountId, amount: T::Balance) -> dispatch::Result {
-			let _who = ensure_root(origin)?;
+			T::AdminOrigin::ensure_origin(origin)?;
 
 			ensure!(<TokenBalances<T>>::exists(&from), "Token from account does not exist");
 			let from_balance = Self::token_balance_of(&from);
@@ -97,6 +466,8 @@ decl_module! {
 
 			let to_balance = Self::token_balance_of(&to);
 
+			Self::count_token_balance(&from);
+			Self::count_token_balance(&to);
 			<TokenBalances<T>>::insert(&from, from_balance - amount);
 			<TokenBalances<T>>::insert(&to, to_balance.saturating_add(amount));
 
@@ -104,18 +475,15 @@ decl_module! {
 		}
 
 		/// Convenience function to transfer liquid balances between accounts
-		/// Only callable by root.
+		/// Only callable by the admin origin.
 		fn transfer_liquid(origin, from: T::AccountId, to: T::AccountId, amount: T::Balance) -> dispatch::Result {
-			let _who = ensure_root(origin)?;
+			T::AdminOrigin::ensure_origin(origin)?;
 
 			ensure!(<LiquidBalances<T>>::exists(&from), "Liquid from account does not exist");
 			let from_balance = Self::liquid_balance_of(&from);
 			ensure!(from_balance > amount, "Not enough liquid for transfer");
 
-			let to_balance = Self::liquid_balance_of(&to);
-
-			<LiquidBalances<T>>::insert(&from, from_balance - amount);
-			<LiquidBalances<T>>::insert(&to, to_balance.saturating_add(amount));
+			Self::move_liquid(&from, &to, amount, from_balance);
 
 			Ok(())
 		}
@@ -129,69 +497,7 @@ decl_module! {
 		/// by trading on the market.
 		fn deposit_liquidity(origin, glmr_value: T::Balance, token_value: T::Balance) -> dispatch::Result {
 			let sender = ensure_signed(origin)?;
-			let sender_glmr_balance = Self::glmr_balance_of(&sender);
-			ensure!(sender_glmr_balance >= glmr_value, "Not enough glmr to cover liquidity deposit");
-			let sender_token_balance = Self::token_balance_of(&sender);
-			ensure!(sender_token_balance >= token_value, "Not enough tokens to cover liquidity deposit");
-			
-			let total_liquid_supply = Self::total_liquid_supply();
-			let glmr_reserve = Self::glmr_pool_balance();
-			let token_reserve = Self::token_pool_balance();
-			let liquid_minted;
-
-			if total_liquid_supply > T::Balance::from(0) {
-				// add liquidity to pool
-				ensure!(glmr_reserve > T::Balance::from(0), "There is liquidity in this exchange but the glmr reserve is empty");
-				let token_amount = glmr_value * token_reserve / glmr_reserve + T::Balance::from(1);
-				ensure!(token_amount <= sender_token_balance, "You do not have enough tokens to complete the deposit");
-				liquid_minted = glmr_value * total_liquid_supply / glmr_reserve;
-
-				let glmr_newbal = match glmr_reserve.checked_add(&glmr_value) {
-					Some(val) => val,
-					None => return Err("Glmr reserve balance overflow"),
-				};
-
-				let token_newbal = match token_reserve.checked_add(&token_amount) {
-					Some(val) => val,
-					None => return Err("Token reserve balance overflow"),
-				};
-
-				let sender_liquid_balance = Self::liquid_balance_of(&sender);
-				let liquid_newbal = match sender_liquid_balance.checked_add(&liquid_minted) {
-					Some(val) => val,
-					None => return Err("User liquid balance overflow"),
-				};
-
-				let liquid_supply_newbal = match total_liquid_supply.checked_add(&liquid_minted) {
-					Some(val) => val,
-					None => return Err("Liquid supply balance overflow"),
-				};
-
-				<GlmrBalances<T>>::insert(&sender, sender_glmr_balance - glmr_value);
-				<GlmrPoolBalance<T>>::put(glmr_newbal);
-
-				<TokenBalances<T>>::insert(&sender, sender_token_balance - token_amount);
-				<TokenPoolBalance<T>>::put(token_newbal);
-				
-				<LiquidBalances<T>>::insert(&sender, liquid_newbal);
-				<TotalLiquidSupply<T>>::put(liquid_supply_newbal);
-				
-
-			} else {
-				// initialize liquidity pool
-				liquid_minted = glmr_value;
-
-				<GlmrPoolBalance<T>>::put(glmr_value);
-				<GlmrBalances<T>>::insert(&sender, sender_glmr_balance - glmr_value);
-
-				<TokenPoolBalance<T>>::put(token_value);
-				<TokenBalances<T>>::insert(&sender, sender_token_balance - token_value);
-				
-				<TotalLiquidSupply<T>>::put(liquid_minted);
-				<LiquidBalances<T>>::insert(&sender, liquid_minted);
-			}
-
-			Self::update_prices();
+			let (_, liquid_minted) = Self::add_liquidity(&sender, glmr_value, token_value)?;
 			Self::deposit_event(RawEvent::DepositLiquidity(sender, liquid_minted));
 
 			Ok(())
@@ -204,87 +510,101 @@ decl_module! {
 		fn withdraw_liquidity(origin, liquid_value: T::Balance) -> dispatch::Result {
 			let sender = ensure_signed(origin)?;
 
-			let total_liquid_supply = Self::total_liquid_supply();
-			ensure!(total_liquid_supply > T::Balance::from(0) && 
-				liquid_value <= total_liquid_supply,
-				"Not enough liquidity in pool to withdraw");
-			let glmr_reserve = Self::glmr_pool_balance();
-			let token_reserve = Self::token_pool_balance();
-			let glmr_amount = liquid_value * glmr_reserve / total_liquid_supply;
-			let token_amount = liquid_value * token_reserve / total_liquid_supply;
-			let sender_liquid_balance = Self::liquid_balance_of(&sender);
-			ensure!(liquid_value <= sender_liquid_balance, "Trying to withdraw more than owned liquidity");
-			let sender_glmr_balance = Self::glmr_balance_of(&sender);
-			ensure!(glmr_amount <= glmr_reserve, "Trying to withdraw more GLMR than is in the pool");
-			let sender_token_balance = Self::token_balance_of(&sender);
-			ensure!(token_amount <= token_reserve, "Trying to withdraw more Token than is in the pool");
-
-			let glmr_newbal = match sender_glmr_balance.checked_add(&glmr_amount) {
-				Some(val) => val,
-				None => return Err("Glmr user balance overflow"),
-			};
+			Self::remove_liquidity(&sender, liquid_value)?;
+			Self::deposit_event(RawEvent::WithdrawLiquidity(sender, liquid_value));
 
-			let token_newbal = match sender_token_balance.checked_add(&token_amount) {
-				Some(val) => val,
-				None => return Err("Token user balance overflow"),
-			};
-			
-			<LiquidBalances<T>>::insert(&sender, sender_liquid_balance - liquid_value);
-			<TotalLiquidSupply<T>>::put(total_liquid_supply - liquid_value);
-			
-			<GlmrBalances<T>>::insert(&sender, glmr_newbal);
-			<GlmrPoolBalance<T>>::put(glmr_reserve - glmr_amount);
-			
-			<TokenBalances<T>>::insert(&sender, token_newbal);
-			<TokenPoolBalance<T>>::put(token_reserve - token_amount);
-			
-			Self::update_prices();
+			Ok(())
+		}
+
+		/// Deposits liquidity with glmr alone.  The share of `glmr_value` that
+		/// balances the deposit is sold for tokens through the pool, and the rest is
+		/// deposited along with the tokens bought.  Dust that does not fit the
+		/// pool's ratio is left with the sender.  Fails without changing anything if
+		/// fewer than `min_liquid` liquid would be minted.
+		fn zap_glmr(origin, glmr_value: T::Balance, min_liquid: T::Balance) -> dispatch::Result {
+			let sender = ensure_signed(origin)?;
+
+			let (_, liquid_minted) = Self::rollback_on_error(&sender, || {
+				Self::add_glmr_liquidity(&sender, glmr_value, min_liquid)
+			})?;
+			Self::deposit_event(RawEvent::DepositLiquidity(sender, liquid_minted));
+
+			Ok(())
+		}
+
+		/// Deposits liquidity with tokens alone, see `zap_glmr`.
+		fn zap_token(origin, token_value: T::Balance, min_liquid: T::Balance) -> dispatch::Result {
+			let sender = ensure_signed(origin)?;
+
+			let (_, liquid_minted) = Self::rollback_on_error(&sender, || {
+				Self::add_token_liquidity(&sender, token_value, min_liquid)
+			})?;
+			Self::deposit_event(RawEvent::DepositLiquidity(sender, liquid_minted));
+
+			Ok(())
+		}
+
+		/// Withdraws liquidity like `withdraw_liquidity` and sells the tokens paid
+		/// out for glmr, so only glmr is received.  Fails without changing anything
+		/// if less than `min_glmr` would be received.
+		fn withdraw_liquidity_to_glmr(origin, liquid_value: T::Balance, min_glmr: T::Balance) -> dispatch::Result {
+			let sender = ensure_signed(origin)?;
+
+			Self::rollback_on_error(&sender, || {
+				Self::remove_glmr_liquidity(&sender, liquid_value, min_glmr)
+			})?;
 			Self::deposit_event(RawEvent::WithdrawLiquidity(sender, liquid_value));
 
 			Ok(())
 		}
 
-		/// users can call this function to execute a trade of glmr to tokens.
-		/// the number of tokens you get for a specified input number of glmr
-		/// is algorithmically determined by the x * y = k constant product
-		/// market making formula.  there is also a 0.3% trading fee which is
-		/// charged for every trade.  this fee is added to the liquidity pool
-		/// and accrues to liquidity token holders.
-		fn trade_glmr_to_token(origin, glmr_value: T::Balance) -> dispatch::Result {
+		/// Withdraws liquidity in tokens alone, see `withdraw_liquidity_to_glmr`.
+		fn withdraw_liquidity_to_token(origin, liquid_value: T::Balance, min_tokens: T::Balance) -> dispatch::Result {
 			let sender = ensure_signed(origin)?;
 
-			let glmr_reserve = Self::glmr_pool_balance();
-			let token_reserve = Self::token_pool_balance();
+			Self::rollback_on_error(&sender, || {
+				Self::remove_token_liquidity(&sender, liquid_value, min_tokens)
+			})?;
+			Self::deposit_event(RawEvent::WithdrawLiquidity(sender, liquid_value));
 
-			let tokens_bought = match Self::get_price(glmr_value, glmr_reserve, token_reserve) {
-				Some(val) => val,
-				None => return Err("Error caluculating number of tokens in trade"),
-			};
+			Ok(())
+		}
 
-			let sender_glmr_balance = Self::glmr_balance_of(&sender);
-			ensure!(sender_glmr_balance >= glmr_value, "Not enough glmr to execute trade");
-			let sender_token_balance = Self::token_balance_of(&sender);
-			ensure!(token_reserve >= tokens_bought, "Not enough tokens to execute trade");
+		/// Opens, delists or pauses the market.
+		/// Only callable by the admin origin.
+		fn set_market_status(origin, status: MarketStatus) -> dispatch::Result {
+			T::AdminOrigin::ensure_origin(origin)?;
 
-			let glmr_pool_newbal = match glmr_reserve.checked_add(&glmr_value) {
-				Some(val) => val,
-				None => return Err("GLMR pool balance overflow"),
-			};
+			Status::put(status);
+			Self::deposit_event(RawEvent::MarketStatusChanged(status));
 
-			let token_newbal = match sender_token_balance.checked_add(&tokens_bought) {
-				Some(val) => val,
-				None => return Err("User token balance overflow"),
-			};
+			Ok(())
+		}
+
+		/// Sets the share of every trade kept by the pool.
+		/// Only callable by the admin origin.
+		fn set_trading_fee(origin, fee: Permill) -> dispatch::Result {
+			T::AdminOrigin::ensure_origin(origin)?;
+			ensure!(fee < Permill::one(), "Trading fee must be less than the whole trade");
 
-			// tranfer glmr in
-			<GlmrBalances<T>>::insert(&sender, sender_glmr_balance - glmr_value);
-			<GlmrPoolBalance<T>>::put(glmr_pool_newbal);
+			TradingFee::put(fee);
+			Self::deposit_event(RawEvent::TradingFeeChanged(fee));
+
+			Ok(())
+		}
 
-			// transfer token out
-			<TokenBalances<T>>::insert(&sender, token_newbal);
-			<TokenPoolBalance<T>>::put(token_reserve - tokens_bought);
+		/// users can call this function to execute a trade of glmr to tokens.
+		/// the number of tokens you get for a specified input number of glmr
+		/// is algorithmically determined by the x * y = k constant product
+		/// market making formula.  there is also a trading fee, 0.3% unless
+		/// changed by `set_trading_fee`, which is charged for every trade.  this
+		/// fee is added to the liquidity pool and accrues to liquidity token
+		/// holders, less the `ReferralShare` of it that is paid to the
+		/// `referrer`, if one is given, and the `ProtocolFeeShare` of the rest.
+		fn trade_glmr_to_token(origin, glmr_value: T::Balance, referrer: Option<T::AccountId>) -> dispatch::Result {
+			let sender = ensure_signed(origin)?;
 
-			Self::update_prices();
+			let tokens_bought = Self::sell_glmr(&sender, glmr_value, referrer.as_ref())?;
 			Self::deposit_event(RawEvent::TokenPurchase(sender, tokens_bought));
 
 			Ok(())
@@ -292,45 +612,38 @@ decl_module! {
 
 		/// users can call this function to trade tokens for glmr.  the number of
 		/// glmr you get for a given amount of tokens is determined by the
-		/// x * y = k constant product market making formula. there is also a 0.3% 
-		/// trading fee which is charged for every trade.  this fee is added to the 
-		/// liquidity pool and accrues to liquidity token holders.
-		fn trade_token_to_glmr(origin, token_value: T::Balance) -> dispatch::Result {
+		/// x * y = k constant product market making formula. there is also a
+		/// trading fee, 0.3% unless changed by `set_trading_fee`, which is charged
+		/// for every trade.  this fee is added to the 
+		/// liquidity pool and accrues to liquidity token holders, less the
+		/// `ReferralShare` of it that is paid to the `referrer`, if one is given,
+		/// and the `ProtocolFeeShare` of the rest.
+		fn trade_token_to_glmr(origin, token_value: T::Balance, referrer: Option<T::AccountId>) -> dispatch::Result {
 			let sender = ensure_signed(origin)?;
 
-			let glmr_reserve = Self::glmr_pool_balance();
-			let token_reserve = Self::token_pool_balance();
-
-			let glmr_bought = match Self::get_price(token_value, token_reserve, glmr_reserve) {
-				Some(val) => val,
-				None => return Err("Error caluculating number of GLMR in trade"),
-			};
-
-			let sender_token_balance = Self::token_balance_of(&sender);
-			ensure!(sender_token_balance >= token_value, "Not enough tokens to execute trade");
-			let sender_glmr_balance = Self::glmr_balance_of(&sender);
-			ensure!(glmr_reserve >= glmr_bought, "Not enough glmr to execute trade");
-
-			let token_pool_newbal = match token_reserve.checked_add(&token_value) {
-				Some(val) => val,
-				None => return Err("Token pool balance overflow"),
-			};
+			let glmr_bought = Self::sell_tokens(&sender, token_value, referrer.as_ref())?;
+			Self::deposit_event(RawEvent::GlmrPurchase(sender, glmr_bought));
 
-			let glmr_newbal = match sender_glmr_balance.checked_add(&glmr_bought) {
-				Some(val) => val,
-				None => return Err("User GLMR balance overflow"),
-			};
+			Ok(())
+		}
 
-			// tranfer token in
-			<TokenBalances<T>>::insert(&sender, sender_token_balance - token_value);
-			<TokenPoolBalance<T>>::put(token_pool_newbal);
+		/// Executes a list of dex operations in order, all or nothing.  If any
+		/// operation fails the whole batch is rolled back and its error returned.
+		/// A single `Batch` event carrying every outcome is emitted on success.
+		/// Weighs as much as one dex call per operation.
+		#[weight = BatchWeight(10_000)]
+		fn batch(origin, operations: Vec<Operation<T::Balance>>) -> dispatch::Result {
+			let sender = ensure_signed(origin)?;
+			ensure!(!operations.is_empty(), "Batch contains no operations");
+			ensure!(operations.len() <= T::MaxBatchOperations::get() as usize, "Too many operations in batch");
 
-			// transfer glmr out
-			<GlmrBalances<T>>::insert(&sender, glmr_newbal);
-			<GlmrPoolBalance<T>>::put(glmr_reserve - glmr_bought);
+			let outcomes = Self::rollback_on_error(&sender, || {
+				operations.into_iter()
+					.map(|operation| Self::execute(&sender, operation))
+					.collect::<Result<Vec<_>, _>>()
+			})?;
 
-			Self::update_prices();
-			Self::deposit_event(RawEvent::GlmrPurchase(sender, glmr_bought));
+			Self::deposit_event(RawEvent::Batch(sender, outcomes));
 
 			Ok(())
 		}
@@ -347,47 +660,1046 @@ decl_event!(
 		GlmrPurchase(AccountId, Balance),
 		DepositLiquidity(AccountId, Balance),
 		WithdrawLiquidity(AccountId, Balance),
+		/// A batch of operations succeeded, with the outcome of each in order.
+		Batch(AccountId, Vec<Outcome<Balance>>),
+		/// The market status was changed.
+		MarketStatusChanged(MarketStatus),
+		/// The trading fee was changed.
+		TradingFeeChanged(Permill),
+		/// A referrer was paid their share of a trading fee, in glmr and tokens.
+		ReferralReward(AccountId, Balance, Balance),
+		/// Protocol fees paid to the protocol fee destination, in native glmr.
+		ProtocolFeesPaid(Balance),
+		/// Some of an asset was transferred: from, to, amount.
+		Transfer(Asset, AccountId, AccountId, Balance),
+		/// An owner let a spender transfer up to the amount of an asset: owner,
+		/// spender, amount.
+		Approval(Asset, AccountId, AccountId, Balance),
 	}
 );
 
+/// Everything a batch of operations by one account can write, so that a failed
+/// batch can be undone, see `Module::rollback_on_error`.  Items that were not
+/// stored are removed again rather than left at their default.  Anything new
+/// that the operations write must be kept here as well, which
+/// `failed_batch_leaves_storage_untouched` checks.
+struct Snapshot<T: Trait> {
+	who: T::AccountId,
+	glmr_balance: Option<T::Balance>,
+	token_balance: Option<T::Balance>,
+	liquid_balance: Option<T::Balance>,
+	position: Option<LiquidityPosition<T::Balance>>,
+	pool: Option<PoolInfo<T::Balance>>,
+	token_price: Option<T::Balance>,
+	glmr_price: Option<T::Balance>,
+	block_volume: Option<(T::Balance, T::Balance)>,
+	protocol_fees: Option<(T::Balance, T::Balance)>,
+}
+
+impl<T: Trait> Snapshot<T> {
+	fn take(who: &T::AccountId) -> Self {
+		Snapshot {
+			who: who.clone(),
+			glmr_balance: if <GlmrBalances<T>>::exists(who) { Some(<GlmrBalances<T>>::get(who)) } else { None },
+			token_balance: if <TokenBalances<T>>::exists(who) { Some(<TokenBalances<T>>::get(who)) } else { None },
+			liquid_balance: if <LiquidBalances<T>>::exists(who) { Some(<LiquidBalances<T>>::get(who)) } else { None },
+			position: <Positions<T>>::get(who),
+			pool: if <Pool<T>>::exists() { Some(<Pool<T>>::get()) } else { None },
+			token_price: if <TokenPrice<T>>::exists() { Some(<TokenPrice<T>>::get()) } else { None },
+			glmr_price: if <GlmrPrice<T>>::exists() { Some(<GlmrPrice<T>>::get()) } else { None },
+			block_volume: if <BlockVolume<T>>::exists() { Some(<BlockVolume<T>>::get()) } else { None },
+			protocol_fees: if <ProtocolFees<T>>::exists() { Some(<ProtocolFees<T>>::get()) } else { None },
+		}
+	}
+
+	fn restore(self) {
+		match self.glmr_balance {
+			Some(balance) => <GlmrBalances<T>>::insert(&self.who, balance),
+			None => <GlmrBalances<T>>::remove(&self.who),
+		}
+		match self.token_balance {
+			Some(balance) => <TokenBalances<T>>::insert(&self.who, balance),
+			None => <TokenBalances<T>>::remove(&self.who),
+		}
+		match self.liquid_balance {
+			Some(balance) => <LiquidBalances<T>>::insert(&self.who, balance),
+			None => <LiquidBalances<T>>::remove(&self.who),
+		}
+		match self.position {
+			Some(position) => <Positions<T>>::insert(&self.who, position),
+			None => <Positions<T>>::remove(&self.who),
+		}
+		match self.pool {
+			Some(pool) => <Pool<T>>::put(pool),
+			None => <Pool<T>>::kill(),
+		}
+		match self.token_price {
+			Some(price) => <TokenPrice<T>>::put(price),
+			None => <TokenPrice<T>>::kill(),
+		}
+		match self.glmr_price {
+			Some(price) => <GlmrPrice<T>>::put(price),
+			None => <GlmrPrice<T>>::kill(),
+		}
+		match self.block_volume {
+			Some(volume) => <BlockVolume<T>>::put(volume),
+			None => <BlockVolume<T>>::kill(),
+		}
+		match self.protocol_fees {
+			Some(fees) => <ProtocolFees<T>>::put(fees),
+			None => <ProtocolFees<T>>::kill(),
+		}
+	}
+}
+
+/// Everything the dex can write for some accounts, and some allowances, taken
+/// before a contract uses the dex through a precompile, so that what it did
+/// can be put back should the contract revert.  See `Module::checkpoint`.
+pub struct Checkpoint<T: Trait> {
+	snapshots: Vec<Snapshot<T>>,
+	allowances: Vec<(T::AccountId, (Asset, T::AccountId), T::Balance)>,
+}
+
+impl<T: Trait> Checkpoint<T> {
+	/// Puts back everything the checkpoint covers.
+	pub fn restore(self) {
+		for snapshot in self.snapshots {
+			snapshot.restore();
+		}
+		for (owner, key, allowance) in self.allowances {
+			if allowance.is_zero() {
+				<Allowances<T>>::remove(&owner, &key);
+			} else {
+				<Allowances<T>>::insert(&owner, &key, allowance);
+			}
+		}
+	}
+}
+
 impl<T: Trait> Module<T> {
-	fn get_price(amount: T::Balance, input_reserve: T::Balance, output_reserve: T::Balance) -> Option<T::Balance> {
-		if amount <= T::Balance::from(0) || input_reserve <= T::Balance::from(0) || output_reserve <= T::Balance::from(0) {
-			return None	
+	/// A checkpoint of what transfers and trades can write for `accounts`, and
+	/// of the `allowances` given by their owners to their spenders.
+	pub fn checkpoint(accounts: &[T::AccountId], allowances: &[(T::AccountId, (Asset, T::AccountId))]) -> Checkpoint<T> {
+		Checkpoint {
+			snapshots: accounts.iter().map(Snapshot::take).collect(),
+			allowances: allowances.iter()
+				.map(|(owner, key)| (owner.clone(), key.clone(), Self::allowance(owner, key)))
+				.collect(),
 		}
+	}
 
-		let net_amount = match TryInto::<u128>::try_into(amount) {
-			Ok(converted_val) => match converted_val.checked_mul(997) {
-				Some(result_val) => result_val,
-				None => return None,
-			},
-			Err(_e) => return None,
+
+	/// The account holding the native glmr deposited into the dex and backing
+	/// the glmr reserve of the pool.
+	pub fn account_id() -> T::AccountId {
+		MODULE_ID.into_account()
+	}
+
+	/// The native glmr of the dex account that users did not deposit, which
+	/// pays out glmr bought from the pool.
+	pub fn native_reserve() -> T::Balance {
+		<balances::Module<T>>::free_balance(&Self::account_id()).saturating_sub(Self::total_glmr_deposits())
+	}
+
+	/// Takes `value` native glmr out of the native reserve of the dex account,
+	/// failing if it does not hold that much.  Deposits are never touched.
+	fn withdraw_native(value: T::Balance) -> Result<NegativeImbalanceOf<T>, &'static str> {
+		ensure!(Self::native_reserve() >= value, "Not enough native glmr in the dex account");
+		<balances::Module<T>>::withdraw(
+			&Self::account_id(),
+			value,
+			WithdrawReason::Transfer.into(),
+			ExistenceRequirement::AllowDeath,
+		).map_err(|_| "Not enough native glmr in the dex account")
+	}
+
+	/// The glmr pool balance.
+	pub fn glmr_pool_balance() -> T::Balance {
+		Self::pool().glmr_reserve
+	}
+
+	/// The token pool balance.
+	pub fn token_pool_balance() -> T::Balance {
+		Self::pool().token_reserve
+	}
+
+	/// The total liquid supply.
+	pub fn total_liquid_supply() -> T::Balance {
+		Self::pool().liquid_supply
+	}
+
+	/// Moves `glmr_value` glmr and the matching amount of tokens from `sender` into
+	/// the pool and credits them with newly minted liquid.  Returns the tokens
+	/// deposited and the liquid minted.
+	fn add_liquidity(sender: &T::AccountId, glmr_value: T::Balance, token_value: T::Balance) -> Result<(T::Balance, T::Balance), &'static str> {
+		Self::ensure_open()?;
+		let sender_glmr_balance = Self::glmr_balance_of(sender);
+		ensure!(sender_glmr_balance >= glmr_value, "Not enough glmr to cover liquidity deposit");
+		let sender_token_balance = Self::token_balance_of(sender);
+		ensure!(sender_token_balance >= token_value, "Not enough tokens to cover liquidity deposit");
+		
+		let total_liquid_supply = Self::total_liquid_supply();
+		let glmr_reserve = Self::glmr_pool_balance();
+		let token_reserve = Self::token_pool_balance();
+		let token_amount;
+		let liquid_minted;
+		// a deposit into an empty pool starts the position over
+		let position = if total_liquid_supply > T::Balance::from(0) {
+			Self::current_position(sender)
+		} else {
+			LiquidityPosition::default()
+		};
+
+		if total_liquid_supply > T::Balance::from(0) {
+			// add liquidity to pool
+			ensure!(glmr_reserve > T::Balance::from(0), "There is liquidity in this exchange but the glmr reserve is empty");
+			// tokens paid in round up and liquid paid out rounds down, so the deposit
+			// can never dilute existing liquidity providers
+			token_amount = match Self::mul_div(glmr_value, token_reserve, glmr_reserve, Rounding::Up) {
+				Some(val) => val,
+				None => return Err("Token deposit amount overflow"),
+			};
+			ensure!(token_amount <= sender_token_balance, "You do not have enough tokens to complete the deposit");
+			liquid_minted = match Self::mul_div(glmr_value, total_liquid_supply, glmr_reserve, Rounding::Down) {
+				Some(val) => val,
+				None => return Err("Liquid minted amount overflow"),
+			};
+
+			let glmr_newbal = match glmr_reserve.checked_add(&glmr_value) {
+				Some(val) => val,
+				None => return Err("Glmr reserve balance overflow"),
+			};
+
+			let token_newbal = match token_reserve.checked_add(&token_amount) {
+				Some(val) => val,
+				None => return Err("Token reserve balance overflow"),
+			};
+
+			let sender_liquid_balance = Self::liquid_balance_of(sender);
+			let liquid_newbal = match sender_liquid_balance.checked_add(&liquid_minted) {
+				Some(val) => val,
+				None => return Err("User liquid balance overflow"),
+			};
+
+			let liquid_supply_newbal = match total_liquid_supply.checked_add(&liquid_minted) {
+				Some(val) => val,
+				None => return Err("Liquid supply balance overflow"),
+			};
+
+			<GlmrBalances<T>>::insert(sender, sender_glmr_balance - glmr_value);
+			Self::count_token_balance(sender);
+			<TokenBalances<T>>::insert(sender, sender_token_balance - token_amount);
+			<LiquidBalances<T>>::insert(sender, liquid_newbal);
+
+			<Pool<T>>::put(PoolInfo {
+				glmr_reserve: glmr_newbal,
+				token_reserve: token_newbal,
+				liquid_supply: liquid_supply_newbal,
+			});
+		} else {
+			// initialize liquidity pool
+			ensure!(glmr_value > T::Balance::from(0) && token_value > T::Balance::from(0),
+				"Initial liquidity deposit requires both glmr and tokens");
+			token_amount = token_value;
+			liquid_minted = glmr_value;
+
+			// the reserves may still hold dust left over from earlier deposits, so
+			// add to them rather than overwriting what is already in the pool
+			let glmr_newbal = match glmr_reserve.checked_add(&glmr_value) {
+				Some(val) => val,
+				None => return Err("Glmr reserve balance overflow"),
+			};
+
+			let token_newbal = match token_reserve.checked_add(&token_amount) {
+				Some(val) => val,
+				None => return Err("Token reserve balance overflow"),
+			};
+
+			<GlmrBalances<T>>::insert(sender, sender_glmr_balance - glmr_value);
+			Self::count_token_balance(sender);
+			<TokenBalances<T>>::insert(sender, sender_token_balance - token_amount);
+			<LiquidBalances<T>>::insert(sender, liquid_minted);
+
+			<Pool<T>>::put(PoolInfo {
+				glmr_reserve: glmr_newbal,
+				token_reserve: token_newbal,
+				liquid_supply: liquid_minted,
+			});
+		}
+
+		<Positions<T>>::insert(sender, LiquidityPosition {
+			glmr_entry: position.glmr_entry.saturating_add(glmr_value),
+			token_entry: position.token_entry.saturating_add(token_amount),
+			root_k_entry: position.root_k_entry.saturating_add(Self::root_product(glmr_value, token_amount).unwrap_or_else(Zero::zero)),
+		});
+
+		Self::update_prices();
+
+		Ok((token_amount, liquid_minted))
+	}
+
+	/// Burns `liquid_value` of `sender`'s liquid and pays out their share of both
+	/// reserves.  Returns the glmr and tokens paid out.
+	fn remove_liquidity(sender: &T::AccountId, liquid_value: T::Balance) -> Result<(T::Balance, T::Balance), &'static str> {
+		Self::ensure_not_paused()?;
+		let total_liquid_supply = Self::total_liquid_supply();
+		ensure!(total_liquid_supply > T::Balance::from(0) && 
+			liquid_value <= total_liquid_supply,
+			"Not enough liquidity in pool to withdraw");
+		let glmr_reserve = Self::glmr_pool_balance();
+		let token_reserve = Self::token_pool_balance();
+		let glmr_amount = match Self::mul_div(liquid_value, glmr_reserve, total_liquid_supply, Rounding::Down) {
+			Some(val) => val,
+			None => return Err("Glmr withdrawal amount overflow"),
+		};
+		let token_amount = match Self::mul_div(liquid_value, token_reserve, total_liquid_supply, Rounding::Down) {
+			Some(val) => val,
+			None => return Err("Token withdrawal amount overflow"),
+		};
+		let sender_liquid_balance = Self::liquid_balance_of(sender);
+		ensure!(liquid_value <= sender_liquid_balance, "Trying to withdraw more than owned liquidity");
+		let sender_glmr_balance = Self::glmr_balance_of(sender);
+		ensure!(glmr_amount <= glmr_reserve, "Trying to withdraw more GLMR than is in the pool");
+		let sender_token_balance = Self::token_balance_of(sender);
+		ensure!(token_amount <= token_reserve, "Trying to withdraw more Token than is in the pool");
+
+		let glmr_newbal = match sender_glmr_balance.checked_add(&glmr_amount) {
+			Some(val) => val,
+			None => return Err("Glmr user balance overflow"),
+		};
+
+		let token_newbal = match sender_token_balance.checked_add(&token_amount) {
+			Some(val) => val,
+			None => return Err("Token user balance overflow"),
+		};
+		let position = Self::current_position(sender);
+		
+		<LiquidBalances<T>>::insert(sender, sender_liquid_balance - liquid_value);
+		<GlmrBalances<T>>::insert(sender, glmr_newbal);
+		Self::count_token_balance(sender);
+		<TokenBalances<T>>::insert(sender, token_newbal);
+		Self::keep_position(sender, position, sender_liquid_balance - liquid_value, sender_liquid_balance);
+
+		<Pool<T>>::put(PoolInfo {
+			glmr_reserve: glmr_reserve - glmr_amount,
+			token_reserve: token_reserve - token_amount,
+			liquid_supply: total_liquid_supply - liquid_value,
+		});
+		
+		Self::update_prices();
+
+		Ok((glmr_amount, token_amount))
+	}
+
+	/// Sells the share of `glmr_value` that balances a deposit and deposits the
+	/// rest with the tokens bought.  Returns the glmr taken from `sender` and the
+	/// liquid minted.
+	fn add_glmr_liquidity(sender: &T::AccountId, glmr_value: T::Balance, min_liquid: T::Balance) -> Result<(T::Balance, T::Balance), &'static str> {
+		ensure!(Self::total_liquid_supply() > T::Balance::from(0), "Single sided deposits need a pool with liquidity");
+
+		let glmr_sold = match Self::get_swap_amount(glmr_value, Self::glmr_pool_balance()) {
+			Some(val) => val,
+			None => return Err("Error caluculating glmr to sell for deposit"),
+		};
+		let tokens_bought = Self::sell_glmr(sender, glmr_sold, None)?;
+
+		// the glmr left over may be a little more than the tokens bought can match
+		let glmr_deposit = match Self::mul_div(tokens_bought, Self::glmr_pool_balance(), Self::token_pool_balance(), Rounding::Down) {
+			Some(val) => val.min(glmr_value - glmr_sold),
+			None => return Err("Glmr deposit amount overflow"),
+		};
+		let (_, liquid_minted) = Self::add_liquidity(sender, glmr_deposit, tokens_bought)?;
+		ensure!(liquid_minted >= min_liquid, "Deposit mints less liquid than the minimum");
+
+		Ok((glmr_sold + glmr_deposit, liquid_minted))
+	}
+
+	/// Sells the share of `token_value` that balances a deposit and deposits the
+	/// rest with the glmr bought.  Returns the tokens taken from `sender` and the
+	/// liquid minted.
+	fn add_token_liquidity(sender: &T::AccountId, token_value: T::Balance, min_liquid: T::Balance) -> Result<(T::Balance, T::Balance), &'static str> {
+		ensure!(Self::total_liquid_supply() > T::Balance::from(0), "Single sided deposits need a pool with liquidity");
+
+		let tokens_sold = match Self::get_swap_amount(token_value, Self::token_pool_balance()) {
+			Some(val) => val,
+			None => return Err("Error caluculating tokens to sell for deposit"),
+		};
+		let glmr_bought = Self::sell_tokens(sender, tokens_sold, None)?;
+
+		// the tokens left over may be a little more than the glmr bought can match
+		let glmr_deposit = match Self::mul_div(token_value - tokens_sold, Self::glmr_pool_balance(), Self::token_pool_balance(), Rounding::Down) {
+			Some(val) => val.min(glmr_bought),
+			None => return Err("Glmr deposit amount overflow"),
+		};
+		let (tokens_deposited, liquid_minted) = Self::add_liquidity(sender, glmr_deposit, token_value - tokens_sold)?;
+		ensure!(liquid_minted >= min_liquid, "Deposit mints less liquid than the minimum");
+
+		Ok((tokens_sold + tokens_deposited, liquid_minted))
+	}
+
+	/// Withdraws `liquid_value` of `sender`'s liquid and sells the tokens paid
+	/// out for glmr.  Returns the glmr received in all.
+	fn remove_glmr_liquidity(sender: &T::AccountId, liquid_value: T::Balance, min_glmr: T::Balance) -> Result<T::Balance, &'static str> {
+		let (glmr_amount, token_amount) = Self::remove_liquidity(sender, liquid_value)?;
+		let glmr_bought = if token_amount.is_zero() {
+			token_amount
+		} else {
+			Self::sell_tokens(sender, token_amount, None)?
+		};
+
+		let glmr_received = glmr_amount.saturating_add(glmr_bought);
+		ensure!(glmr_received >= min_glmr, "Withdrawal pays out less than the minimum");
+
+		Ok(glmr_received)
+	}
+
+	/// Withdraws `liquid_value` of `sender`'s liquid and sells the glmr paid out
+	/// for tokens.  Returns the tokens received in all.
+	fn remove_token_liquidity(sender: &T::AccountId, liquid_value: T::Balance, min_tokens: T::Balance) -> Result<T::Balance, &'static str> {
+		let (glmr_amount, token_amount) = Self::remove_liquidity(sender, liquid_value)?;
+		let tokens_bought = if glmr_amount.is_zero() {
+			glmr_amount
+		} else {
+			Self::sell_glmr(sender, glmr_amount, None)?
+		};
+
+		let tokens_received = token_amount.saturating_add(tokens_bought);
+		ensure!(tokens_received >= min_tokens, "Withdrawal pays out less than the minimum");
+
+		Ok(tokens_received)
+	}
+
+	/// Trades `glmr_value` of `sender`'s glmr for tokens from the pool.  Returns
+	/// the number of tokens bought.
+	fn sell_glmr(sender: &T::AccountId, glmr_value: T::Balance, referrer: Option<&T::AccountId>) -> Result<T::Balance, &'static str> {
+		Self::ensure_open()?;
+		let glmr_reserve = Self::glmr_pool_balance();
+		let token_reserve = Self::token_pool_balance();
+
+		let tokens_bought = match Self::get_price(glmr_value, glmr_reserve, token_reserve) {
+			Some(val) => val,
+			None => return Err("Error caluculating number of tokens in trade"),
+		};
+		let (referral_reward, protocol_fee) = Self::fee_shares(sender, glmr_value, referrer)?;
+
+		let sender_glmr_balance = Self::glmr_balance_of(sender);
+		ensure!(sender_glmr_balance >= glmr_value, "Not enough glmr to execute trade");
+		let sender_token_balance = Self::token_balance_of(sender);
+		ensure!(token_reserve >= tokens_bought, "Not enough tokens to execute trade");
+
+		let glmr_pool_newbal = match glmr_reserve.checked_add(&(glmr_value - referral_reward - protocol_fee)) {
+			Some(val) => val,
+			None => return Err("GLMR pool balance overflow"),
+		};
+
+		let token_newbal = match sender_token_balance.checked_add(&tokens_bought) {
+			Some(val) => val,
+			None => return Err("User token balance overflow"),
+		};
+
+		// tranfer glmr in, transfer token out
+		<GlmrBalances<T>>::insert(sender, sender_glmr_balance - glmr_value);
+		Self::count_token_balance(sender);
+		<TokenBalances<T>>::insert(sender, token_newbal);
+		<Pool<T>>::mutate(|pool| {
+			pool.glmr_reserve = glmr_pool_newbal;
+			pool.token_reserve = token_reserve - tokens_bought;
+		});
+		if let Some(referrer) = referrer {
+			Self::pay_referral(referrer, referral_reward, Zero::zero());
+		}
+		Self::collect_protocol_fees(protocol_fee, Zero::zero());
+
+		Self::update_prices();
+		Self::record_volume(glmr_value, tokens_bought);
+
+		Ok(tokens_bought)
+	}
+
+	/// Trades `token_value` of `sender`'s tokens for glmr from the pool.  Returns
+	/// the amount of glmr bought.
+	fn sell_tokens(sender: &T::AccountId, token_value: T::Balance, referrer: Option<&T::AccountId>) -> Result<T::Balance, &'static str> {
+		Self::ensure_open()?;
+		let glmr_reserve = Self::glmr_pool_balance();
+		let token_reserve = Self::token_pool_balance();
+
+		let glmr_bought = match Self::get_price(token_value, token_reserve, glmr_reserve) {
+			Some(val) => val,
+			None => return Err("Error caluculating number of GLMR in trade"),
 		};
+		let (referral_reward, protocol_fee) = Self::fee_shares(sender, token_value, referrer)?;
+
+		let sender_token_balance = Self::token_balance_of(sender);
+		ensure!(sender_token_balance >= token_value, "Not enough tokens to execute trade");
+		let sender_glmr_balance = Self::glmr_balance_of(sender);
+		ensure!(glmr_reserve >= glmr_bought, "Not enough glmr to execute trade");
+
+		let token_pool_newbal = match token_reserve.checked_add(&(token_value - referral_reward - protocol_fee)) {
+			Some(val) => val,
+			None => return Err("Token pool balance overflow"),
+		};
+
+		let glmr_newbal = match sender_glmr_balance.checked_add(&glmr_bought) {
+			Some(val) => val,
+			None => return Err("User GLMR balance overflow"),
+		};
+
+		// tranfer token in, transfer glmr out
+		Self::count_token_balance(sender);
+		<TokenBalances<T>>::insert(sender, sender_token_balance - token_value);
+		<GlmrBalances<T>>::insert(sender, glmr_newbal);
+		<Pool<T>>::mutate(|pool| {
+			pool.token_reserve = token_pool_newbal;
+			pool.glmr_reserve = glmr_reserve - glmr_bought;
+		});
+		if let Some(referrer) = referrer {
+			Self::pay_referral(referrer, Zero::zero(), referral_reward);
+		}
+		Self::collect_protocol_fees(Zero::zero(), protocol_fee);
+
+		Self::update_prices();
+		Self::record_volume(glmr_bought, token_value);
+
+		Ok(glmr_bought)
+	}
+
+	/// Trades `glmr_value` of `sender`'s glmr for tokens, as `trade_glmr_to_token`
+	/// does without a referrer, unless fewer than `min_tokens` would be bought.
+	/// Returns the number of tokens bought.
+	pub fn swap_glmr_for_tokens(sender: &T::AccountId, glmr_value: T::Balance, min_tokens: T::Balance) -> Result<T::Balance, &'static str> {
+		let tokens_bought = Self::rollback_on_error(sender, || {
+			let tokens_bought = Self::sell_glmr(sender, glmr_value, None)?;
+			ensure!(tokens_bought >= min_tokens, "Fewer tokens bought than the minimum");
+			Ok(tokens_bought)
+		})?;
+		Self::deposit_event(RawEvent::TokenPurchase(sender.clone(), tokens_bought));
+
+		Ok(tokens_bought)
+	}
+
+	/// Trades `token_value` of `sender`'s tokens for glmr, as `trade_token_to_glmr`
+	/// does without a referrer, unless less than `min_glmr` would be bought.
+	/// Returns the amount of glmr bought.
+	pub fn swap_tokens_for_glmr(sender: &T::AccountId, token_value: T::Balance, min_glmr: T::Balance) -> Result<T::Balance, &'static str> {
+		let glmr_bought = Self::rollback_on_error(sender, || {
+			let glmr_bought = Self::sell_tokens(sender, token_value, None)?;
+			ensure!(glmr_bought >= min_glmr, "Less glmr bought than the minimum");
+			Ok(glmr_bought)
+		})?;
+		Self::deposit_event(RawEvent::GlmrPurchase(sender.clone(), glmr_bought));
+
+		Ok(glmr_bought)
+	}
+
+	/// Runs a single operation for `sender`, as a one operation `batch` does,
+	/// and hands its outcome to `then`.  Everything the operation wrote is put
+	/// back if either fails.
+	pub fn execute_then<R>(
+		sender: &T::AccountId,
+		operation: Operation<T::Balance>,
+		then: impl FnOnce(Outcome<T::Balance>) -> Result<R, &'static str>,
+	) -> Result<R, &'static str> {
+		Self::rollback_on_error(sender, || then(Self::execute(sender, operation)?))
+	}
+
+	/// The tokens `glmr_value` glmr would buy from the pool now.
+	pub fn quote_glmr_to_token(glmr_value: T::Balance) -> Option<T::Balance> {
+		Self::get_price(glmr_value, Self::glmr_pool_balance(), Self::token_pool_balance())
+	}
+
+	/// The glmr `token_value` tokens would buy from the pool now.
+	pub fn quote_token_to_glmr(token_value: T::Balance) -> Option<T::Balance> {
+		Self::get_price(token_value, Self::token_pool_balance(), Self::glmr_pool_balance())
+	}
+
+	/// `who`'s balance of `asset`.
+	pub fn asset_balance_of(asset: Asset, who: &T::AccountId) -> T::Balance {
+		match asset {
+			Asset::Token => Self::token_balance_of(who),
+			Asset::Liquid => Self::liquid_balance_of(who),
+		}
+	}
+
+	/// The amount of `asset` in existence.
+	pub fn asset_supply(asset: Asset) -> T::Balance {
+		match asset {
+			Asset::Token => Self::token_issuance(),
+			Asset::Liquid => Self::total_liquid_supply(),
+		}
+	}
+
+	/// Transfers `amount` of `from`'s `asset` to `to`.  Liquid carries its share
+	/// of `from`'s position along.
+	pub fn transfer_asset(asset: Asset, from: &T::AccountId, to: &T::AccountId, amount: T::Balance) -> Result<(), &'static str> {
+		let from_balance = Self::asset_balance_of(asset, from);
+		ensure!(from_balance >= amount, "Not enough balance for transfer");
+
+		if from != to && !amount.is_zero() {
+			match asset {
+				Asset::Token => {
+					Self::count_token_balance(from);
+					Self::count_token_balance(to);
+					<TokenBalances<T>>::insert(from, from_balance - amount);
+					<TokenBalances<T>>::mutate(to, |balance| *balance = balance.saturating_add(amount));
+				},
+				Asset::Liquid => Self::move_liquid(from, to, amount, from_balance),
+			}
+		}
+		Self::deposit_event(RawEvent::Transfer(asset, from.clone(), to.clone(), amount));
+
+		Ok(())
+	}
+
+	/// Lets `spender` transfer up to `amount` of `owner`'s `asset`, replacing
+	/// any earlier allowance.
+	pub fn approve(asset: Asset, owner: &T::AccountId, spender: &T::AccountId, amount: T::Balance) {
+		<Allowances<T>>::insert(owner, &(asset, spender.clone()), amount);
+		Self::deposit_event(RawEvent::Approval(asset, owner.clone(), spender.clone(), amount));
+	}
 
-		let numerator = match TryInto::<u128>::try_into(output_reserve) {
-			Ok(converted_val) => match converted_val.checked_mul(net_amount) {
-				Some(result_val) => result_val,
-				None => return None,
+	/// Transfers `amount` of `from`'s `asset` to `to` for `spender`, out of the
+	/// allowance `from` gave it.  An allowance of the largest balance is never
+	/// used up.
+	pub fn transfer_asset_from(
+		asset: Asset,
+		spender: &T::AccountId,
+		from: &T::AccountId,
+		to: &T::AccountId,
+		amount: T::Balance,
+	) -> Result<(), &'static str> {
+		let allowance = Self::allowance(from, &(asset, spender.clone()));
+		ensure!(allowance >= amount, "Transfer amount exceeds allowance");
+
+		Self::transfer_asset(asset, from, to, amount)?;
+		if allowance != T::Balance::max_value() {
+			<Allowances<T>>::insert(from, &(asset, spender.clone()), allowance - amount);
+		}
+
+		Ok(())
+	}
+
+	/// The tokens `who` has to sell through the pool to buy `fee` glmr, or an
+	/// error if they do not have them or it takes more than `max_tokens`.
+	pub fn quote_fee_in_tokens(who: &T::AccountId, fee: T::Balance, max_tokens: T::Balance) -> Result<T::Balance, &'static str> {
+		if fee.is_zero() {
+			return Ok(fee)
+		}
+		Self::ensure_open()?;
+
+		let tokens_sold = match Self::get_input_price(fee, Self::token_pool_balance(), Self::glmr_pool_balance()) {
+			Some(val) => val,
+			None => return Err("Error caluculating number of tokens to pay the fee"),
+		};
+		ensure!(tokens_sold <= max_tokens, "Fee costs more tokens than allowed");
+		ensure!(Self::token_balance_of(who) >= tokens_sold, "Not enough tokens to pay the fee");
+		ensure!(Self::native_reserve() >= fee, "Not enough native glmr in the dex account");
+
+		Ok(tokens_sold)
+	}
+
+	/// Sells just enough of `who`'s tokens to buy `fee` glmr from the pool.
+	/// Used to pay transaction fees in tokens, see `payment::ChargeFee`.  The
+	/// glmr bought leaves the dex as native glmr out of the dex account, which
+	/// is returned along with the tokens sold.
+	pub fn pay_fee_in_tokens(who: &T::AccountId, fee: T::Balance, max_tokens: T::Balance) -> Result<(T::Balance, NegativeImbalanceOf<T>), &'static str> {
+		let tokens_sold = Self::quote_fee_in_tokens(who, fee, max_tokens)?;
+		if fee.is_zero() {
+			return Ok((tokens_sold, NegativeImbalanceOf::<T>::zero()))
+		}
+
+		let token_pool_newbal = match Self::token_pool_balance().checked_add(&tokens_sold) {
+			Some(val) => val,
+			None => return Err("Token pool balance overflow"),
+		};
+
+		// tranfer token in, the glmr bought leaves the pool for native balances
+		let imbalance = Self::withdraw_native(fee)?;
+		Self::count_token_balance(who);
+		<TokenBalances<T>>::mutate(who, |balance| *balance -= tokens_sold);
+		<Pool<T>>::mutate(|pool| {
+			pool.token_reserve = token_pool_newbal;
+			pool.glmr_reserve -= fee;
+		});
+
+		Self::update_prices();
+		Self::record_volume(fee, tokens_sold);
+
+		Ok((tokens_sold, imbalance))
+	}
+
+	/// The parts of the trading fee on `amount` owed to `referrer`, if any, and
+	/// to the protocol.  Both are taken out of the amount sold, and together
+	/// they never exceed the fee.
+	fn fee_shares(sender: &T::AccountId, amount: T::Balance, referrer: Option<&T::AccountId>) -> Result<(T::Balance, T::Balance), &'static str> {
+		if let Some(referrer) = referrer {
+			ensure!(referrer != sender, "Traders can not refer themselves");
+		}
+
+		let fee = match Self::fee_on(amount) {
+			Some(val) => val,
+			None => return Err("Error caluculating trading fee"),
+		};
+
+		let referral_reward = match referrer {
+			Some(_) => T::ReferralShare::get() * fee,
+			None => Zero::zero(),
+		};
+		let protocol_fee = T::ProtocolFeeShare::get() * (fee - referral_reward);
+		Ok((referral_reward, protocol_fee))
+	}
+
+	/// Sets aside protocol fees to be paid out when the block ends.
+	fn collect_protocol_fees(glmr_fee: T::Balance, token_fee: T::Balance) {
+		<ProtocolFees<T>>::mutate(|(glmr_fees, token_fees)| {
+			*glmr_fees = glmr_fees.saturating_add(glmr_fee);
+			*token_fees = token_fees.saturating_add(token_fee);
+		});
+	}
+
+	/// Pays the protocol fees collected this block to the protocol fee
+	/// destination.  The token fees are sold through the pool, and the glmr
+	/// leaves the dex as native glmr out of the dex account.  Whatever can not
+	/// be paid, token fees too small to buy any glmr or glmr the dex account
+	/// does not hold, is kept for a later block.
+	fn pay_protocol_fees() {
+		let (glmr_fees, token_fees) = <ProtocolFees<T>>::take();
+
+		let (glmr_bought, tokens_kept) = match Self::get_price(token_fees, Self::token_pool_balance(), Self::glmr_pool_balance()) {
+			Some(glmr_bought) if !glmr_bought.is_zero() => {
+				<Pool<T>>::mutate(|pool| {
+					pool.token_reserve = pool.token_reserve.saturating_add(token_fees);
+					pool.glmr_reserve -= glmr_bought;
+				});
+				Self::update_prices();
+				Self::record_volume(glmr_bought, token_fees);
+				(glmr_bought, Zero::zero())
 			},
-			Err(_e) => return None,
+			_ => (Zero::zero(), token_fees),
+		};
+
+		let owed = glmr_fees.saturating_add(glmr_bought);
+		let paid = if owed.is_zero() {
+			owed
+		} else {
+			match Self::withdraw_native(owed) {
+				Ok(imbalance) => {
+					T::ProtocolFeeDestination::on_unbalanced(imbalance);
+					owed
+				},
+				Err(_) => Zero::zero(),
+			}
 		};
 
-		let denominator = match TryInto::<u128>::try_into(input_reserve) {
-			Ok(converted_val) => match converted_val.checked_mul(1000) {
-				Some(multiplied_val) => match multiplied_val.checked_add(net_amount) {
-					Some(result_val) => result_val,
-					None => return None,
-				}
-				None => return None,
+		let glmr_kept = owed - paid;
+		if !glmr_kept.is_zero() || !tokens_kept.is_zero() {
+			<ProtocolFees<T>>::put((glmr_kept, tokens_kept));
+		}
+		if !paid.is_zero() {
+			Self::deposit_event(RawEvent::ProtocolFeesPaid(paid));
+		}
+	}
+
+	/// Counts `who`'s tokens into `TokenIssuance` if they are not yet, which
+	/// has to be done before its token balance changes.
+	fn count_token_balance(who: &T::AccountId) {
+		if !Self::token_balance_counted(who) {
+			<CountedTokenBalances<T>>::insert(who, true);
+			let balance = Self::token_balance_of(who);
+			<TokenIssuance<T>>::mutate(|issuance| *issuance = issuance.saturating_add(balance));
+		}
+	}
+
+	/// Credits `referrer` with their share of a trading fee.
+	fn pay_referral(referrer: &T::AccountId, glmr_reward: T::Balance, token_reward: T::Balance) {
+		<GlmrBalances<T>>::mutate(referrer, |balance| *balance = balance.saturating_add(glmr_reward));
+		Self::count_token_balance(referrer);
+		<TokenBalances<T>>::mutate(referrer, |balance| *balance = balance.saturating_add(token_reward));
+		<ReferralTotals<T>>::mutate(referrer, |(glmr_total, token_total)| {
+			*glmr_total = glmr_total.saturating_add(glmr_reward);
+			*token_total = token_total.saturating_add(token_reward);
+		});
+		Self::deposit_event(RawEvent::ReferralReward(referrer.clone(), glmr_reward, token_reward));
+	}
+
+	/// Adds a trade to the volume of the current block.
+	fn record_volume(glmr_amount: T::Balance, token_amount: T::Balance) {
+		<BlockVolume<T>>::mutate(|(glmr_volume, token_volume)| {
+			*glmr_volume = glmr_volume.saturating_add(glmr_amount);
+			*token_volume = token_volume.saturating_add(token_amount);
+		});
+	}
+
+	/// Folds the block that is ending into the current candle, closing it first
+	/// if `now` is past its period.  Nothing is recorded until the market has
+	/// liquidity.
+	fn update_candles(now: T::BlockNumber) {
+		let volume = <BlockVolume<T>>::take();
+		if Self::total_liquid_supply().is_zero() {
+			return
+		}
+
+		let price = Self::token_price();
+		let period = T::CandlePeriod::get().max(One::one());
+		let start = now - now % period;
+
+		let candle = match <CurrentCandle<T>>::get() {
+			Some(mut candle) if candle.start == start => {
+				candle.record(price, volume);
+				candle
+			},
+			Some(closed) => {
+				Self::push_candle(closed);
+				let mut candle = Candle::opening(start, closed.close, volume);
+				candle.record(price, (Zero::zero(), Zero::zero()));
+				candle
 			},
-			Err(_e) => return None,
+			None => Candle::opening(start, price, volume),
 		};
 
-		let result = match numerator.checked_div(denominator) {
-			Some(val) => val,
-			None => return None,
+		<CurrentCandle<T>>::put(candle);
+	}
+
+	/// Stores a closed candle in the ring buffer, overwriting the oldest once it
+	/// is full.
+	fn push_candle(candle: Candle<T::BlockNumber, T::Balance>) {
+		let count = Self::candle_count();
+		let slot = count % T::MaxCandles::get().max(1);
+		<Candles<T>>::insert(slot, candle);
+		CandleCount::put(count.wrapping_add(1));
+	}
+
+	/// The closed candles still held, oldest first, followed by the candle for
+	/// the period in progress.
+	pub fn candles() -> Vec<Candle<T::BlockNumber, T::Balance>> {
+		let count = Self::candle_count();
+		let max_candles = T::MaxCandles::get().max(1);
+		let held = count.min(max_candles);
+
+		let mut candles: Vec<_> = (count - held..count)
+			.filter_map(|index| <Candles<T>>::get(index % max_candles))
+			.collect();
+		candles.extend(Self::current_candle());
+		candles
+	}
+
+	/// The glmr and tokens `liquid_value` liquid can be withdrawn for.
+	fn redeemable(liquid_value: T::Balance) -> Option<(T::Balance, T::Balance)> {
+		let pool = Self::pool();
+		if pool.liquid_supply.is_zero() {
+			return Some((Zero::zero(), Zero::zero()))
+		}
+
+		Some((
+			Self::mul_div(liquid_value, pool.glmr_reserve, pool.liquid_supply, Rounding::Down)?,
+			Self::mul_div(liquid_value, pool.token_reserve, pool.liquid_supply, Rounding::Down)?,
+		))
+	}
+
+	/// `who`'s recorded position, or one entered at the current reserves if their
+	/// liquid predates position tracking.
+	fn current_position(who: &T::AccountId) -> LiquidityPosition<T::Balance> {
+		<Positions<T>>::get(who).unwrap_or_else(|| {
+			let (glmr_entry, token_entry) = Self::redeemable(Self::liquid_balance_of(who)).unwrap_or_default();
+			LiquidityPosition {
+				glmr_entry,
+				token_entry,
+				root_k_entry: Self::root_product(glmr_entry, token_entry).unwrap_or_else(Zero::zero),
+			}
+		})
+	}
+
+	/// The fraction `numerator / denominator` of `position`.
+	fn scale_position(position: LiquidityPosition<T::Balance>, numerator: T::Balance, denominator: T::Balance) -> LiquidityPosition<T::Balance> {
+		let scale = |value| Self::mul_div(value, numerator, denominator, Rounding::Down).unwrap_or_else(Zero::zero);
+		LiquidityPosition {
+			glmr_entry: scale(position.glmr_entry),
+			token_entry: scale(position.token_entry),
+			root_k_entry: scale(position.root_k_entry),
+		}
+	}
+
+	/// Moves `amount` of `from`'s `from_balance` liquid to `to`, along with its
+	/// share of `from`'s position.
+	fn move_liquid(from: &T::AccountId, to: &T::AccountId, amount: T::Balance, from_balance: T::Balance) {
+		let to_balance = Self::liquid_balance_of(to);
+
+		// the position moves along with the liquid
+		let from_position = Self::current_position(from);
+		let to_position = Self::current_position(to);
+		let moved = Self::scale_position(from_position, amount, from_balance);
+
+		<LiquidBalances<T>>::insert(from, from_balance - amount);
+		<LiquidBalances<T>>::insert(to, to_balance.saturating_add(amount));
+
+		Self::keep_position(from, from_position, from_balance - amount, from_balance);
+		<Positions<T>>::insert(to, LiquidityPosition {
+			glmr_entry: to_position.glmr_entry.saturating_add(moved.glmr_entry),
+			token_entry: to_position.token_entry.saturating_add(moved.token_entry),
+			root_k_entry: to_position.root_k_entry.saturating_add(moved.root_k_entry),
+		});
+	}
+
+	/// Records what is left of `position` once `who`'s liquid has gone from
+	/// `liquid_before` to `liquid_kept`.
+	fn keep_position(who: &T::AccountId, position: LiquidityPosition<T::Balance>, liquid_kept: T::Balance, liquid_before: T::Balance) {
+		if liquid_kept.is_zero() {
+			<Positions<T>>::remove(who);
+		} else {
+			<Positions<T>>::insert(who, Self::scale_position(position, liquid_kept, liquid_before));
+		}
+	}
+
+	/// What `who`'s liquid is worth at the current reserves, or `None` if they
+	/// hold none.
+	pub fn position_value(who: &T::AccountId) -> Option<PositionValue<T::Balance>> {
+		let liquid = Self::liquid_balance_of(who);
+		if liquid.is_zero() {
+			return None
+		}
+
+		let (glmr_redeemable, token_redeemable) = Self::redeemable(liquid)?;
+		let position = Self::current_position(who);
+
+		// scaling the redeemable amounts down to the invariant that was deposited
+		// leaves what they would be without fees
+		let root_k_now = Self::root_product(glmr_redeemable, token_redeemable)?;
+		let root_k_entry = position.root_k_entry.min(root_k_now);
+		let (glmr_principal, token_principal) = if root_k_now.is_zero() {
+			(glmr_redeemable, token_redeemable)
+		} else {
+			(
+				Self::mul_div(glmr_redeemable, root_k_entry, root_k_now, Rounding::Up)?,
+				Self::mul_div(token_redeemable, root_k_entry, root_k_now, Rounding::Up)?,
+			)
 		};
 
+		let pool = Self::pool();
+		let in_glmr = |tokens| Self::mul_div(tokens, pool.glmr_reserve, pool.token_reserve, Rounding::Down);
+		let held_value = position.glmr_entry.saturating_add(in_glmr(position.token_entry)?);
+		let principal_value = glmr_principal.saturating_add(in_glmr(token_principal)?);
+
+		Some(PositionValue {
+			liquid,
+			glmr_redeemable,
+			token_redeemable,
+			glmr_fees: glmr_redeemable - glmr_principal,
+			token_fees: token_redeemable - token_principal,
+			impermanent_loss: held_value.saturating_sub(principal_value),
+		})
+	}
+
+	/// Fails unless the market accepts trades and deposits.
+	fn ensure_open() -> Result<(), &'static str> {
+		ensure!(Self::market_status() == MarketStatus::Open, "Market is not open");
+		Ok(())
+	}
+
+	/// Fails if the market is paused.
+	fn ensure_not_paused() -> Result<(), &'static str> {
+		ensure!(Self::market_status() != MarketStatus::Paused, "Market is paused");
+		Ok(())
+	}
+
+	/// Runs `f`, putting back everything it wrote if it fails.  There are no
+	/// storage transactions to fall back on, so `Snapshot` keeps whatever `f`
+	/// can write on behalf of `who`.
+	fn rollback_on_error<R>(who: &T::AccountId, f: impl FnOnce() -> Result<R, &'static str>) -> Result<R, &'static str> {
+		let snapshot = Snapshot::<T>::take(who);
+		let result = f();
+		if result.is_err() {
+			snapshot.restore();
+		}
+		result
+	}
+
+	/// Runs a single operation for `sender` and describes what it did.
+	fn execute(sender: &T::AccountId, operation: Operation<T::Balance>) -> Result<Outcome<T::Balance>, &'static str> {
+		Ok(match operation {
+			Operation::DepositLiquidity { glmr_value, token_value } => {
+				let (tokens_deposited, liquid_minted) = Self::add_liquidity(sender, glmr_value, token_value)?;
+				Outcome::DepositLiquidity { glmr_deposited: glmr_value, tokens_deposited, liquid_minted }
+			},
+			Operation::WithdrawLiquidity { liquid_value } => {
+				let (glmr_received, tokens_received) = Self::remove_liquidity(sender, liquid_value)?;
+				Outcome::WithdrawLiquidity { liquid_burned: liquid_value, glmr_received, tokens_received }
+			},
+			Operation::TradeGlmrToToken { glmr_value } => {
+				let tokens_bought = Self::sell_glmr(sender, glmr_value, None)?;
+				Outcome::TokenPurchase { glmr_sold: glmr_value, tokens_bought }
+			},
+			Operation::TradeTokenToGlmr { token_value } => {
+				let glmr_bought = Self::sell_tokens(sender, token_value, None)?;
+				Outcome::GlmrPurchase { tokens_sold: token_value, glmr_bought }
+			},
+		})
+	}
+
+	/// Number of output units bought with `amount` input units, after the trading fee.
+	fn get_price(amount: T::Balance, input_reserve: T::Balance, output_reserve: T::Balance) -> Option<T::Balance> {
+		let amount_out = math::get_amount_out(
+			TryInto::<u128>::try_into(amount).ok()?,
+			TryInto::<u128>::try_into(input_reserve).ok()?,
+			TryInto::<u128>::try_into(output_reserve).ok()?,
+			Self::trading_fee().deconstruct().into(),
+		)?;
+
+		amount_out.try_into().ok()
+	}
+
+	/// Number of input units that buy exactly `amount` output units, after the trading fee.
+	fn get_input_price(amount: T::Balance, input_reserve: T::Balance, output_reserve: T::Balance) -> Option<T::Balance> {
+		let amount_in = math::get_amount_in(
+			TryInto::<u128>::try_into(amount).ok()?,
+			TryInto::<u128>::try_into(input_reserve).ok()?,
+			TryInto::<u128>::try_into(output_reserve).ok()?,
+			Self::trading_fee().deconstruct().into(),
+		)?;
+
+		amount_in.try_into().ok()
+	}
+
+	/// The trading fee charged on selling `amount`, rounded down.
+	fn fee_on(amount: T::Balance) -> Option<T::Balance> {
+		let fee = math::mul_div(
+			TryInto::<u128>::try_into(amount).ok()?,
+			Self::trading_fee().deconstruct().into(),
+			math::FEE_DENOMINATOR,
+			Rounding::Down,
+		)?;
+
+		fee.try_into().ok()
+	}
+
+	/// Share of `amount` to sell before a single sided deposit, see `math::get_swap_amount`.
+	fn get_swap_amount(amount: T::Balance, input_reserve: T::Balance) -> Option<T::Balance> {
+		let swap_amount = math::get_swap_amount(
+			TryInto::<u128>::try_into(amount).ok()?,
+			TryInto::<u128>::try_into(input_reserve).ok()?,
+			Self::trading_fee().deconstruct().into(),
+		)?;
+
+		swap_amount.try_into().ok()
+	}
+
+	/// `sqrt(a * b)`, rounded down.
+	fn root_product(a: T::Balance, b: T::Balance) -> Option<T::Balance> {
+		let root = math::root_product(
+			TryInto::<u128>::try_into(a).ok()?,
+			TryInto::<u128>::try_into(b).ok()?,
+		);
+
+		root.try_into().ok()
+	}
+
+	/// Computes `a * b / c` on 256-bit intermediates, see `math::mul_div`.
+	fn mul_div(a: T::Balance, b: T::Balance, c: T::Balance, rounding: Rounding) -> Option<T::Balance> {
+		let result = math::mul_div(
+			TryInto::<u128>::try_into(a).ok()?,
+			TryInto::<u128>::try_into(b).ok()?,
+			TryInto::<u128>::try_into(c).ok()?,
+			rounding,
+		)?;
+
 		result.try_into().ok()
 	}
 
@@ -0,0 +1,124 @@
+//! Overflow-safe arithmetic for pool calculations.
+//!
+//! Intermediate products are computed on `U256` so that multiplying two `u128`
+//! balances can never overflow.  Every division states which way it rounds:
+//! amounts paid out of the pool round down and amounts paid into the pool round
+//! up, so rounding error always accrues to the pool and never to the caller.
+
+use sp_core::U256;
+
+/// Trading fees are the share of every trade kept by the pool, given in parts
+/// per `FEE_DENOMINATOR` as in `Permill`.
+pub const FEE_DENOMINATOR: u128 = 1_000_000;
+
+/// Direction in which an inexact division is rounded.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rounding {
+	/// Towards zero. Used for amounts leaving the pool.
+	Down,
+	/// Away from zero. Used for amounts entering the pool.
+	Up,
+}
+
+/// Computes `a * b / c` rounded in the given direction.
+///
+/// Returns `None` if `c` is zero or the result does not fit in a `u128`.
+pub fn mul_div(a: u128, b: u128, c: u128, rounding: Rounding) -> Option<u128> {
+	if c == 0 {
+		return None
+	}
+
+	let (quotient, remainder) = (U256::from(a) * U256::from(b)).div_mod(U256::from(c));
+	let quotient = match rounding {
+		Rounding::Up if !remainder.is_zero() => quotient + U256::one(),
+		_ => quotient,
+	};
+
+	to_u128(quotient)
+}
+
+/// Number of output units bought with `amount_in` input units under the
+/// x * y = k formula, after the trading `fee` is taken.  Rounded down.
+///
+/// Returns `None` if any of the amounts is zero, the fee is not below
+/// `FEE_DENOMINATOR` or the result does not fit in a `u128`.
+pub fn get_amount_out(amount_in: u128, input_reserve: u128, output_reserve: u128, fee: u128) -> Option<u128> {
+	if amount_in == 0 || input_reserve == 0 || output_reserve == 0 || fee >= FEE_DENOMINATOR {
+		return None
+	}
+
+	let net_amount = U256::from(amount_in) * U256::from(FEE_DENOMINATOR - fee);
+	let numerator = net_amount.checked_mul(U256::from(output_reserve))?;
+	let denominator = (U256::from(input_reserve) * U256::from(FEE_DENOMINATOR)).checked_add(net_amount)?;
+
+	to_u128(numerator / denominator)
+}
+
+/// Number of input units that must be sold to buy exactly `amount_out` output
+/// units under the x * y = k formula, including the trading `fee`.  Rounded up.
+///
+/// Returns `None` if any of the amounts is zero, the pool does not hold more
+/// than `amount_out`, the fee is not below `FEE_DENOMINATOR` or the result does
+/// not fit in a `u128`.
+pub fn get_amount_in(amount_out: u128, input_reserve: u128, output_reserve: u128, fee: u128) -> Option<u128> {
+	if amount_out == 0 || input_reserve == 0 || amount_out >= output_reserve || fee >= FEE_DENOMINATOR {
+		return None
+	}
+
+	let numerator = (U256::from(input_reserve) * U256::from(amount_out)).checked_mul(U256::from(FEE_DENOMINATOR))?;
+	let denominator = U256::from(output_reserve - amount_out) * U256::from(FEE_DENOMINATOR - fee);
+	let (quotient, remainder) = numerator.div_mod(denominator);
+
+	to_u128(if remainder.is_zero() { quotient } else { quotient + U256::one() })
+}
+
+/// Portion of `amount_in` to sell so that the rest, together with what the
+/// sale buys, can be deposited in the ratio the pool is left in.  Rounded down.
+///
+/// Solves `(amount_in - s) / (input_reserve + s) = amount_out(s) / (output_reserve - amount_out(s))`,
+/// which comes to `s = (sqrt(r * (r * m^2 + 4 * n * d * amount_in)) - r * m) / 2n`
+/// where `r` is `input_reserve`, `d` is `FEE_DENOMINATOR`, `n = d - fee` and
+/// `m = d + n`.
+///
+/// Returns `None` if `input_reserve` is zero, the fee is not below
+/// `FEE_DENOMINATOR` or the intermediates overflow.
+pub fn get_swap_amount(amount_in: u128, input_reserve: u128, fee: u128) -> Option<u128> {
+	if input_reserve == 0 || fee >= FEE_DENOMINATOR {
+		return None
+	}
+
+	let reserve = U256::from(input_reserve);
+	let n = U256::from(FEE_DENOMINATOR - fee);
+	let m = U256::from(FEE_DENOMINATOR) + n;
+
+	let inner = (reserve * m * m)
+		.checked_add(U256::from(4) * n * U256::from(FEE_DENOMINATOR) * U256::from(amount_in))?
+		.checked_mul(reserve)?;
+
+	to_u128((sqrt(inner) - reserve * m) / (U256::from(2) * n))
+}
+
+/// `sqrt(a * b)`, rounded down.  Never overflows, as the result is no larger
+/// than the larger of `a` and `b`.
+pub fn root_product(a: u128, b: u128) -> u128 {
+	sqrt(U256::from(a) * U256::from(b)).low_u128()
+}
+
+/// Integer square root, rounded down.
+fn sqrt(value: U256) -> U256 {
+	let mut x = value;
+	let mut y = (value >> 1) + (value & U256::one());
+	while y < x {
+		x = y;
+		y = (x + value / x) >> 1;
+	}
+	x
+}
+
+fn to_u128(value: U256) -> Option<u128> {
+	if value > U256::from(u128::max_value()) {
+		None
+	} else {
+		Some(value.low_u128())
+	}
+}
@@ -0,0 +1,74 @@
+//! Storage migrations for the moonbeam module.
+//!
+//! `StorageVersion` records the layout the stored data is in.  Chains built
+//! from genesis start at `CURRENT_VERSION`; chains that predate a layout change
+//! are brought forward one version at a time by `migrate`, which the module runs
+//! in the first block after an upgrade whose storage is behind.  To change
+//! the layout, bump `CURRENT_VERSION`, keep the old items in `deprecated` so
+//! they can still be read, and add the step that moves them.
+
+use frame_support::StorageValue;
+use sp_runtime::traits::Saturating;
+use super::{Trait, Pool, PoolInfo, ProtocolFees, StorageVersion, TokenIssuance};
+
+/// The storage layout this version of the module reads and writes.
+pub const CURRENT_VERSION: u32 = 2;
+
+/// Storage items that have been replaced, kept so migrations can read them.
+pub mod deprecated {
+	use frame_support::decl_storage;
+	use sp_std::marker::PhantomData;
+	use super::super::Trait;
+
+	pub struct Module<T: Trait>(PhantomData<T>);
+
+	decl_storage! {
+		trait Store for Module<T: Trait> as Moonbeam {
+			/// The glmr pool balance. Moved into `Pool` in version 1.
+			pub GlmrPoolBalance: T::Balance;
+			/// The token pool balance. Moved into `Pool` in version 1.
+			pub TokenPoolBalance: T::Balance;
+			/// The total liquid supply. Moved into `Pool` in version 1.
+			pub TotalLiquidSupply: T::Balance;
+		}
+	}
+}
+
+/// Brings storage from whatever version it is in up to `CURRENT_VERSION`.
+/// Each step reads and writes a fixed number of items, as `on_initialize` can
+/// not report the weight of more.
+pub fn migrate<T: Trait>() {
+	let version = StorageVersion::get();
+	if version >= CURRENT_VERSION {
+		return
+	}
+
+	if version < 1 {
+		migrate_to_v1::<T>();
+	}
+	if version < 2 {
+		migrate_to_v2::<T>();
+	}
+
+	StorageVersion::put(CURRENT_VERSION);
+}
+
+/// Version 1 keeps the pool reserves and liquid supply in a single `Pool` value
+/// instead of three separate ones.
+fn migrate_to_v1<T: Trait>() {
+	<Pool<T>>::put(PoolInfo {
+		glmr_reserve: <deprecated::GlmrPoolBalance<T>>::take(),
+		token_reserve: <deprecated::TokenPoolBalance<T>>::take(),
+		liquid_supply: <deprecated::TotalLiquidSupply<T>>::take(),
+	});
+}
+
+/// Version 2 keeps the token issuance in `TokenIssuance`: the tokens users
+/// hold, those in the pool and those owed as fees.  Token balances can not be
+/// enumerated, so the upgrade counts the pool and fees, and leaves the balances
+/// of users to be counted as they change, or by `count_token_balances`, with
+/// `CountedTokenBalances` recording which are.
+fn migrate_to_v2<T: Trait>() {
+	let (_, token_fees) = <ProtocolFees<T>>::get();
+	<TokenIssuance<T>>::put(<Pool<T>>::get().token_reserve.saturating_add(token_fees));
+}
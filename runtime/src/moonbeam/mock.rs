@@ -0,0 +1,137 @@
+//! Test runtime for the moonbeam module.
+
+use std::cell::RefCell;
+use frame_support::{
+	impl_outer_origin, parameter_types,
+	traits::{Currency, Get, OnUnbalanced},
+	weights::Weight,
+};
+use sp_core::H256;
+use sp_runtime::{
+	Perbill, testing::Header, traits::{BlakeTwo256, IdentityLookup, ConvertInto},
+};
+use super::{Module, Trait};
+
+impl_outer_origin! {
+	pub enum Origin for Test {}
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Test;
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: Weight = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::one();
+}
+
+impl system::Trait for Test {
+	type Origin = Origin;
+	type Call = ();
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = ();
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+}
+
+parameter_types! {
+	pub const ExistentialDeposit: u128 = 0;
+	pub const TransferFee: u128 = 0;
+	pub const CreationFee: u128 = 0;
+}
+
+impl balances::Trait for Test {
+	type Balance = u128;
+	type OnFreeBalanceZero = ();
+	type OnNewAccount = ();
+	type Event = ();
+	type DustRemoval = ();
+	type TransferPayment = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type TransferFee = TransferFee;
+	type CreationFee = CreationFee;
+}
+
+parameter_types! {
+	pub const TransactionBaseFee: u128 = 10;
+	pub const TransactionByteFee: u128 = 1;
+}
+
+/// The account transaction fees are paid to.
+pub const FEE_COLLECTOR: u64 = 98;
+/// The account protocol fees are paid to.
+pub const PROTOCOL_FEE_DESTINATION: u64 = 99;
+
+pub struct CollectFees;
+impl OnUnbalanced<balances::NegativeImbalance<Test>> for CollectFees {
+	fn on_unbalanced(fees: balances::NegativeImbalance<Test>) {
+		balances::Module::<Test>::resolve_creating(&FEE_COLLECTOR, fees);
+	}
+}
+
+pub struct CollectProtocolFees;
+impl OnUnbalanced<balances::NegativeImbalance<Test>> for CollectProtocolFees {
+	fn on_unbalanced(fees: balances::NegativeImbalance<Test>) {
+		balances::Module::<Test>::resolve_creating(&PROTOCOL_FEE_DESTINATION, fees);
+	}
+}
+
+impl transaction_payment::Trait for Test {
+	type Currency = balances::Module<Test>;
+	type OnTransactionPayment = CollectFees;
+	type TransactionBaseFee = TransactionBaseFee;
+	type TransactionByteFee = TransactionByteFee;
+	type WeightToFee = ConvertInto;
+	type FeeMultiplierUpdate = ();
+}
+
+parameter_types! {
+	pub const MaxBatchOperations: u32 = 4;
+	pub const CandlePeriod: u64 = 10;
+	pub const MaxCandles: u32 = 3;
+	pub const ReferralShare: Perbill = Perbill::from_percent(50);
+}
+
+thread_local! {
+	static PROTOCOL_FEE_SHARE: RefCell<Perbill> = RefCell::new(Perbill::zero());
+}
+
+/// No protocol fee is taken unless a test sets one with `set_protocol_fee_share`.
+pub struct ProtocolFeeShare;
+impl Get<Perbill> for ProtocolFeeShare {
+	fn get() -> Perbill {
+		PROTOCOL_FEE_SHARE.with(|share| *share.borrow())
+	}
+}
+
+pub fn set_protocol_fee_share(share: Perbill) {
+	PROTOCOL_FEE_SHARE.with(|v| *v.borrow_mut() = share);
+}
+
+impl Trait for Test {
+	type Event = ();
+	type AdminOrigin = system::EnsureRoot<u64>;
+	type MaxBatchOperations = MaxBatchOperations;
+	type CandlePeriod = CandlePeriod;
+	type MaxCandles = MaxCandles;
+	type ReferralShare = ReferralShare;
+	type ProtocolFeeShare = ProtocolFeeShare;
+	type ProtocolFeeDestination = CollectProtocolFees;
+}
+
+pub type Moonbeam = Module<Test>;
+
+/// Build test externalities with an empty market.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	system::GenesisConfig::default().build_storage::<Test>().unwrap().into()
+}
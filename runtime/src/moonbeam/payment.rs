@@ -0,0 +1,109 @@
+//! Paying transaction fees in tokens.
+
+use codec::{Encode, Decode};
+use frame_support::{traits::OnUnbalanced, weights::DispatchInfo};
+use sp_runtime::{
+	traits::{SignedExtension, SaturatedConversion},
+	transaction_validity::{
+		TransactionValidity, TransactionValidityError, TransactionPriority, InvalidTransaction,
+		ValidTransaction,
+	},
+};
+use transaction_payment::ChargeTransactionPayment;
+use super::{Trait, Module};
+
+/// Replacement for `transaction_payment::ChargeTransactionPayment` that lets
+/// accounts without glmr pay their fees in tokens.
+///
+/// Without a `max_token_fee` the fee and tip are charged in glmr exactly as
+/// `ChargeTransactionPayment` charges them.  With one, just enough of the
+/// sender's tokens are sold through the pool to buy the fee and tip in glmr,
+/// which is taken out of the native glmr backing the pool and handed to
+/// `OnTransactionPayment` like a fee paid in glmr.  The transaction is rejected
+/// if that would take more than `max_token_fee` tokens, or if the dex account
+/// does not hold the glmr beyond what users deposited.  Validation only checks
+/// the tokens can be sold; they are sold when the transaction is dispatched.
+#[derive(Encode, Decode, Clone, Eq, PartialEq)]
+pub struct ChargeFee<T: Trait + transaction_payment::Trait + Send + Sync> {
+	#[codec(compact)]
+	tip: T::Balance,
+	max_token_fee: Option<T::Balance>,
+}
+
+impl<T> ChargeFee<T> where
+	T: Trait + transaction_payment::Trait<Currency = balances::Module<T>> + Send + Sync,
+{
+	/// Pays `tip` on top of the fee, in tokens if `max_token_fee` is given.
+	pub fn new(tip: T::Balance, max_token_fee: Option<T::Balance>) -> Self {
+		ChargeFee { tip, max_token_fee }
+	}
+}
+
+impl<T> sp_std::fmt::Debug for ChargeFee<T> where
+	T: Trait + transaction_payment::Trait + Send + Sync,
+{
+	#[cfg(feature = "std")]
+	fn fmt(&self, f: &mut sp_std::fmt::Formatter) -> sp_std::fmt::Result {
+		write!(f, "ChargeFee<{:?}, {:?}>", self.tip, self.max_token_fee)
+	}
+
+	#[cfg(not(feature = "std"))]
+	fn fmt(&self, _: &mut sp_std::fmt::Formatter) -> sp_std::fmt::Result {
+		Ok(())
+	}
+}
+
+impl<T> SignedExtension for ChargeFee<T> where
+	T: Trait + transaction_payment::Trait<Currency = balances::Module<T>> + Send + Sync,
+{
+	type AccountId = T::AccountId;
+	type Call = <T as system::Trait>::Call;
+	type AdditionalSigned = ();
+	type DispatchInfo = DispatchInfo;
+	type Pre = ();
+
+	fn additional_signed(&self) -> Result<(), TransactionValidityError> { Ok(()) }
+
+	fn validate(
+		&self,
+		who: &Self::AccountId,
+		call: &Self::Call,
+		info: Self::DispatchInfo,
+		len: usize,
+	) -> TransactionValidity {
+		let max_token_fee = match self.max_token_fee {
+			Some(max_token_fee) => max_token_fee,
+			None => return ChargeTransactionPayment::<T>::from(self.tip).validate(who, call, info, len),
+		};
+
+		let fee = ChargeTransactionPayment::<T>::compute_fee(len as u32, info, self.tip);
+		if <Module<T>>::quote_fee_in_tokens(who, fee, max_token_fee).is_err() {
+			return InvalidTransaction::Payment.into()
+		}
+
+		let mut r = ValidTransaction::default();
+		// same priority as the fee would get if it were paid in glmr
+		r.priority = fee.saturated_into::<TransactionPriority>();
+		Ok(r)
+	}
+
+	fn pre_dispatch(
+		self,
+		who: &Self::AccountId,
+		call: &Self::Call,
+		info: Self::DispatchInfo,
+		len: usize,
+	) -> Result<(), TransactionValidityError> {
+		let max_token_fee = match self.max_token_fee {
+			Some(max_token_fee) => max_token_fee,
+			None => return ChargeTransactionPayment::<T>::from(self.tip).pre_dispatch(who, call, info, len),
+		};
+
+		let fee = ChargeTransactionPayment::<T>::compute_fee(len as u32, info, self.tip);
+		let (_, imbalance) = <Module<T>>::pay_fee_in_tokens(who, fee, max_token_fee)
+			.map_err(|_| InvalidTransaction::Payment)?;
+		<T as transaction_payment::Trait>::OnTransactionPayment::on_unbalanced(imbalance);
+
+		Ok(())
+	}
+}
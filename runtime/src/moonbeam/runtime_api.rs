@@ -0,0 +1,22 @@
+//! Runtime API for reading the market without an external indexer.
+
+use codec::Codec;
+use sp_std::prelude::*;
+use super::{Candle, PositionValue};
+
+sp_api::decl_runtime_apis! {
+	/// Read-only access to the dex.
+	pub trait DexApi<AccountId, BlockNumber, Balance> where
+		AccountId: Codec,
+		BlockNumber: Codec,
+		Balance: Codec,
+	{
+		/// The closed candles still held, oldest first, followed by the candle for
+		/// the period in progress.  Prices are the price of one token in glmr.
+		fn candles() -> Vec<Candle<BlockNumber, Balance>>;
+
+		/// What the account's liquid is worth now, the fees it has earned and its
+		/// impermanent loss, or `None` if the account holds no liquid.
+		fn position_value(who: AccountId) -> Option<PositionValue<Balance>>;
+	}
+}
@@ -0,0 +1,853 @@
+//! Tests for the moonbeam module.
+//!
+//! Most check one call or behaviour of the dex.  The constant product market
+//! maker is also tested with properties: random sequences of deposits,
+//! withdrawals and trades are run against the module and the market
+//! invariants are checked after every step.  Failing sequences are shrunk by
+//! proptest to a minimal reproduction.
+
+use super::*;
+use super::mock::*;
+use super::math::{self, Rounding};
+use super::payment::ChargeFee;
+use frame_support::{assert_ok, StorageMap, StorageValue, weights::{DispatchInfo, GetDispatchInfo}};
+use proptest::prelude::*;
+use sp_core::U256;
+use sp_runtime::traits::{OnFinalize, OnInitialize, SignedExtension};
+use transaction_payment::ChargeTransactionPayment;
+
+const ACCOUNTS: u64 = 4;
+const INITIAL_BALANCE: u128 = 1 << 100;
+/// The default trading fee, in parts per million.
+const FEE: u128 = 3_000;
+
+#[derive(Clone, Debug)]
+enum Op {
+	Deposit(u64, u128, u128),
+	/// Withdraw a per mille share of the account's liquid balance.
+	Withdraw(u64, u32),
+	/// Trade, optionally paying part of the fee to a referrer.
+	GlmrToToken(u64, u128, Option<u64>),
+	TokenToGlmr(u64, u128, Option<u64>),
+}
+
+fn account() -> impl Strategy<Value = u64> {
+	1..=ACCOUNTS
+}
+
+fn amount() -> impl Strategy<Value = u128> {
+	prop_oneof![
+		0..=1_000_000u128,
+		0..=INITIAL_BALANCE,
+		any::<u128>(),
+	]
+}
+
+fn op() -> impl Strategy<Value = Op> {
+	prop_oneof![
+		(account(), amount(), amount()).prop_map(|(who, glmr, token)| Op::Deposit(who, glmr, token)),
+		(account(), 0..=1000u32).prop_map(|(who, per_mille)| Op::Withdraw(who, per_mille)),
+		(account(), amount(), prop::option::of(account()))
+			.prop_map(|(who, glmr, referrer)| Op::GlmrToToken(who, glmr, referrer)),
+		(account(), amount(), prop::option::of(account()))
+			.prop_map(|(who, token, referrer)| Op::TokenToGlmr(who, token, referrer)),
+	]
+}
+
+fn apply(op: &Op) {
+	// rejected operations are fine, the invariants must hold either way
+	let _ = match *op {
+		Op::Deposit(who, glmr, token) =>
+			Moonbeam::deposit_liquidity(Origin::signed(who), glmr, token),
+		Op::Withdraw(who, per_mille) => {
+			let liquid = Moonbeam::liquid_balance_of(&who) * u128::from(per_mille) / 1000;
+			Moonbeam::withdraw_liquidity(Origin::signed(who), liquid)
+		},
+		Op::GlmrToToken(who, glmr, referrer) =>
+			Moonbeam::trade_glmr_to_token(Origin::signed(who), glmr, referrer),
+		Op::TokenToGlmr(who, token, referrer) =>
+			Moonbeam::trade_token_to_glmr(Origin::signed(who), token, referrer),
+	};
+}
+
+#[derive(Debug)]
+struct Market {
+	glmr_reserve: u128,
+	token_reserve: u128,
+	liquid_supply: u128,
+}
+
+impl Market {
+	fn current() -> Self {
+		Market {
+			glmr_reserve: Moonbeam::glmr_pool_balance(),
+			token_reserve: Moonbeam::token_pool_balance(),
+			liquid_supply: Moonbeam::total_liquid_supply(),
+		}
+	}
+
+	fn k(&self) -> U256 {
+		U256::from(self.glmr_reserve) * U256::from(self.token_reserve)
+	}
+
+	/// Whether each unit of liquid in `after` is backed by at least as much glmr
+	/// and tokens as it was in `self`.
+	fn share_value_kept_by(&self, after: &Market) -> bool {
+		if self.liquid_supply == 0 || after.liquid_supply == 0 {
+			return true
+		}
+
+		let kept = |before_reserve: u128, after_reserve: u128| {
+			U256::from(after_reserve) * U256::from(self.liquid_supply) >=
+				U256::from(before_reserve) * U256::from(after.liquid_supply)
+		};
+
+		kept(self.glmr_reserve, after.glmr_reserve) && kept(self.token_reserve, after.token_reserve)
+	}
+}
+
+fn endow_accounts() {
+	for who in 1..=ACCOUNTS {
+		<GlmrBalances<Test>>::insert(who, INITIAL_BALANCE);
+		<TokenBalances<Test>>::insert(who, INITIAL_BALANCE);
+	}
+}
+
+fn assert_supply_conserved() -> Result<(), TestCaseError> {
+	let total = INITIAL_BALANCE * u128::from(ACCOUNTS);
+
+	let glmr: u128 = (1..=ACCOUNTS).map(|who| Moonbeam::glmr_balance_of(&who)).sum();
+	prop_assert_eq!(glmr + Moonbeam::glmr_pool_balance(), total, "glmr was created or destroyed");
+
+	let tokens: u128 = (1..=ACCOUNTS).map(|who| Moonbeam::token_balance_of(&who)).sum();
+	prop_assert_eq!(tokens + Moonbeam::token_pool_balance(), total, "tokens were created or destroyed");
+
+	let liquid: u128 = (1..=ACCOUNTS).map(|who| Moonbeam::liquid_balance_of(&who)).sum();
+	prop_assert_eq!(liquid, Moonbeam::total_liquid_supply(), "liquid balances do not add up to the supply");
+
+	Ok(())
+}
+
+proptest! {
+	#[test]
+	fn market_invariants_hold(ops in prop::collection::vec(op(), 1..50)) {
+		new_test_ext().execute_with(|| -> Result<(), TestCaseError> {
+			endow_accounts();
+
+			for op in &ops {
+				let before = Market::current();
+				apply(op);
+				let after = Market::current();
+
+				assert_supply_conserved()?;
+
+				match op {
+					Op::GlmrToToken(..) | Op::TokenToGlmr(..) => {
+						prop_assert!(after.k() >= before.k(), "k decreased: {:?} -> {:?}", before, after);
+						prop_assert_eq!(after.liquid_supply, before.liquid_supply);
+					},
+					Op::Deposit(..) | Op::Withdraw(..) => {
+						prop_assert!(before.share_value_kept_by(&after),
+							"liquid share value decreased: {:?} -> {:?}", before, after);
+					},
+				}
+			}
+
+			Ok(())
+		})?;
+	}
+}
+
+#[test]
+fn mul_div_rounds_in_requested_direction() {
+	assert_eq!(math::mul_div(7, 3, 2, Rounding::Down), Some(10));
+	assert_eq!(math::mul_div(7, 3, 2, Rounding::Up), Some(11));
+	assert_eq!(math::mul_div(8, 3, 2, Rounding::Up), Some(12));
+	assert_eq!(math::mul_div(u128::max_value(), u128::max_value(), u128::max_value(), Rounding::Down),
+		Some(u128::max_value()));
+	assert_eq!(math::mul_div(u128::max_value(), 2, 1, Rounding::Down), None);
+	assert_eq!(math::mul_div(1, 1, 0, Rounding::Down), None);
+}
+
+#[test]
+fn deposit_with_large_reserves_does_not_overflow() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(1), 1 << 99, 1 << 99));
+		// glmr_value * token_reserve is 2^198 here, well past u128
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(2), 1 << 99, 0));
+
+		assert_eq!(Moonbeam::total_liquid_supply(), 1 << 100);
+		assert_eq!(Moonbeam::token_pool_balance(), 1 << 100);
+		assert_eq!(Moonbeam::token_balance_of(&2), INITIAL_BALANCE - (1 << 99));
+	});
+}
+
+#[test]
+fn genesis_seeds_tradable_market() {
+	let mut storage = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	GenesisConfig::<Test> {
+		glmr_balances: vec![(1, 1_000), (2, 1_000)],
+		token_balances: vec![(1, 5_000), (2, 5_000)],
+		liquidity: vec![(1, 400, 2_000)],
+	}.assimilate_storage(&mut storage).unwrap();
+
+	sp_io::TestExternalities::from(storage).execute_with(|| {
+		assert_eq!(Moonbeam::glmr_pool_balance(), 400);
+		assert_eq!(Moonbeam::token_pool_balance(), 2_000);
+		assert_eq!(Moonbeam::liquid_balance_of(&1), 400);
+		assert_eq!(Moonbeam::glmr_balance_of(&1), 600);
+		assert!(Moonbeam::token_price() > 0);
+		// the glmr seeded into the pool is held natively by the dex account
+		assert_eq!(balances::Module::<Test>::free_balance(&Moonbeam::account_id()), 400);
+		assert_eq!(Moonbeam::native_reserve(), 400);
+
+		assert_ok!(Moonbeam::trade_glmr_to_token(Origin::signed(2), 100, None));
+		assert!(Moonbeam::token_balance_of(&2) > 5_000);
+	});
+}
+
+#[test]
+fn migration_to_v1_moves_pool_values_into_pool_info() {
+	new_test_ext().execute_with(|| {
+		// a market as it was stored before versioning existed
+		<GlmrBalances<Test>>::insert(1, 600);
+		<TokenBalances<Test>>::insert(1, 3_000);
+		<LiquidBalances<Test>>::insert(1, 400);
+		<migration::deprecated::GlmrPoolBalance<Test>>::put(400);
+		<migration::deprecated::TokenPoolBalance<Test>>::put(2_000);
+		<migration::deprecated::TotalLiquidSupply<Test>>::put(400);
+		assert_eq!(Moonbeam::storage_version(), 0);
+
+		Moonbeam::on_initialize(1);
+
+		assert_eq!(Moonbeam::storage_version(), migration::CURRENT_VERSION);
+		assert_eq!(Moonbeam::pool(), PoolInfo { glmr_reserve: 400, token_reserve: 2_000, liquid_supply: 400 });
+		assert!(!<migration::deprecated::GlmrPoolBalance<Test>>::exists());
+		assert!(!<migration::deprecated::TokenPoolBalance<Test>>::exists());
+		assert!(!<migration::deprecated::TotalLiquidSupply<Test>>::exists());
+
+		// the migrated liquidity can be withdrawn in full
+		assert_ok!(Moonbeam::withdraw_liquidity(Origin::signed(1), 400));
+		assert_eq!(Moonbeam::glmr_balance_of(&1), 1_000);
+		assert_eq!(Moonbeam::token_balance_of(&1), 5_000);
+		assert_eq!(Moonbeam::pool(), PoolInfo::default());
+	});
+}
+
+#[test]
+fn migration_leaves_current_version_untouched() {
+	new_test_ext().execute_with(|| {
+		let pool = PoolInfo { glmr_reserve: 400, token_reserve: 2_000, liquid_supply: 400 };
+		StorageVersion::put(migration::CURRENT_VERSION);
+		<Pool<Test>>::put(pool);
+
+		Moonbeam::on_initialize(1);
+
+		assert_eq!(Moonbeam::storage_version(), migration::CURRENT_VERSION);
+		assert_eq!(Moonbeam::pool(), pool);
+	});
+}
+
+#[test]
+fn batch_applies_every_operation() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+
+		assert_ok!(Moonbeam::batch(Origin::signed(1), vec![
+			Operation::DepositLiquidity { glmr_value: 1_000, token_value: 4_000 },
+			Operation::TradeGlmrToToken { glmr_value: 100 },
+			Operation::WithdrawLiquidity { liquid_value: 500 },
+		]));
+
+		assert_eq!(Moonbeam::liquid_balance_of(&1), 500);
+		assert_eq!(Moonbeam::total_liquid_supply(), 500);
+	});
+}
+
+#[test]
+fn failed_batch_is_rolled_back() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(2), 1_000, 4_000));
+		let pool = Moonbeam::pool();
+		let token_price = Moonbeam::token_price();
+
+		assert_eq!(
+			Moonbeam::batch(Origin::signed(1), vec![
+				Operation::TradeGlmrToToken { glmr_value: 100 },
+				Operation::DepositLiquidity { glmr_value: 100, token_value: 400 },
+				Operation::WithdrawLiquidity { liquid_value: 1_000 },
+			]),
+			Err("Trying to withdraw more than owned liquidity"),
+		);
+
+		assert_eq!(Moonbeam::glmr_balance_of(&1), INITIAL_BALANCE);
+		assert_eq!(Moonbeam::token_balance_of(&1), INITIAL_BALANCE);
+		assert!(!<LiquidBalances<Test>>::exists(&1));
+		assert_eq!(Moonbeam::pool(), pool);
+		assert_eq!(Moonbeam::token_price(), token_price);
+		assert_eq!(<BlockVolume<Test>>::get(), (0, 0));
+	});
+}
+
+#[test]
+fn failed_batch_leaves_storage_untouched() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(2), 1_000, 4_000));
+		let root = sp_io::storage::root();
+
+		// every kind of operation writes before the last one fails
+		assert!(Moonbeam::batch(Origin::signed(1), vec![
+			Operation::TradeGlmrToToken { glmr_value: 100 },
+			Operation::TradeTokenToGlmr { token_value: 100 },
+			Operation::DepositLiquidity { glmr_value: 100, token_value: 400 },
+			Operation::WithdrawLiquidity { liquid_value: 1_000 },
+		]).is_err());
+
+		assert_eq!(sp_io::storage::root(), root);
+	});
+}
+
+#[test]
+fn batch_weight_grows_with_its_operations() {
+	let weight = |count| Call::<Test>::batch(
+		vec![Operation::TradeGlmrToToken { glmr_value: 100 }; count],
+	).get_dispatch_info().weight;
+
+	assert_eq!(weight(2), 2 * weight(1));
+	assert_eq!(weight(4), 4 * weight(1));
+}
+
+#[test]
+fn batch_size_is_bounded() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		let operation = Operation::TradeGlmrToToken { glmr_value: 1 };
+
+		assert_eq!(Moonbeam::batch(Origin::signed(1), vec![]), Err("Batch contains no operations"));
+		assert_eq!(Moonbeam::batch(Origin::signed(1), vec![operation; 5]), Err("Too many operations in batch"));
+	});
+}
+
+#[test]
+fn candles_track_price_and_volume() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		Moonbeam::on_finalize(1);
+		assert_eq!(Moonbeam::candles(), vec![]);
+
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(1), 1_000, 4_000));
+		Moonbeam::on_finalize(2);
+		let opening_price = Moonbeam::token_price();
+
+		assert_ok!(Moonbeam::trade_glmr_to_token(Origin::signed(2), 100, None));
+		let tokens_bought = Moonbeam::token_balance_of(&2) - INITIAL_BALANCE;
+		assert_ok!(Moonbeam::trade_token_to_glmr(Origin::signed(3), 50, None));
+		let glmr_bought = Moonbeam::glmr_balance_of(&3) - INITIAL_BALANCE;
+		Moonbeam::on_finalize(3);
+		let closing_price = Moonbeam::token_price();
+		assert_ne!(opening_price, closing_price);
+
+		assert_eq!(Moonbeam::candles(), vec![Candle {
+			start: 0,
+			open: opening_price,
+			high: opening_price.max(closing_price),
+			low: opening_price.min(closing_price),
+			close: closing_price,
+			glmr_volume: 100 + glmr_bought,
+			token_volume: tokens_bought + 50,
+		}]);
+	});
+}
+
+#[test]
+fn closed_candles_are_kept_in_a_ring_buffer() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(1), 1_000, 4_000));
+
+		for period in 0..5u64 {
+			assert_ok!(Moonbeam::trade_glmr_to_token(Origin::signed(2), 10, None));
+			Moonbeam::on_finalize(period * 10 + 5);
+		}
+
+		let candles = Moonbeam::candles();
+		assert_eq!(Moonbeam::candle_count(), 4);
+		assert_eq!(candles.iter().map(|candle| candle.start).collect::<Vec<_>>(), vec![10, 20, 30, 40]);
+		for pair in candles.windows(2) {
+			assert_eq!(pair[1].open, pair[0].close);
+			assert_eq!(pair[1].glmr_volume, 10);
+		}
+	});
+}
+
+#[test]
+fn amount_in_buys_at_least_amount_out() {
+	for &(amount_out, input_reserve, output_reserve) in &[
+		(1, 1_000, 1_000),
+		(999, 1_000, 1_000),
+		(25, 4_000_000, 1_000_000),
+		(1 << 60, u128::max_value(), 1 << 100),
+	] {
+		let amount_in = math::get_amount_in(amount_out, input_reserve, output_reserve, FEE).unwrap();
+		assert!(math::get_amount_out(amount_in, input_reserve, output_reserve, FEE).unwrap() >= amount_out);
+		assert!(math::get_amount_out(amount_in - 1, input_reserve, output_reserve, FEE).unwrap_or(0) < amount_out);
+	}
+	assert_eq!(math::get_amount_in(1_000, 1_000, 1_000, FEE), None);
+}
+
+#[test]
+fn fees_can_be_paid_in_tokens() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(1), 1_000_000, 4_000_000));
+		let info = DispatchInfo::default();
+		let fee = ChargeTransactionPayment::<Test>::compute_fee(10, info, 5);
+		let pool = Moonbeam::pool();
+		let tokens = math::get_amount_in(fee, pool.token_reserve, pool.glmr_reserve, FEE).unwrap();
+
+		// the glmr bought has to be held natively by the dex account, beyond
+		// what users deposited
+		assert!(ChargeFee::<Test>::new(5, Some(tokens)).validate(&2, &(), info, 10).is_err());
+		let _ = balances::Module::<Test>::deposit_creating(&5, fee);
+		assert_ok!(Moonbeam::deposit_glmr(Origin::signed(5), fee));
+		assert!(ChargeFee::<Test>::new(5, Some(tokens)).validate(&2, &(), info, 10).is_err());
+		assert!(ChargeFee::<Test>::new(5, Some(tokens)).pre_dispatch(&2, &(), info, 10).is_err());
+		let _ = balances::Module::<Test>::deposit_creating(&Moonbeam::account_id(), fee);
+
+		assert!(ChargeFee::<Test>::new(5, Some(tokens - 1)).validate(&2, &(), info, 10).is_err());
+		assert!(ChargeFee::<Test>::new(5, Some(tokens - 1)).pre_dispatch(&2, &(), info, 10).is_err());
+		assert_eq!(Moonbeam::token_balance_of(&2), INITIAL_BALANCE);
+		assert_eq!(Moonbeam::pool(), pool);
+
+		// validation leaves the tokens with the sender
+		assert!(ChargeFee::<Test>::new(5, Some(tokens)).validate(&2, &(), info, 10).is_ok());
+		assert_eq!(Moonbeam::token_balance_of(&2), INITIAL_BALANCE);
+		assert_eq!(Moonbeam::pool(), pool);
+
+		assert!(ChargeFee::<Test>::new(5, Some(tokens)).pre_dispatch(&2, &(), info, 10).is_ok());
+		assert_eq!(Moonbeam::token_balance_of(&2), INITIAL_BALANCE - tokens);
+		assert_eq!(Moonbeam::glmr_balance_of(&2), INITIAL_BALANCE);
+		assert_eq!(Moonbeam::glmr_pool_balance(), pool.glmr_reserve - fee);
+		assert_eq!(Moonbeam::token_pool_balance(), pool.token_reserve + tokens);
+
+		// the glmr bought is paid out of the dex account as a fee paid in glmr
+		// would be, without minting any
+		assert_eq!(balances::Module::<Test>::free_balance(&FEE_COLLECTOR), fee);
+		assert_eq!(balances::Module::<Test>::free_balance(&Moonbeam::account_id()), fee);
+		assert_eq!(Moonbeam::native_reserve(), 0);
+		assert_eq!(balances::Module::<Test>::total_issuance(), 2 * fee);
+	});
+}
+
+#[test]
+fn native_glmr_is_deposited_into_and_withdrawn_from_the_dex() {
+	new_test_ext().execute_with(|| {
+		let _ = balances::Module::<Test>::deposit_creating(&1, 1_000);
+
+		assert!(Moonbeam::deposit_glmr(Origin::signed(1), 1_001).is_err());
+		assert_ok!(Moonbeam::deposit_glmr(Origin::signed(1), 600));
+		assert_eq!(balances::Module::<Test>::free_balance(&1), 400);
+		assert_eq!(balances::Module::<Test>::free_balance(&Moonbeam::account_id()), 600);
+		assert_eq!(Moonbeam::glmr_balance_of(&1), 600);
+
+		assert!(Moonbeam::withdraw_glmr(Origin::signed(1), 601).is_err());
+		assert_ok!(Moonbeam::withdraw_glmr(Origin::signed(1), 200));
+		assert_eq!(balances::Module::<Test>::free_balance(&1), 600);
+		assert_eq!(balances::Module::<Test>::free_balance(&Moonbeam::account_id()), 400);
+		assert_eq!(Moonbeam::glmr_balance_of(&1), 400);
+
+		// glmr credited in any other way is not backed, and can not be withdrawn
+		assert_ok!(Moonbeam::set_glmr_balance(system::RawOrigin::Root.into(), 2, 1_000));
+		assert_eq!(Moonbeam::withdraw_glmr(Origin::signed(2), 1), Err("Not enough deposited glmr to withdraw"));
+		assert_ok!(Moonbeam::set_glmr_balance(system::RawOrigin::Root.into(), 1, 1_000));
+		assert_eq!(Moonbeam::withdraw_glmr(Origin::signed(1), 401), Err("Not enough deposited glmr to withdraw"));
+		assert_eq!(Moonbeam::glmr_balance_of(&2), 1_000);
+		assert_eq!(balances::Module::<Test>::free_balance(&Moonbeam::account_id()), 400);
+		assert_eq!(balances::Module::<Test>::total_issuance(), 1_000);
+
+		// deposits are not there to pay out glmr bought from the pool
+		assert_eq!(Moonbeam::native_reserve(), 0);
+		assert_ok!(Moonbeam::withdraw_glmr(Origin::signed(1), 400));
+		assert_eq!(Moonbeam::glmr_deposit_of(&1), 0);
+		assert_eq!(Moonbeam::total_glmr_deposits(), 0);
+	});
+}
+
+#[test]
+fn referrers_are_paid_a_share_of_the_fee() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(1), 1_000_000, 4_000_000));
+		let pool = Moonbeam::pool();
+
+		assert_eq!(
+			Moonbeam::trade_glmr_to_token(Origin::signed(2), 10_000, Some(2)),
+			Err("Traders can not refer themselves"),
+		);
+
+		// a 0.3% fee on 10_000 is 30, half of which goes to the referrer
+		assert_ok!(Moonbeam::trade_glmr_to_token(Origin::signed(2), 10_000, Some(3)));
+		assert_eq!(Moonbeam::glmr_balance_of(&3), INITIAL_BALANCE + 15);
+		assert_eq!(Moonbeam::glmr_pool_balance(), pool.glmr_reserve + 10_000 - 15);
+
+		assert_ok!(Moonbeam::trade_token_to_glmr(Origin::signed(4), 2_000, Some(3)));
+		assert_eq!(Moonbeam::token_balance_of(&3), INITIAL_BALANCE + 3);
+		assert_eq!(Moonbeam::referral_totals(&3), (15, 3));
+	});
+}
+
+#[test]
+fn protocol_fees_are_paid_out_when_the_block_ends() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		set_protocol_fee_share(Perbill::from_percent(20));
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(1), 1_000_000, 4_000_000));
+		let pool = Moonbeam::pool();
+
+		// of the 30 glmr fee on 10_000 the referrer gets 15 and the protocol a
+		// fifth of the rest
+		assert_ok!(Moonbeam::trade_glmr_to_token(Origin::signed(2), 10_000, Some(3)));
+		assert_eq!(Moonbeam::glmr_pool_balance(), pool.glmr_reserve + 10_000 - 15 - 3);
+
+		// without a referrer the protocol gets a fifth of the whole fee
+		assert_ok!(Moonbeam::trade_token_to_glmr(Origin::signed(4), 500_000, None));
+		assert_eq!(Moonbeam::protocol_fees(), (3, 300));
+		let pool = Moonbeam::pool();
+		let glmr_bought = math::get_amount_out(300, pool.token_reserve, pool.glmr_reserve, FEE).unwrap();
+		assert!(glmr_bought > 0);
+		let _ = balances::Module::<Test>::deposit_creating(&Moonbeam::account_id(), 1_000);
+
+		// the token fees are sold for glmr, and all of it is paid out natively
+		// from the dex account
+		Moonbeam::on_finalize(1);
+		assert_eq!(Moonbeam::protocol_fees(), (0, 0));
+		assert_eq!(Moonbeam::token_pool_balance(), pool.token_reserve + 300);
+		assert_eq!(Moonbeam::glmr_pool_balance(), pool.glmr_reserve - glmr_bought);
+		assert_eq!(balances::Module::<Test>::free_balance(&PROTOCOL_FEE_DESTINATION), 3 + glmr_bought);
+		assert_eq!(balances::Module::<Test>::free_balance(&Moonbeam::account_id()), 1_000 - 3 - glmr_bought);
+		assert_eq!(balances::Module::<Test>::total_issuance(), 1_000);
+		assert_eq!(Moonbeam::glmr_balance_of(&PROTOCOL_FEE_DESTINATION), 0);
+	});
+}
+
+#[test]
+fn protocol_fees_the_dex_account_can_not_pay_are_kept() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		set_protocol_fee_share(Perbill::from_percent(20));
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(1), 1_000_000, 4_000_000));
+
+		assert_ok!(Moonbeam::trade_glmr_to_token(Origin::signed(2), 10_000, None));
+		assert_eq!(Moonbeam::protocol_fees(), (6, 0));
+
+		// no glmr is minted for the fees
+		Moonbeam::on_finalize(1);
+		assert_eq!(Moonbeam::protocol_fees(), (6, 0));
+		assert_eq!(balances::Module::<Test>::total_issuance(), 0);
+
+		// deposits do not pay them either
+		let _ = balances::Module::<Test>::deposit_creating(&5, 6);
+		assert_ok!(Moonbeam::deposit_glmr(Origin::signed(5), 6));
+		Moonbeam::on_finalize(2);
+		assert_eq!(Moonbeam::protocol_fees(), (6, 0));
+
+		let _ = balances::Module::<Test>::deposit_creating(&Moonbeam::account_id(), 6);
+		Moonbeam::on_finalize(3);
+		assert_eq!(Moonbeam::protocol_fees(), (0, 0));
+		assert_eq!(balances::Module::<Test>::free_balance(&PROTOCOL_FEE_DESTINATION), 6);
+	});
+}
+
+#[test]
+fn protocol_fees_too_small_to_sell_are_kept() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		set_protocol_fee_share(Perbill::from_percent(20));
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(1), 1_000_000, 4_000_000));
+
+		assert_ok!(Moonbeam::trade_token_to_glmr(Origin::signed(4), 5_000, None));
+		assert_eq!(Moonbeam::protocol_fees(), (0, 3));
+
+		Moonbeam::on_finalize(1);
+		assert_eq!(Moonbeam::protocol_fees(), (0, 3));
+		assert_eq!(balances::Module::<Test>::free_balance(&PROTOCOL_FEE_DESTINATION), 0);
+	});
+}
+
+#[test]
+fn zap_deposits_a_single_asset() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(1), 1_000_000, 4_000_000));
+
+		assert_ok!(Moonbeam::zap_glmr(Origin::signed(2), 100_000, 1));
+		let liquid = Moonbeam::liquid_balance_of(&2);
+		assert!(liquid > 0);
+		// no more than a unit of glmr's worth of dust is left over, and a glmr
+		// is worth about 4 tokens
+		assert!(INITIAL_BALANCE - Moonbeam::glmr_balance_of(&2) >= 100_000 - 1);
+		assert!(Moonbeam::token_balance_of(&2) - INITIAL_BALANCE <= 4);
+
+		assert_ok!(Moonbeam::zap_token(Origin::signed(3), 400_000, 1));
+		assert!(Moonbeam::liquid_balance_of(&3) > 0);
+		assert!(INITIAL_BALANCE - Moonbeam::token_balance_of(&3) >= 400_000 - 4);
+		assert!(Moonbeam::glmr_balance_of(&3) - INITIAL_BALANCE <= 1);
+	});
+}
+
+#[test]
+fn zap_below_minimum_liquid_changes_nothing() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		assert_eq!(
+			Moonbeam::zap_glmr(Origin::signed(2), 100_000, 1),
+			Err("Single sided deposits need a pool with liquidity"),
+		);
+
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(1), 1_000_000, 4_000_000));
+		let pool = Moonbeam::pool();
+
+		assert_eq!(
+			Moonbeam::zap_glmr(Origin::signed(2), 100_000, 100_000),
+			Err("Deposit mints less liquid than the minimum"),
+		);
+		assert_eq!(Moonbeam::glmr_balance_of(&2), INITIAL_BALANCE);
+		assert_eq!(Moonbeam::token_balance_of(&2), INITIAL_BALANCE);
+		assert_eq!(Moonbeam::liquid_balance_of(&2), 0);
+		assert_eq!(Moonbeam::pool(), pool);
+	});
+}
+
+#[test]
+fn liquidity_can_be_withdrawn_in_a_single_asset() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(1), 1_000_000, 4_000_000));
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(2), 100_000, 400_000));
+		let glmr_before = Moonbeam::glmr_balance_of(&2);
+
+		assert_eq!(
+			Moonbeam::withdraw_liquidity_to_glmr(Origin::signed(2), 100_000, 200_000),
+			Err("Withdrawal pays out less than the minimum"),
+		);
+		assert_eq!(Moonbeam::liquid_balance_of(&2), 100_000);
+
+		assert_ok!(Moonbeam::withdraw_liquidity_to_glmr(Origin::signed(2), 50_000, 90_000));
+		assert_eq!(Moonbeam::token_balance_of(&2), INITIAL_BALANCE - 400_000);
+		assert!(Moonbeam::glmr_balance_of(&2) - glmr_before >= 90_000);
+
+		assert_ok!(Moonbeam::withdraw_liquidity_to_token(Origin::signed(2), 50_000, 1));
+		assert_eq!(Moonbeam::liquid_balance_of(&2), 0);
+		assert!(Moonbeam::token_balance_of(&2) > INITIAL_BALANCE - 400_000);
+	});
+}
+
+#[test]
+fn position_value_separates_fees_from_price_movement() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		assert_eq!(Moonbeam::position_value(&1), None);
+
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(1), 1_000_000, 4_000_000));
+		assert_eq!(Moonbeam::position_value(&1), Some(PositionValue {
+			liquid: 1_000_000,
+			glmr_redeemable: 1_000_000,
+			token_redeemable: 4_000_000,
+			glmr_fees: 0,
+			token_fees: 0,
+			impermanent_loss: 0,
+		}));
+
+		// a round trip puts the price back about where it was, so the position has
+		// earned fees without losing anything to price movement
+		assert_ok!(Moonbeam::trade_glmr_to_token(Origin::signed(2), 100_000, None));
+		let tokens_bought = Moonbeam::token_balance_of(&2) - INITIAL_BALANCE;
+		assert_ok!(Moonbeam::trade_token_to_glmr(Origin::signed(2), tokens_bought, None));
+		let value = Moonbeam::position_value(&1).unwrap();
+		assert!(value.glmr_fees > 0 && value.token_fees > 0);
+		assert_eq!(value.impermanent_loss, 0);
+
+		// moving the price leaves the position worth less than holding
+		assert_ok!(Moonbeam::trade_glmr_to_token(Origin::signed(2), 500_000, None));
+		let value = Moonbeam::position_value(&1).unwrap();
+		assert_eq!(value.glmr_redeemable, Moonbeam::glmr_pool_balance());
+		assert!(value.impermanent_loss > 200_000);
+	});
+}
+
+#[test]
+fn positions_follow_the_liquid() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(1), 1_000_000, 4_000_000));
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(2), 100_000, 400_000));
+		assert_eq!(Moonbeam::position_of(&2), Some(LiquidityPosition {
+			glmr_entry: 100_000,
+			token_entry: 400_000,
+			root_k_entry: 200_000,
+		}));
+
+		assert_ok!(Moonbeam::withdraw_liquidity(Origin::signed(2), 25_000));
+		assert_ok!(Moonbeam::transfer_liquid(system::RawOrigin::Root.into(), 2, 3, 25_000));
+		assert_eq!(Moonbeam::position_of(&2), Some(LiquidityPosition {
+			glmr_entry: 50_000,
+			token_entry: 200_000,
+			root_k_entry: 100_000,
+		}));
+		assert_eq!(Moonbeam::position_of(&3), Some(LiquidityPosition {
+			glmr_entry: 25_000,
+			token_entry: 100_000,
+			root_k_entry: 50_000,
+		}));
+
+		assert_ok!(Moonbeam::withdraw_liquidity(Origin::signed(2), 50_000));
+		assert_eq!(Moonbeam::position_of(&2), None);
+	});
+}
+
+#[test]
+fn admin_origin_manages_the_market() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		let root = || -> Origin { system::RawOrigin::Root.into() };
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(1), 1_000_000, 4_000_000));
+
+		assert!(Moonbeam::set_market_status(Origin::signed(1), MarketStatus::Paused).is_err());
+		assert!(Moonbeam::set_trading_fee(Origin::signed(1), Permill::zero()).is_err());
+
+		assert_ok!(Moonbeam::set_market_status(root(), MarketStatus::Delisted));
+		assert_eq!(Moonbeam::trade_glmr_to_token(Origin::signed(2), 100, None), Err("Market is not open"));
+		assert_eq!(Moonbeam::deposit_liquidity(Origin::signed(2), 100, 400), Err("Market is not open"));
+		assert_ok!(Moonbeam::withdraw_liquidity(Origin::signed(1), 100_000));
+
+		assert_ok!(Moonbeam::set_market_status(root(), MarketStatus::Paused));
+		assert_eq!(Moonbeam::withdraw_liquidity(Origin::signed(1), 100_000), Err("Market is paused"));
+
+		assert_ok!(Moonbeam::set_market_status(root(), MarketStatus::Open));
+		assert_eq!(
+			Moonbeam::set_trading_fee(root(), Permill::one()),
+			Err("Trading fee must be less than the whole trade"),
+		);
+		assert_ok!(Moonbeam::set_trading_fee(root(), Permill::zero()));
+		let pool = Moonbeam::pool();
+		assert_ok!(Moonbeam::trade_glmr_to_token(Origin::signed(2), 100, None));
+		assert_eq!(
+			Moonbeam::token_balance_of(&2) - INITIAL_BALANCE,
+			math::get_amount_out(100, pool.glmr_reserve, pool.token_reserve, 0).unwrap(),
+		);
+	});
+}
+
+#[test]
+fn assets_are_transferred_and_spent_by_approved_spenders() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		assert_ok!(Moonbeam::transfer_asset(Asset::Token, &1, &2, 1_000));
+		assert_eq!(Moonbeam::asset_balance_of(Asset::Token, &1), INITIAL_BALANCE - 1_000);
+		assert_eq!(Moonbeam::asset_balance_of(Asset::Token, &2), INITIAL_BALANCE + 1_000);
+		assert_eq!(Moonbeam::transfer_asset(Asset::Liquid, &1, &2, 1), Err("Not enough balance for transfer"));
+
+		Moonbeam::approve(Asset::Token, &1, &3, 500);
+		assert_eq!(Moonbeam::allowance(&1, &(Asset::Token, 3u64)), 500);
+		assert_eq!(Moonbeam::allowance(&1, &(Asset::Liquid, 3u64)), 0);
+		assert_eq!(
+			Moonbeam::transfer_asset_from(Asset::Token, &3, &1, &4, 501),
+			Err("Transfer amount exceeds allowance"),
+		);
+		assert_ok!(Moonbeam::transfer_asset_from(Asset::Token, &3, &1, &4, 200));
+		assert_eq!(Moonbeam::allowance(&1, &(Asset::Token, 3u64)), 300);
+		assert_eq!(Moonbeam::token_balance_of(&4), INITIAL_BALANCE + 200);
+
+		// an unlimited allowance is never used up
+		Moonbeam::approve(Asset::Token, &1, &3, u128::max_value());
+		assert_ok!(Moonbeam::transfer_asset_from(Asset::Token, &3, &1, &4, 200));
+		assert_eq!(Moonbeam::allowance(&1, &(Asset::Token, 3u64)), u128::max_value());
+	});
+}
+
+#[test]
+fn liquid_transfers_carry_the_position_along() {
+	new_test_ext().execute_with(|| {
+		endow_accounts();
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(1), 100_000, 400_000));
+		assert_eq!(Moonbeam::asset_supply(Asset::Liquid), 100_000);
+
+		assert_ok!(Moonbeam::transfer_asset(Asset::Liquid, &1, &2, 25_000));
+		assert_eq!(Moonbeam::liquid_balance_of(&2), 25_000);
+		assert_eq!(Moonbeam::position_of(&2), Some(LiquidityPosition {
+			glmr_entry: 25_000,
+			token_entry: 100_000,
+			root_k_entry: 50_000,
+		}));
+
+		// sending to yourself changes nothing
+		let position = Moonbeam::position_of(&1);
+		assert_ok!(Moonbeam::transfer_asset(Asset::Liquid, &1, &1, 25_000));
+		assert_eq!(Moonbeam::liquid_balance_of(&1), 75_000);
+		assert_eq!(Moonbeam::position_of(&1), position);
+	});
+}
+
+#[test]
+fn token_issuance_counts_genesis_and_admin_balances() {
+	let mut storage = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	GenesisConfig::<Test> {
+		glmr_balances: vec![(1, 1_000)],
+		token_balances: vec![(1, 5_000), (2, 5_000)],
+		liquidity: vec![(1, 400, 2_000)],
+	}.assimilate_storage(&mut storage).unwrap();
+
+	sp_io::TestExternalities::from(storage).execute_with(|| {
+		assert_eq!(Moonbeam::asset_supply(Asset::Token), 10_000);
+		assert_ok!(Moonbeam::trade_glmr_to_token(Origin::signed(1), 100, None));
+		assert_eq!(Moonbeam::asset_supply(Asset::Token), 10_000);
+
+		assert_ok!(Moonbeam::set_token_balance(system::RawOrigin::Root.into(), 2, 1_000));
+		assert_ok!(Moonbeam::set_token_balance(system::RawOrigin::Root.into(), 3, 500));
+		assert_eq!(Moonbeam::asset_supply(Asset::Token), 6_500);
+	});
+}
+
+#[test]
+fn migration_to_v2_counts_the_tokens_users_hold_and_in_the_pool() {
+	new_test_ext().execute_with(|| {
+		StorageVersion::put(1);
+		<Pool<Test>>::put(PoolInfo { glmr_reserve: 400, token_reserve: 2_000, liquid_supply: 400 });
+		<ProtocolFees<Test>>::put((10, 30));
+		// written directly, as version 1 did not track the issuance
+		<TokenBalances<Test>>::insert(1, 3_000);
+		<TokenBalances<Test>>::insert(2, 2_000);
+		<TokenBalances<Test>>::insert(10, 5);
+		<GlmrBalances<Test>>::insert(1, 1_000);
+
+		Moonbeam::on_initialize(1);
+
+		assert_eq!(Moonbeam::storage_version(), 2);
+		assert_eq!(Moonbeam::token_issuance(), 2_030);
+
+		// balances are counted before they first change, whatever changes them
+		assert_ok!(Moonbeam::trade_glmr_to_token(Origin::signed(1), 100, None));
+		assert_eq!(Moonbeam::token_issuance(), 5_030);
+		assert_ok!(Moonbeam::transfer_asset(Asset::Token, &1, &11, 100));
+		assert_eq!(Moonbeam::token_issuance(), 5_030);
+
+		// and the rest by the admin, each only once
+		assert!(Moonbeam::count_token_balances(Origin::signed(1), vec![2]).is_err());
+		assert!(Moonbeam::count_token_balances(system::RawOrigin::Root.into(), vec![2; 5]).is_err());
+		assert_ok!(Moonbeam::count_token_balances(system::RawOrigin::Root.into(), vec![1, 2, 10, 2]));
+		assert_eq!(Moonbeam::token_issuance(), 7_035);
+		assert_eq!(Moonbeam::asset_supply(Asset::Token), 7_035);
+
+		// and tracked from then on
+		assert_ok!(Moonbeam::set_token_balance(system::RawOrigin::Root.into(), 3, 1_000));
+		assert_eq!(Moonbeam::token_issuance(), 8_035);
+
+		assert!(Moonbeam::set_token_issuance(Origin::signed(1), 5_000).is_err());
+		assert_ok!(Moonbeam::set_token_issuance(system::RawOrigin::Root.into(), 5_000));
+		assert_eq!(Moonbeam::asset_supply(Asset::Token), 5_000);
+	});
+}
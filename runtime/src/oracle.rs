@@ -0,0 +1,377 @@
+//! # Price Oracle Module
+//!
+//! Publishes a reference price for the dex token, fetched from external HTTP
+//! sources by the off-chain workers of the oracle authorities.
+//!
+//! Each authority's off-chain worker fetches the configured `PriceSource` every
+//! `ReportInterval` blocks, signs the price with its oracle key and submits it
+//! back to the chain as an unsigned `submit_price` transaction.  The signature
+//! is checked when the transaction is validated.  The `ReferencePrice` is the
+//! median of the reports that are at most `MaxPriceAge` blocks old.
+//!
+//! Prices are the price of one token in glmr, scaled by `PRICE_PRECISION`, so
+//! they can be compared directly with the dex `TokenPrice`.  Markets quote the
+//! token and glmr in some other currency, such as USD, so the workers fetch the
+//! price of each from `PriceSource` and `GlmrPriceSource`, which must quote in
+//! the same currency, and report the ratio of the two.  No report is made
+//! while either source is unset.
+//!
+//! The oracle authorities are the validators of the current session, known by
+//! the oracle key in their session keys.  Reports are dropped when the set
+//! changes, since they are kept by index in the set.
+
+use frame_support::{decl_module, decl_storage, decl_event, dispatch, traits::{EnsureOrigin, Get}, Parameter};
+use frame_support::unsigned::ValidateUnsigned;
+use codec::{Encode, Decode};
+use system::{ensure_none, offchain::SubmitUnsignedTransaction};
+use sp_core::{U256, offchain::Duration};
+use sp_runtime::{
+	RuntimeAppPublic,
+	offchain::http,
+	traits::{Member, SaturatedConversion, Saturating, Zero},
+	transaction_validity::{
+		TransactionValidity, ValidTransaction, InvalidTransaction, TransactionPriority,
+	},
+};
+use sp_std::{convert::TryInto, prelude::*};
+
+#[cfg(test)]
+mod mock;
+#[cfg(test)]
+mod tests;
+
+pub mod sr25519 {
+	mod app_sr25519 {
+		use sp_application_crypto::{app_crypto, sr25519};
+		app_crypto!(sr25519, super::super::KEY_TYPE);
+	}
+
+	/// An oracle authority keypair using sr25519 as its crypto.
+	#[cfg(feature = "std")]
+	pub type AuthorityPair = app_sr25519::Pair;
+
+	/// An oracle authority signature using sr25519 as its crypto.
+	pub type AuthoritySignature = app_sr25519::Signature;
+
+	/// An oracle authority identifier using sr25519 as its crypto.
+	pub type AuthorityId = app_sr25519::Public;
+}
+
+/// Key type of the oracle authority keys.
+pub const KEY_TYPE: sp_core::crypto::KeyTypeId = sp_core::crypto::KeyTypeId(*b"orcl");
+
+/// A price, scaled by `PRICE_PRECISION`.
+pub type Price = u128;
+
+/// The number of price units in one whole price.
+pub const PRICE_PRECISION: Price = 1_000_000_000_000;
+
+/// How long an off-chain worker waits for the price source to answer, in milliseconds.
+const FETCH_TIMEOUT_MILLIS: u64 = 2_000;
+
+/// A price fetched by one of the oracle authorities.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct PriceReport<BlockNumber> {
+	/// The block at which the price was fetched.
+	pub block_number: BlockNumber,
+	/// Index of the reporting authority in `Authorities`.
+	pub authority_index: u32,
+	/// The fetched price.
+	pub price: Price,
+}
+
+pub trait Trait: system::Trait {
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+	/// A dispatchable call type.
+	type Call: From<Call<Self>>;
+	/// The origin allowed to change where prices are fetched from.
+	type AdminOrigin: EnsureOrigin<Self::Origin>;
+	/// A transaction submitter.
+	type SubmitTransaction: SubmitUnsignedTransaction<Self, <Self as Trait>::Call>;
+	/// The identifier type for an oracle authority.
+	type AuthorityId: Member + Parameter + RuntimeAppPublic + Default + Ord;
+	/// Number of blocks between two reports of the same authority.
+	type ReportInterval: Get<Self::BlockNumber>;
+	/// Reports older than this many blocks are left out of the reference price.
+	type MaxPriceAge: Get<Self::BlockNumber>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Oracle {
+		/// The authorities allowed to report prices.
+		Authorities get(authorities): Vec<T::AuthorityId>;
+		/// URL the off-chain workers fetch the token price from.  The first
+		/// decimal number in the response body is taken as the price.
+		PriceSource get(price_source) config(): Vec<u8>;
+		/// URL the off-chain workers fetch the glmr price from, in the currency
+		/// `PriceSource` quotes the token in.
+		GlmrPriceSource get(glmr_price_source) config(): Vec<u8>;
+		/// The latest report of each authority, as (block number, price).
+		Reports get(reports): map u32 => Option<(T::BlockNumber, Price)>;
+		/// Median of the recent reports.
+		ReferencePrice get(reference_price): Option<Price>;
+	}
+	add_extra_genesis {
+		config(authorities): Vec<T::AuthorityId>;
+		build(|config| Module::<T>::initialize_authorities(&config.authorities))
+	}
+}
+
+decl_event!(
+	pub enum Event<T> where AuthorityId = <T as Trait>::AuthorityId {
+		/// An authority reported a price.
+		PriceReported(AuthorityId, Price),
+		/// The reference price changed.
+		ReferencePriceUpdated(Price),
+	}
+);
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event() = default;
+
+		/// Records a price report.  The report's signature is checked by
+		/// `validate_unsigned` before the transaction gets here.
+		fn submit_price(
+			origin,
+			report: PriceReport<T::BlockNumber>,
+			_signature: <T::AuthorityId as RuntimeAppPublic>::Signature
+		) -> dispatch::Result {
+			ensure_none(origin)?;
+
+			let authority = match Self::authorities().get(report.authority_index as usize) {
+				Some(authority) => authority.clone(),
+				None => return Err("Unknown oracle authority"),
+			};
+
+			<Reports<T>>::insert(report.authority_index, (report.block_number, report.price));
+			Self::deposit_event(RawEvent::PriceReported(authority, report.price));
+			Self::update_reference_price(<system::Module<T>>::block_number());
+
+			Ok(())
+		}
+
+		/// Changes the URL token prices are fetched from.
+		/// Only callable by the admin origin.
+		fn set_price_source(origin, source: Vec<u8>) -> dispatch::Result {
+			T::AdminOrigin::ensure_origin(origin)?;
+
+			PriceSource::put(source);
+
+			Ok(())
+		}
+
+		/// Changes the URL glmr prices are fetched from.
+		/// Only callable by the admin origin.
+		fn set_glmr_price_source(origin, source: Vec<u8>) -> dispatch::Result {
+			T::AdminOrigin::ensure_origin(origin)?;
+
+			GlmrPriceSource::put(source);
+
+			Ok(())
+		}
+
+		fn offchain_worker(now: T::BlockNumber) {
+			if !sp_io::offchain::is_validator() || !(now % T::ReportInterval::get()).is_zero() {
+				return
+			}
+
+			if let Err(e) = Self::report_price(now) {
+				sp_runtime::print(e);
+			}
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	fn initialize_authorities(authorities: &[T::AuthorityId]) {
+		if !authorities.is_empty() {
+			assert!(Self::authorities().is_empty(), "Oracle authorities are already initialized");
+			<Authorities<T>>::put(authorities.to_vec());
+		}
+	}
+
+	/// Fetches the price and submits a signed report for it, using the first
+	/// local key that belongs to an oracle authority.
+	fn report_price(block_number: T::BlockNumber) -> Result<(), &'static str> {
+		let local_keys = T::AuthorityId::all();
+		let (authority_index, key) = match Self::authorities().into_iter()
+			.enumerate()
+			.find(|(_, authority)| local_keys.contains(authority))
+		{
+			Some(found) => found,
+			None => return Ok(()),
+		};
+
+		let price = Self::fetch_price()?;
+		let report = PriceReport {
+			block_number,
+			authority_index: authority_index as u32,
+			price,
+		};
+		let signature = match key.sign(&report.encode()) {
+			Some(signature) => signature,
+			None => return Err("Failed to sign price report"),
+		};
+
+		T::SubmitTransaction::submit_unsigned(Call::submit_price(report, signature))
+			.map_err(|_| "Failed to submit price report")
+	}
+
+	/// Fetches the current price of the token in glmr, from the prices of both
+	/// in `PriceSource` and `GlmrPriceSource`.
+	fn fetch_price() -> Result<Price, &'static str> {
+		let (token_source, glmr_source) = (Self::price_source(), Self::glmr_price_source());
+		if token_source.is_empty() || glmr_source.is_empty() {
+			return Err("Price sources are not set")
+		}
+
+		let token_price = Self::fetch(&token_source)?;
+		let glmr_price = Self::fetch(&glmr_source)?;
+		if glmr_price.is_zero() {
+			return Err("Glmr price source returned zero")
+		}
+
+		let price = U256::from(token_price) * U256::from(PRICE_PRECISION) / U256::from(glmr_price);
+		price.try_into().map_err(|_| "Price is out of range")
+	}
+
+	/// Fetches a price from the URL in `source`.
+	fn fetch(source: &[u8]) -> Result<Price, &'static str> {
+		let url = sp_std::str::from_utf8(source).map_err(|_| "Price source is not valid utf8")?;
+
+		let deadline = sp_io::offchain::timestamp().add(Duration::from_millis(FETCH_TIMEOUT_MILLIS));
+		let pending = http::Request::get(url)
+			.deadline(deadline)
+			.send()
+			.map_err(|_| "Failed to send price request")?;
+		let response = pending.try_wait(deadline)
+			.map_err(|_| "Price request timed out")?
+			.map_err(|_| "Price request failed")?;
+		if response.code != 200 {
+			return Err("Price source returned an error")
+		}
+
+		let body = response.body().collect::<Vec<u8>>();
+		parse_price(&body).ok_or("Price source returned no price")
+	}
+
+	/// Sets the reference price to the median of the reports that are recent
+	/// enough at `now`.
+	fn update_reference_price(now: T::BlockNumber) {
+		let max_age = T::MaxPriceAge::get();
+		let mut prices = (0..Self::authorities().len() as u32)
+			.filter_map(Self::reports)
+			.filter(|(reported_at, _)| now.saturating_sub(*reported_at) <= max_age)
+			.map(|(_, price)| price)
+			.collect::<Vec<_>>();
+		if prices.is_empty() {
+			return
+		}
+
+		prices.sort();
+		let median = prices[prices.len() / 2];
+		if Self::reference_price() != Some(median) {
+			ReferencePrice::put(median);
+			Self::deposit_event(RawEvent::ReferencePriceUpdated(median));
+		}
+	}
+}
+
+/// Parses the first decimal number in `body` into a price, keeping at most as
+/// many fractional digits as `PRICE_PRECISION` has.
+fn parse_price(body: &[u8]) -> Option<Price> {
+	let start = body.iter().position(u8::is_ascii_digit)?;
+	let mut price: Price = 0;
+	let mut scale = PRICE_PRECISION;
+	let mut fraction = false;
+
+	for &byte in &body[start..] {
+		match byte {
+			b'0'..=b'9' if !fraction => {
+				price = price.checked_mul(10)?.checked_add(Price::from(byte - b'0'))?;
+			},
+			b'0'..=b'9' => {
+				if scale > 1 {
+					scale /= 10;
+					price = price.checked_mul(10)?.checked_add(Price::from(byte - b'0'))?;
+				}
+			},
+			b'.' if !fraction => fraction = true,
+			_ => break,
+		}
+	}
+
+	price.checked_mul(scale)
+}
+
+impl<T: Trait> sp_runtime::BoundToRuntimeAppPublic for Module<T> {
+	type Public = T::AuthorityId;
+}
+
+impl<T: Trait> session::OneSessionHandler<T::AccountId> for Module<T> {
+	type Key = T::AuthorityId;
+
+	fn on_genesis_session<'a, I: 'a>(validators: I)
+		where I: Iterator<Item=(&'a T::AccountId, T::AuthorityId)>
+	{
+		let keys = validators.map(|(_, key)| key).collect::<Vec<_>>();
+		Self::initialize_authorities(&keys);
+	}
+
+	fn on_new_session<'a, I: 'a>(changed: bool, validators: I, _queued_validators: I)
+		where I: Iterator<Item=(&'a T::AccountId, T::AuthorityId)>
+	{
+		if !changed {
+			return
+		}
+
+		// reports are kept by authority index, which a new set reuses
+		for index in 0..Self::authorities().len() as u32 {
+			<Reports<T>>::remove(index);
+		}
+		<Authorities<T>>::put(validators.map(|(_, key)| key).collect::<Vec<_>>());
+	}
+
+	fn on_disabled(_validator_index: usize) {}
+}
+
+impl<T: Trait> ValidateUnsigned for Module<T> {
+	type Call = Call<T>;
+
+	fn validate_unsigned(call: &Self::Call) -> TransactionValidity {
+		if let Call::submit_price(report, signature) = call {
+			if report.block_number > <system::Module<T>>::block_number() {
+				return InvalidTransaction::Future.into()
+			}
+
+			if let Some((reported_at, _)) = Self::reports(report.authority_index) {
+				if reported_at >= report.block_number {
+					return InvalidTransaction::Stale.into()
+				}
+			}
+
+			let authorities = Self::authorities();
+			let authority = match authorities.get(report.authority_index as usize) {
+				Some(authority) => authority,
+				None => return InvalidTransaction::BadProof.into(),
+			};
+
+			let signature_valid = report.using_encoded(|encoded| authority.verify(&encoded, signature));
+			if !signature_valid {
+				return InvalidTransaction::BadProof.into()
+			}
+
+			Ok(ValidTransaction {
+				priority: TransactionPriority::max_value(),
+				requires: vec![],
+				provides: vec![(report.authority_index, report.block_number).encode()],
+				longevity: T::ReportInterval::get().saturated_into::<u64>(),
+				propagate: true,
+			})
+		} else {
+			InvalidTransaction::Call.into()
+		}
+	}
+}
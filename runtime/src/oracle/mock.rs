@@ -0,0 +1,77 @@
+//! Test runtime for the oracle module.
+
+use frame_support::{impl_outer_origin, parameter_types, weights::Weight};
+use sp_core::H256;
+use sp_runtime::{
+	Perbill,
+	testing::{Header, TestXt, UintAuthorityId},
+	traits::{BlakeTwo256, IdentityLookup},
+};
+use super::{Call, GenesisConfig, Module, Trait};
+
+impl_outer_origin! {
+	pub enum Origin for Test {}
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Test;
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: Weight = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::one();
+}
+
+impl system::Trait for Test {
+	type Origin = Origin;
+	type Call = ();
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = ();
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+}
+
+/// An extrinsic type used for tests.
+pub type Extrinsic = TestXt<Call<Test>, ()>;
+type SubmitTransaction = system::offchain::TransactionSubmitter<(), Call<Test>, Extrinsic>;
+
+parameter_types! {
+	pub const ReportInterval: u64 = 5;
+	pub const MaxPriceAge: u64 = 10;
+}
+
+impl Trait for Test {
+	type Event = ();
+	type Call = Call<Test>;
+	type AdminOrigin = system::EnsureRoot<u64>;
+	type SubmitTransaction = SubmitTransaction;
+	type AuthorityId = UintAuthorityId;
+	type ReportInterval = ReportInterval;
+	type MaxPriceAge = MaxPriceAge;
+}
+
+pub type Oracle = Module<Test>;
+
+pub const PRICE_SOURCE: &str = "https://prices.example.com/token";
+pub const GLMR_PRICE_SOURCE: &str = "https://prices.example.com/glmr";
+
+/// Build test externalities with authorities 1, 2 and 3.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	let mut storage = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	GenesisConfig::<Test> {
+		authorities: vec![UintAuthorityId(1), UintAuthorityId(2), UintAuthorityId(3)],
+		price_source: PRICE_SOURCE.as_bytes().to_vec(),
+		glmr_price_source: GLMR_PRICE_SOURCE.as_bytes().to_vec(),
+	}.assimilate_storage(&mut storage).unwrap();
+	storage.into()
+}
@@ -0,0 +1,135 @@
+//! Tests for the oracle module.
+
+use super::*;
+use super::mock::*;
+use frame_support::assert_ok;
+use sp_core::offchain::{OffchainExt, testing::{PendingRequest, TestOffchainExt}};
+use session::OneSessionHandler;
+use sp_runtime::{testing::UintAuthorityId, transaction_validity::TransactionValidityError};
+
+fn signed_report(authority_index: u32, block_number: u64, price: Price)
+	-> (PriceReport<u64>, <UintAuthorityId as RuntimeAppPublic>::Signature)
+{
+	let report = PriceReport { block_number, authority_index, price };
+	let signature = UintAuthorityId(u64::from(authority_index) + 1).sign(&report.encode()).unwrap();
+	(report, signature)
+}
+
+fn submit(authority_index: u32, block_number: u64, price: Price) -> dispatch::Result {
+	let (report, signature) = signed_report(authority_index, block_number, price);
+	Oracle::submit_price(system::RawOrigin::None.into(), report, signature)
+}
+
+#[test]
+fn parse_price_takes_first_decimal_number() {
+	assert_eq!(parse_price(b"3"), Some(3 * PRICE_PRECISION));
+	assert_eq!(parse_price(br#"{"USD":0.25}"#), Some(PRICE_PRECISION / 4));
+	assert_eq!(parse_price(br#"{"USD": 12.5, "EUR": 11.0}"#), Some(12 * PRICE_PRECISION + PRICE_PRECISION / 2));
+	assert_eq!(parse_price(b"0.1234567890123456"), Some(123_456_789_012));
+	assert_eq!(parse_price(b"no price here"), None);
+}
+
+#[test]
+fn fetch_price_converts_the_token_price_into_glmr() {
+	let (offchain, state) = TestOffchainExt::new();
+	let mut ext = new_test_ext();
+	ext.register_extension(OffchainExt::new(offchain));
+	state.write().expect_request(0, PendingRequest {
+		method: "GET".into(),
+		uri: PRICE_SOURCE.into(),
+		response: Some(br#"{"USD":0.25}"#.to_vec()),
+		sent: true,
+		..Default::default()
+	});
+	state.write().expect_request(1, PendingRequest {
+		method: "GET".into(),
+		uri: GLMR_PRICE_SOURCE.into(),
+		response: Some(br#"{"USD":2}"#.to_vec()),
+		sent: true,
+		..Default::default()
+	});
+
+	ext.execute_with(|| {
+		assert_eq!(Oracle::fetch_price(), Ok(PRICE_PRECISION / 8));
+	});
+}
+
+#[test]
+fn no_price_is_fetched_without_a_glmr_price_source() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Oracle::set_glmr_price_source(system::RawOrigin::Root.into(), vec![]));
+
+		assert_eq!(Oracle::fetch_price(), Err("Price sources are not set"));
+	});
+}
+
+#[test]
+fn authorities_follow_the_session_validators() {
+	new_test_ext().execute_with(|| {
+		system::Module::<Test>::set_block_number(20);
+		assert_ok!(submit(0, 20, 100));
+
+		let validators = vec![(10, UintAuthorityId(4)), (11, UintAuthorityId(5))];
+		let keys = validators.iter().map(|(validator, key)| (validator, key.clone()));
+		Oracle::on_new_session(false, keys.clone(), keys.clone());
+		assert_eq!(Oracle::authorities().len(), 3);
+
+		Oracle::on_new_session(true, keys.clone(), keys);
+		assert_eq!(Oracle::authorities(), vec![UintAuthorityId(4), UintAuthorityId(5)]);
+		assert_eq!(Oracle::reports(0), None);
+	});
+}
+
+#[test]
+fn reference_price_is_median_of_recent_reports() {
+	new_test_ext().execute_with(|| {
+		system::Module::<Test>::set_block_number(20);
+
+		// too old to count
+		assert_ok!(submit(2, 5, 1_000));
+		assert_eq!(Oracle::reference_price(), None);
+
+		assert_ok!(submit(0, 18, 100));
+		assert_ok!(submit(1, 20, 300));
+		assert_eq!(Oracle::reference_price(), Some(300));
+
+		assert_ok!(submit(2, 20, 200));
+		assert_eq!(Oracle::reference_price(), Some(200));
+		assert_eq!(Oracle::reports(2), Some((20, 200)));
+	});
+}
+
+#[test]
+fn only_fresh_reports_signed_by_their_authority_are_valid() {
+	new_test_ext().execute_with(|| {
+		system::Module::<Test>::set_block_number(10);
+
+		let (report, signature) = signed_report(0, 10, 100);
+		assert!(Oracle::validate_unsigned(&Call::submit_price(report.clone(), signature.clone())).is_ok());
+
+		// signed by authority 1 but claiming to be authority 0
+		let (_, wrong_signature) = signed_report(1, 10, 100);
+		assert_eq!(
+			Oracle::validate_unsigned(&Call::submit_price(report.clone(), wrong_signature)),
+			Err(TransactionValidityError::Invalid(InvalidTransaction::BadProof)),
+		);
+
+		let (future, future_signature) = signed_report(0, 11, 100);
+		assert_eq!(
+			Oracle::validate_unsigned(&Call::submit_price(future, future_signature)),
+			Err(TransactionValidityError::Invalid(InvalidTransaction::Future)),
+		);
+
+		let (unknown, unknown_signature) = signed_report(3, 10, 100);
+		assert_eq!(
+			Oracle::validate_unsigned(&Call::submit_price(unknown, unknown_signature)),
+			Err(TransactionValidityError::Invalid(InvalidTransaction::BadProof)),
+		);
+
+		assert_ok!(Oracle::submit_price(system::RawOrigin::None.into(), report.clone(), signature.clone()));
+		assert_eq!(
+			Oracle::validate_unsigned(&Call::submit_price(report, signature)),
+			Err(TransactionValidityError::Invalid(InvalidTransaction::Stale)),
+		);
+	});
+}
@@ -0,0 +1,340 @@
+//! The Moonbeam precompiles, which let Solidity contracts and Ethereum wallets
+//! use glmr and the dex natively.
+//!
+//! They take ABI encoded calls, like any contract, and act for whoever calls
+//! them: a contract, or the sender of a transaction sent straight to the
+//! precompile.  What moves funds can not be static or delegate called.  What
+//! they write for a contract is put back if the contract reverts, but staking
+//! can not be put back, so it is dispatched for a contract once its execution
+//! has succeeded.
+//!
+//! At `GLMR_PRECOMPILE`, glmr as held by evm addresses:
+//! * `balanceOf(address) returns (uint256)`
+//! * `transfer(address to, uint256 value) returns (bool)`, which logs an
+//!   ERC-20 `Transfer`.
+//!
+//! At `DEX_PRECOMPILE`, the dex balances of the accounts backing evm addresses:
+//! * `getReserves() returns (uint256 glmr, uint256 tokens)`
+//! * `glmrBalanceOf(address) returns (uint256)`
+//! * `tokenBalanceOf(address) returns (uint256)`
+//! * `quoteGlmrToToken(uint256 glmrValue) returns (uint256 tokens)`
+//! * `quoteTokenToGlmr(uint256 tokenValue) returns (uint256 glmr)`
+//! * `tradeGlmrToToken(uint256 glmrValue, uint256 minTokens) returns (uint256 tokens)`
+//! * `tradeTokenToGlmr(uint256 tokenValue, uint256 minGlmr) returns (uint256 glmr)`
+//!
+//! At `TOKEN_PRECOMPILE` and `LIQUID_PRECOMPILE`, the dex token and liquidity
+//! pool shares as ERC-20 tokens for Ethereum wallets, over the balances and
+//! allowances the dex keeps for the accounts backing evm addresses:
+//! * `totalSupply() returns (uint256)`
+//! * `balanceOf(address) returns (uint256)`
+//! * `allowance(address owner, address spender) returns (uint256)`
+//! * `transfer(address to, uint256 value) returns (bool)`
+//! * `approve(address spender, uint256 value) returns (bool)`
+//! * `transferFrom(address from, address to, uint256 value) returns (bool)`
+//!
+//! which log `Transfer` and `Approval` as ERC-20 contracts do.
+//!
+//! At `STAKING_PRECOMPILE`, staking for accounts backing evm addresses that
+//! are their own controller:
+//! * `bonded(address stash) returns (uint256)`
+//! * `bond(uint256 value) returns (bool)`
+//! * `bondExtra(uint256 value) returns (bool)`
+//! * `unbond(uint256 value) returns (bool)`
+//! * `nominate(bytes32[] targets) returns (bool)`, with the account ids of the
+//!   validators nominated.
+//! * `chill() returns (bool)`
+//!
+//! which return true once the call is dispatched, or for a contract, once it
+//! is going to be.
+
+use sp_runtime::traits::StaticLookup;
+use sp_core::{H160, H256, U256};
+use sp_io::hashing::keccak_256;
+use sp_std::prelude::*;
+use rust_evm::{ExitError, Transfer};
+use crate::evm::{
+	AddressMapping, HashedAddressMapping, Log, PrecompileOutput, Precompiles,
+	precompiles::{
+		charge, encode_words, read_address, read_u256, read_words, selector, split_selector, DeferredWrite,
+	},
+};
+use crate::{AccountId, Balance, Evm, Indices, Moonbeam, Origin, Runtime, Staking};
+use crate::moonbeam::{Asset, Checkpoint};
+
+/// The address of the glmr precompile.
+pub const GLMR_PRECOMPILE: u64 = 1024;
+/// The address of the dex precompile.
+pub const DEX_PRECOMPILE: u64 = 1025;
+/// The address of the ERC-20 contract of the dex token.
+pub const TOKEN_PRECOMPILE: u64 = 1026;
+/// The address of the ERC-20 contract of the liquidity pool shares.
+pub const LIQUID_PRECOMPILE: u64 = 1027;
+/// The address of the staking precompile.
+pub const STAKING_PRECOMPILE: u64 = 1028;
+
+/// Reading a balance or the pool, priced like a storage read.
+const READ_GAS: usize = 800;
+/// Moving glmr or a dex asset between two accounts.
+const TRANSFER_GAS: usize = 9_000;
+/// Writing an allowance.
+const APPROVE_GAS: usize = 5_000;
+/// A trade with the pool, which writes both balances, the pool and the prices.
+const TRADE_GAS: usize = 40_000;
+/// Bonding, unbonding or changing the nominations of a stash, which writes its
+/// ledger and locks.
+const STAKE_GAS: usize = 40_000;
+
+/// The glmr, dex, dex asset and staking precompiles.
+pub struct MoonbeamPrecompiles;
+
+impl Precompiles for MoonbeamPrecompiles {
+	fn execute(
+		caller: Option<H160>,
+		address: H160,
+		input: &[u8],
+		target_gas: Option<usize>,
+	) -> Option<Result<PrecompileOutput, ExitError>> {
+		if address == H160::from_low_u64_be(GLMR_PRECOMPILE) {
+			Some(glmr(caller, input, target_gas))
+		} else if address == H160::from_low_u64_be(DEX_PRECOMPILE) {
+			Some(dex(caller, input, target_gas))
+		} else if address == H160::from_low_u64_be(TOKEN_PRECOMPILE) {
+			Some(erc20(Asset::Token, address, caller, input, target_gas))
+		} else if address == H160::from_low_u64_be(LIQUID_PRECOMPILE) {
+			Some(erc20(Asset::Liquid, address, caller, input, target_gas))
+		} else if address == H160::from_low_u64_be(STAKING_PRECOMPILE) {
+			Some(staking(caller, input, target_gas))
+		} else {
+			None
+		}
+	}
+}
+
+fn glmr(caller: Option<H160>, input: &[u8], target_gas: Option<usize>) -> Result<PrecompileOutput, ExitError> {
+	let (function, arguments) = split_selector(input)?;
+
+	if function == selector("balanceOf(address)") {
+		let used_gas = charge(READ_GAS, target_gas)?;
+		let owner = read_address(arguments, 0)?;
+		Ok(PrecompileOutput::new(encode_words(&[Evm::balance_of(owner)]), used_gas))
+	} else if function == selector("transfer(address,uint256)") {
+		let used_gas = charge(TRANSFER_GAS, target_gas)?;
+		let from = caller_of(caller)?;
+		let to = read_address(arguments, 0)?;
+		let value = read_u256(arguments, 1)?;
+
+		let mut output = PrecompileOutput::new(encode_words(&[U256::one()]), used_gas);
+		output.transfers.push(Transfer { source: from, target: to, value });
+		output.logs.push(erc20_log(H160::from_low_u64_be(GLMR_PRECOMPILE), b"Transfer(address,address,uint256)", from, to, value));
+		Ok(output)
+	} else {
+		Err(ExitError::Other("Unknown function selector".into()))
+	}
+}
+
+fn dex(caller: Option<H160>, input: &[u8], target_gas: Option<usize>) -> Result<PrecompileOutput, ExitError> {
+	let (function, arguments) = split_selector(input)?;
+	let mut undo = None;
+
+	let (words, used_gas) = if function == selector("getReserves()") {
+		let used_gas = charge(READ_GAS, target_gas)?;
+		(vec![Moonbeam::glmr_pool_balance(), Moonbeam::token_pool_balance()], used_gas)
+	} else if function == selector("glmrBalanceOf(address)") {
+		let used_gas = charge(READ_GAS, target_gas)?;
+		(vec![Moonbeam::glmr_balance_of(account(read_address(arguments, 0)?))], used_gas)
+	} else if function == selector("tokenBalanceOf(address)") {
+		let used_gas = charge(READ_GAS, target_gas)?;
+		(vec![Moonbeam::token_balance_of(account(read_address(arguments, 0)?))], used_gas)
+	} else if function == selector("quoteGlmrToToken(uint256)") {
+		let used_gas = charge(READ_GAS, target_gas)?;
+		let quote = Moonbeam::quote_glmr_to_token(balance(read_u256(arguments, 0)?))
+			.ok_or_else(|| ExitError::Other("Error caluculating number of tokens in trade".into()))?;
+		(vec![quote], used_gas)
+	} else if function == selector("quoteTokenToGlmr(uint256)") {
+		let used_gas = charge(READ_GAS, target_gas)?;
+		let quote = Moonbeam::quote_token_to_glmr(balance(read_u256(arguments, 0)?))
+			.ok_or_else(|| ExitError::Other("Error caluculating number of GLMR in trade".into()))?;
+		(vec![quote], used_gas)
+	} else if function == selector("tradeGlmrToToken(uint256,uint256)") {
+		let used_gas = charge(TRADE_GAS, target_gas)?;
+		let sender = account(caller_of(caller)?);
+		let glmr_value = balance(read_u256(arguments, 0)?);
+		let min_tokens = balance(read_u256(arguments, 1)?);
+		let checkpoint = Moonbeam::checkpoint(&[sender.clone()], &[]);
+		let tokens_bought = Moonbeam::swap_glmr_for_tokens(&sender, glmr_value, min_tokens)
+			.map_err(|e| ExitError::Other(e.into()))?;
+		undo = Some(checkpoint);
+		(vec![tokens_bought], used_gas)
+	} else if function == selector("tradeTokenToGlmr(uint256,uint256)") {
+		let used_gas = charge(TRADE_GAS, target_gas)?;
+		let sender = account(caller_of(caller)?);
+		let token_value = balance(read_u256(arguments, 0)?);
+		let min_glmr = balance(read_u256(arguments, 1)?);
+		let checkpoint = Moonbeam::checkpoint(&[sender.clone()], &[]);
+		let glmr_bought = Moonbeam::swap_tokens_for_glmr(&sender, token_value, min_glmr)
+			.map_err(|e| ExitError::Other(e.into()))?;
+		undo = Some(checkpoint);
+		(vec![glmr_bought], used_gas)
+	} else {
+		return Err(ExitError::Other("Unknown function selector".into()))
+	};
+
+	let words: Vec<U256> = words.into_iter().map(U256::from).collect();
+	let mut output = PrecompileOutput::new(encode_words(&words), used_gas);
+	output.undo = undo.map(restore);
+	Ok(output)
+}
+
+fn erc20(asset: Asset, address: H160, caller: Option<H160>, input: &[u8], target_gas: Option<usize>) -> Result<PrecompileOutput, ExitError> {
+	let (function, arguments) = split_selector(input)?;
+
+	if function == selector("totalSupply()") {
+		let used_gas = charge(READ_GAS, target_gas)?;
+		Ok(PrecompileOutput::new(encode_words(&[U256::from(Moonbeam::asset_supply(asset))]), used_gas))
+	} else if function == selector("balanceOf(address)") {
+		let used_gas = charge(READ_GAS, target_gas)?;
+		let owner = account(read_address(arguments, 0)?);
+		Ok(PrecompileOutput::new(encode_words(&[U256::from(Moonbeam::asset_balance_of(asset, &owner))]), used_gas))
+	} else if function == selector("allowance(address,address)") {
+		let used_gas = charge(READ_GAS, target_gas)?;
+		let owner = account(read_address(arguments, 0)?);
+		let spender = account(read_address(arguments, 1)?);
+		Ok(PrecompileOutput::new(encode_words(&[U256::from(Moonbeam::allowance(&owner, &(asset, spender)))]), used_gas))
+	} else if function == selector("transfer(address,uint256)") {
+		let used_gas = charge(TRANSFER_GAS, target_gas)?;
+		let from = caller_of(caller)?;
+		let to = read_address(arguments, 0)?;
+		let value = read_u256(arguments, 1)?;
+
+		let checkpoint = Moonbeam::checkpoint(&[account(from), account(to)], &[]);
+		Moonbeam::transfer_asset(asset, &account(from), &account(to), balance(value))
+			.map_err(|e| ExitError::Other(e.into()))?;
+
+		let mut output = PrecompileOutput::new(encode_words(&[U256::one()]), used_gas);
+		output.undo = Some(restore(checkpoint));
+		output.logs.push(erc20_log(address, b"Transfer(address,address,uint256)", from, to, value));
+		Ok(output)
+	} else if function == selector("approve(address,uint256)") {
+		let used_gas = charge(APPROVE_GAS, target_gas)?;
+		let owner = caller_of(caller)?;
+		let spender = read_address(arguments, 0)?;
+		let value = read_u256(arguments, 1)?;
+
+		let checkpoint = Moonbeam::checkpoint(&[], &[(account(owner), (asset, account(spender)))]);
+		Moonbeam::approve(asset, &account(owner), &account(spender), balance(value));
+
+		let mut output = PrecompileOutput::new(encode_words(&[U256::one()]), used_gas);
+		output.undo = Some(restore(checkpoint));
+		output.logs.push(erc20_log(address, b"Approval(address,address,uint256)", owner, spender, value));
+		Ok(output)
+	} else if function == selector("transferFrom(address,address,uint256)") {
+		let used_gas = charge(TRANSFER_GAS + APPROVE_GAS, target_gas)?;
+		let spender = caller_of(caller)?;
+		let from = read_address(arguments, 0)?;
+		let to = read_address(arguments, 1)?;
+		let value = read_u256(arguments, 2)?;
+
+		let checkpoint = Moonbeam::checkpoint(
+			&[account(from), account(to)],
+			&[(account(from), (asset, account(spender)))],
+		);
+		Moonbeam::transfer_asset_from(asset, &account(spender), &account(from), &account(to), balance(value))
+			.map_err(|e| ExitError::Other(e.into()))?;
+
+		let mut output = PrecompileOutput::new(encode_words(&[U256::one()]), used_gas);
+		output.undo = Some(restore(checkpoint));
+		output.logs.push(erc20_log(address, b"Transfer(address,address,uint256)", from, to, value));
+		Ok(output)
+	} else {
+		Err(ExitError::Other("Unknown function selector".into()))
+	}
+}
+
+fn staking(caller: Option<H160>, input: &[u8], target_gas: Option<usize>) -> Result<PrecompileOutput, ExitError> {
+	let (function, arguments) = split_selector(input)?;
+
+	if function == selector("bonded(address)") {
+		let used_gas = charge(READ_GAS, target_gas)?;
+		let active = Staking::bonded(account(read_address(arguments, 0)?))
+			.and_then(Staking::ledger)
+			.map_or(0, |ledger| ledger.active);
+		return Ok(PrecompileOutput::new(encode_words(&[U256::from(active)]), used_gas))
+	}
+
+	let (write, used_gas) = if function == selector("bond(uint256)") {
+		let used_gas = charge(STAKE_GAS, target_gas)?;
+		let stash = account(caller_of(caller)?);
+		let value = balance(read_u256(arguments, 0)?);
+		let controller = Indices::unlookup(stash.clone());
+		(defer(move || Staking::bond(Origin::signed(stash), controller, value, staking::RewardDestination::Staked)), used_gas)
+	} else if function == selector("bondExtra(uint256)") {
+		let used_gas = charge(STAKE_GAS, target_gas)?;
+		let stash = account(caller_of(caller)?);
+		let value = balance(read_u256(arguments, 0)?);
+		(defer(move || Staking::bond_extra(Origin::signed(stash), value)), used_gas)
+	} else if function == selector("unbond(uint256)") {
+		let used_gas = charge(STAKE_GAS, target_gas)?;
+		let controller = account(caller_of(caller)?);
+		let value = balance(read_u256(arguments, 0)?);
+		(defer(move || Staking::unbond(Origin::signed(controller), value)), used_gas)
+	} else if function == selector("nominate(bytes32[])") {
+		let targets = read_words(arguments, 0)?;
+		let used_gas = charge(STAKE_GAS + READ_GAS * targets.len(), target_gas)?;
+		let controller = account(caller_of(caller)?);
+		let targets = targets.into_iter().map(|target| {
+			let mut id = [0u8; 32];
+			target.to_big_endian(&mut id);
+			Indices::unlookup(AccountId::from(id))
+		}).collect();
+		(defer(move || Staking::nominate(Origin::signed(controller), targets)), used_gas)
+	} else if function == selector("chill()") {
+		let used_gas = charge(STAKE_GAS, target_gas)?;
+		let controller = account(caller_of(caller)?);
+		(defer(move || Staking::chill(Origin::signed(controller))), used_gas)
+	} else {
+		return Err(ExitError::Other("Unknown function selector".into()))
+	};
+
+	// staking can not be put back should a contract revert, so it waits
+	let mut output = PrecompileOutput::new(encode_words(&[U256::one()]), used_gas);
+	output.deferred.push(write);
+	Ok(output)
+}
+
+/// Defers a staking call for a contract, see `PrecompileOutput::deferred`.
+fn defer(write: impl FnOnce() -> Result<(), &'static str> + 'static) -> DeferredWrite {
+	Box::new(write)
+}
+
+/// Puts back what the dex wrote since `checkpoint` was taken.
+fn restore(checkpoint: Checkpoint<Runtime>) -> Box<dyn FnOnce()> {
+	Box::new(move || checkpoint.restore())
+}
+
+/// An ERC-20 `Transfer` or `Approval` log, with both addresses indexed.
+fn erc20_log(address: H160, event: &[u8], first: H160, second: H160, value: U256) -> Log {
+	Log {
+		address,
+		topics: vec![H256::from(keccak_256(event)), H256::from(first), H256::from(second)],
+		data: encode_words(&[value]),
+	}
+}
+
+/// Whoever the precompile acts for.
+fn caller_of(caller: Option<H160>) -> Result<H160, ExitError> {
+	caller.ok_or_else(|| ExitError::Other("Can not be static or delegate called".into()))
+}
+
+/// The account backing `address`.
+fn account(address: H160) -> AccountId {
+	<HashedAddressMapping as AddressMapping<AccountId>>::into_account_id(address)
+}
+
+/// An amount as a balance, clamped to the largest balance, so the largest
+/// uint256, which wallets approve as an unlimited allowance, stays unlimited.
+fn balance(value: U256) -> Balance {
+	if value > U256::from(Balance::max_value()) {
+		return Balance::max_value()
+	}
+	value.low_u128()
+}
@@ -0,0 +1,139 @@
+//! # Block Rewards Module
+//!
+//! Pays the author of every block, so that running a validator is worth it
+//! between staking payouts.
+//!
+//! The author is told to the module by the authorship module when the block
+//! starts, and is paid the `BlockReward`, which is minted.  The module is also
+//! an `OnUnbalanced` handler: the runtime gives it a share of the transaction
+//! fees, which it pays to the author of the block the transactions are in.
+//! Fees paid while no author is known are burned.
+//!
+//! The blocks, rewards and fees of each author are kept in `Stats`.
+
+use frame_support::{
+	decl_module, decl_storage, decl_event, dispatch,
+	traits::{Currency, EnsureOrigin, Imbalance, OnUnbalanced},
+};
+use codec::{Encode, Decode};
+use sp_runtime::traits::{Saturating, Zero};
+
+#[cfg(test)]
+mod mock;
+#[cfg(test)]
+mod tests;
+
+type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+type NegativeImbalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::NegativeImbalance;
+
+/// What an author has been paid for the blocks it authored.
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct AuthorStats<Balance, BlockNumber> {
+	/// The number of blocks authored.
+	pub blocks: u32,
+	/// The last block authored.
+	pub last_block: BlockNumber,
+	/// The block rewards paid.
+	pub rewards: Balance,
+	/// The transaction fees paid.
+	pub fees: Balance,
+}
+
+pub trait Trait: system::Trait {
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+	/// The currency rewards are paid in.
+	type Currency: Currency<Self::AccountId>;
+	/// The origin allowed to change the block reward.
+	type AdminOrigin: EnsureOrigin<Self::Origin>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Rewards {
+		/// Minted for the author of every block.
+		BlockReward get(block_reward) config(): BalanceOf<T>;
+		/// What each author has been paid.
+		Stats get(author_stats): map T::AccountId => AuthorStats<BalanceOf<T>, T::BlockNumber>;
+		/// The author of the current block, if known.
+		Author get(author): Option<T::AccountId>;
+		/// The reward and fees paid to the author of the current block so far.
+		Paid: (BalanceOf<T>, BalanceOf<T>);
+	}
+}
+
+decl_event!(
+	pub enum Event<T> where
+		AccountId = <T as system::Trait>::AccountId,
+		Balance = BalanceOf<T>
+	{
+		/// The author of a block was paid a block reward and fees.
+		AuthorRewarded(AccountId, Balance, Balance),
+		/// The block reward changed.
+		BlockRewardChanged(Balance),
+	}
+);
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event() = default;
+
+		/// Changes the reward minted for every block.
+		/// Only callable by the admin origin.
+		fn set_block_reward(origin, #[compact] reward: BalanceOf<T>) -> dispatch::Result {
+			T::AdminOrigin::ensure_origin(origin)?;
+
+			<BlockReward<T>>::put(reward);
+			Self::deposit_event(RawEvent::BlockRewardChanged(reward));
+
+			Ok(())
+		}
+
+		fn on_finalize() {
+			let (reward, fees) = <Paid<T>>::take();
+			if let Some(author) = <Author<T>>::take() {
+				Self::deposit_event(RawEvent::AuthorRewarded(author, reward, fees));
+			}
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Pays `author` the block reward, and makes it the author fees are paid to.
+	fn reward_author(author: T::AccountId) {
+		// an author whose account does not exist yet is only paid a reward that
+		// can create it
+		let reward = T::Currency::deposit_creating(&author, Self::block_reward()).peek();
+
+		let now = <system::Module<T>>::block_number();
+		<Stats<T>>::mutate(&author, |stats| {
+			stats.blocks = stats.blocks.saturating_add(1);
+			stats.last_block = now;
+			stats.rewards = stats.rewards.saturating_add(reward);
+		});
+		<Paid<T>>::put((reward, BalanceOf::<T>::zero()));
+		<Author<T>>::put(author);
+	}
+}
+
+impl<T: Trait> authorship::EventHandler<T::AccountId, T::BlockNumber> for Module<T> {
+	fn note_author(author: T::AccountId) {
+		Self::reward_author(author)
+	}
+
+	fn note_uncle(_author: T::AccountId, _age: T::BlockNumber) {}
+}
+
+/// Pays fees to the author of the current block.
+impl<T: Trait> OnUnbalanced<NegativeImbalanceOf<T>> for Module<T> {
+	fn on_unbalanced(fees: NegativeImbalanceOf<T>) {
+		let author = match Self::author() {
+			Some(author) => author,
+			None => return,
+		};
+
+		let amount = fees.peek();
+		T::Currency::resolve_creating(&author, fees);
+		<Stats<T>>::mutate(&author, |stats| stats.fees = stats.fees.saturating_add(amount));
+		<Paid<T>>::mutate(|(_, block_fees)| *block_fees = block_fees.saturating_add(amount));
+	}
+}
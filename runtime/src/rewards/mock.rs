@@ -0,0 +1,81 @@
+//! Test runtime for the rewards module.
+
+use frame_support::{impl_outer_origin, parameter_types, weights::Weight};
+use sp_core::H256;
+use sp_runtime::{Perbill, testing::Header, traits::{BlakeTwo256, IdentityLookup}};
+use super::{GenesisConfig, Module, Trait};
+
+impl_outer_origin! {
+	pub enum Origin for Test {}
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Test;
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: Weight = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::one();
+}
+
+impl system::Trait for Test {
+	type Origin = Origin;
+	type Call = ();
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = ();
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+}
+
+parameter_types! {
+	pub const ExistentialDeposit: u128 = 10;
+	pub const TransferFee: u128 = 0;
+	pub const CreationFee: u128 = 0;
+}
+
+impl balances::Trait for Test {
+	type Balance = u128;
+	type OnFreeBalanceZero = ();
+	type OnNewAccount = ();
+	type Event = ();
+	type DustRemoval = ();
+	type TransferPayment = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type TransferFee = TransferFee;
+	type CreationFee = CreationFee;
+}
+
+impl Trait for Test {
+	type Event = ();
+	type Currency = Balances;
+	type AdminOrigin = system::EnsureRoot<u64>;
+}
+
+pub type Balances = balances::Module<Test>;
+pub type Rewards = Module<Test>;
+
+pub const BLOCK_REWARD: u128 = 100;
+
+/// Build test externalities with a block reward of `BLOCK_REWARD`, and
+/// account 1 holding 1000.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	let mut storage = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	balances::GenesisConfig::<Test> {
+		balances: vec![(1, 1000)],
+		vesting: vec![],
+	}.assimilate_storage(&mut storage).unwrap();
+	GenesisConfig::<Test> {
+		block_reward: BLOCK_REWARD,
+	}.assimilate_storage(&mut storage).unwrap();
+	storage.into()
+}
@@ -0,0 +1,74 @@
+//! Tests for the rewards module.
+
+use super::*;
+use super::mock::*;
+use authorship::EventHandler;
+use frame_support::{assert_ok, traits::{ExistenceRequirement, WithdrawReason}};
+use sp_runtime::traits::OnFinalize;
+
+fn fees(amount: u128) -> NegativeImbalanceOf<Test> {
+	Balances::withdraw(&1, amount, WithdrawReason::Fee.into(), ExistenceRequirement::KeepAlive).unwrap()
+}
+
+#[test]
+fn authors_are_paid_the_block_reward_and_the_fees_of_their_blocks() {
+	new_test_ext().execute_with(|| {
+		system::Module::<Test>::set_block_number(5);
+		Rewards::note_author(2);
+		assert_eq!(Balances::free_balance(&2), BLOCK_REWARD);
+		assert_eq!(Rewards::author(), Some(2));
+
+		Rewards::on_unbalanced(fees(30));
+		Rewards::on_unbalanced(fees(20));
+		assert_eq!(Balances::free_balance(&1), 950);
+		assert_eq!(Balances::free_balance(&2), BLOCK_REWARD + 50);
+		assert_eq!(Balances::total_issuance(), 1000 + BLOCK_REWARD);
+
+		Rewards::on_finalize(5);
+		assert_eq!(Rewards::author(), None);
+		assert_eq!(Rewards::author_stats(&2), AuthorStats {
+			blocks: 1,
+			last_block: 5,
+			rewards: BLOCK_REWARD,
+			fees: 50,
+		});
+
+		system::Module::<Test>::set_block_number(6);
+		Rewards::note_author(2);
+		Rewards::on_finalize(6);
+		assert_eq!(Rewards::author_stats(&2).blocks, 2);
+		assert_eq!(Rewards::author_stats(&2).last_block, 6);
+		assert_eq!(Rewards::author_stats(&2).rewards, 2 * BLOCK_REWARD);
+	});
+}
+
+#[test]
+fn fees_paid_without_an_author_are_burned() {
+	new_test_ext().execute_with(|| {
+		Rewards::on_unbalanced(fees(30));
+		assert_eq!(Balances::total_issuance(), 970);
+
+		Rewards::note_author(2);
+		Rewards::on_finalize(1);
+		Rewards::on_unbalanced(fees(30));
+		assert_eq!(Rewards::author_stats(&2).fees, 0);
+		assert_eq!(Balances::total_issuance(), 940 + BLOCK_REWARD);
+	});
+}
+
+#[test]
+fn only_the_admin_changes_the_block_reward() {
+	new_test_ext().execute_with(|| {
+		assert!(Rewards::set_block_reward(Origin::signed(1), 5).is_err());
+		assert_ok!(Rewards::set_block_reward(system::RawOrigin::Root.into(), 5));
+
+		// too little to create the author's account
+		Rewards::note_author(3);
+		assert_eq!(Balances::free_balance(&3), 0);
+		assert_eq!(Rewards::author_stats(&3).blocks, 1);
+		assert_eq!(Rewards::author_stats(&3).rewards, 0);
+
+		Rewards::note_author(1);
+		assert_eq!(Balances::free_balance(&1), 1005);
+	});
+}
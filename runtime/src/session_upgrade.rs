@@ -0,0 +1,111 @@
+//! # Session Upgrade Module
+//!
+//! Upgrades the session keys stored before the runtime added keys to them, so
+//! that the session module can still decode them.
+//!
+//! In the first block after the upgrade, the next keys of every account in
+//! `KeyOwners`, and the keys queued for the next session, are made into the
+//! current keys with `UpgradeKeys`.  The queued keys are marked as changed, so
+//! every session handler takes the upgraded keys at the next session.  Accounts
+//! that are not in `KeyOwners` have to set their keys again.  Chains built from
+//! genesis start upgraded.
+
+use frame_support::{
+	decl_module, decl_storage, traits::Get, Parameter, StorageDoubleMap, StorageValue,
+};
+use sp_runtime::traits::Convert;
+use sp_std::prelude::*;
+
+/// The first key of the session module's `NextKeys`.
+const DEDUP_KEY_PREFIX: &[u8] = b":session:keys";
+
+pub trait Trait: session::Trait {
+	/// The session keys before the upgrade.
+	type OldKeys: Parameter;
+	/// Makes a validator's keys from its keys before the upgrade.
+	type UpgradeKeys: Convert<Self::OldKeys, Self::Keys>;
+	/// The accounts that may have set keys before the upgrade.
+	type KeyOwners: Get<Vec<Self::AccountId>>;
+}
+
+/// The session module's storage, read with the keys before the upgrade.
+pub mod old {
+	use frame_support::decl_storage;
+	use sp_std::{marker::PhantomData, prelude::*};
+	use super::Trait;
+
+	pub struct Module<T: Trait>(PhantomData<T>);
+
+	decl_storage! {
+		trait Store for Module<T: Trait> as Session {
+			/// The keys queued for the next session.
+			pub QueuedKeys: Vec<(T::ValidatorId, T::OldKeys)>;
+			/// The keys of each validator, under `DEDUP_KEY_PREFIX`.
+			pub NextKeys: double_map hasher(twox_64_concat) Vec<u8>, blake2_256(T::ValidatorId)
+				=> Option<T::OldKeys>;
+		}
+	}
+}
+
+/// The session module's storage, written with the upgraded keys.
+mod upgraded {
+	use frame_support::decl_storage;
+	use sp_std::{marker::PhantomData, prelude::*};
+	use super::Trait;
+
+	pub struct Module<T: Trait>(PhantomData<T>);
+
+	decl_storage! {
+		trait Store for Module<T: Trait> as Session {
+			/// The keys queued for the next session.
+			pub QueuedKeys: Vec<(T::ValidatorId, T::Keys)>;
+			/// Whether the keys queued for the next session changed.
+			pub QueuedChanged: bool;
+		}
+	}
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as SessionUpgrade {
+		/// Whether the stored session keys have been upgraded.
+		pub Upgraded get(upgraded) build(|_| true): bool;
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn on_initialize() {
+			if !Self::upgraded() {
+				Self::upgrade_keys();
+				Upgraded::put(true);
+			}
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	fn upgrade_keys() {
+		for owner in T::KeyOwners::get() {
+			let validator = match T::ValidatorIdOf::convert(owner.clone()) {
+				Some(validator) => validator,
+				None => continue,
+			};
+			if let Some(keys) = <old::NextKeys<T>>::get(DEDUP_KEY_PREFIX, &validator) {
+				// The owner already owns the keys it had, and nobody owns the
+				// ones that are new, so setting them does not fail.
+				let _ = <session::Module<T>>::set_keys(
+					system::RawOrigin::Signed(owner).into(),
+					T::UpgradeKeys::convert(keys),
+					Vec::new(),
+				);
+			}
+		}
+
+		let queued = <old::QueuedKeys<T>>::get()
+			.into_iter()
+			.map(|(validator, keys)| (validator, T::UpgradeKeys::convert(keys)))
+			.collect::<Vec<_>>();
+		<upgraded::QueuedKeys<T>>::put(queued);
+		upgraded::QueuedChanged::put(true);
+	}
+}
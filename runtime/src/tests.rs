@@ -0,0 +1,902 @@
+//! Tests for the runtime as a whole.
+//!
+//! Privileged calls are dispatched through the governance origins the runtime
+//! is configured with, so these hold with or without the `no-sudo` feature.
+
+use super::*;
+use codec::Encode;
+use contracts::ContractAddressFor;
+use frame_support::{assert_ok, traits::Currency, weights::GetDispatchInfo, StorageDoubleMap, StorageLinkedMap};
+use moonbeam::{Asset, MarketStatus};
+use sp_core::H256;
+use sp_runtime::traits::{Hash as HashT, OnFinalize, OnInitialize};
+
+const INITIAL_BALANCE: Balance = 1_000_000;
+
+fn account(seed: u8) -> AccountId {
+	AccountId::from([seed; 32])
+}
+
+fn council() -> Vec<AccountId> {
+	(1..=3).map(account).collect()
+}
+
+fn new_test_ext() -> sp_io::TestExternalities {
+	let mut t = system::GenesisConfig::default().build_storage::<Runtime>().unwrap();
+	collective::GenesisConfig::<Runtime, CouncilCollective> {
+		members: council(),
+		phantom: Default::default(),
+	}.assimilate_storage(&mut t).unwrap();
+	t.into()
+}
+
+/// Proposes `call` as a council motion and has `ayes` members vote for it,
+/// counting the proposer, which executes it once the threshold is met.
+fn council_motion(ayes: u32, call: Call) {
+	let members = council();
+	if ayes < 2 {
+		assert_ok!(Council::propose(Origin::signed(members[0].clone()), ayes, Box::new(call)));
+		return
+	}
+
+	let hash = BlakeTwo256::hash_of(&call);
+	assert_ok!(Council::propose(Origin::signed(members[0].clone()), ayes, Box::new(call)));
+	let index = Council::proposal_count() - 1;
+	for member in members.iter().take(ayes as usize).skip(1) {
+		assert_ok!(Council::vote(Origin::signed(member.clone()), hash, index, true));
+	}
+}
+
+fn dex(call: moonbeam::Call<Runtime>) -> Call {
+	Call::Moonbeam(call)
+}
+
+#[test]
+fn council_majority_can_make_every_dex_admin_call() {
+	new_test_ext().execute_with(|| {
+		let (alice, bob) = (account(10), account(11));
+
+		council_motion(2, dex(moonbeam::Call::set_glmr_balance(alice.clone(), INITIAL_BALANCE)));
+		council_motion(2, dex(moonbeam::Call::set_token_balance(alice.clone(), INITIAL_BALANCE)));
+		assert_eq!(Moonbeam::glmr_balance_of(&alice), INITIAL_BALANCE);
+		assert_eq!(Moonbeam::token_balance_of(&alice), INITIAL_BALANCE);
+
+		council_motion(2, dex(moonbeam::Call::transfer_glmr(alice.clone(), bob.clone(), 1_000)));
+		council_motion(2, dex(moonbeam::Call::transfer_token(alice.clone(), bob.clone(), 4_000)));
+		assert_eq!(Moonbeam::glmr_balance_of(&bob), 1_000);
+		assert_eq!(Moonbeam::token_balance_of(&bob), 4_000);
+
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(alice.clone()), 10_000, 40_000));
+		let liquid = Moonbeam::liquid_balance_of(&alice);
+		council_motion(2, dex(moonbeam::Call::transfer_liquid(alice.clone(), bob.clone(), liquid / 2)));
+		assert_eq!(Moonbeam::liquid_balance_of(&bob), liquid / 2);
+
+		council_motion(2, dex(moonbeam::Call::set_trading_fee(Permill::from_parts(1_000))));
+		assert_eq!(Moonbeam::trading_fee(), Permill::from_parts(1_000));
+
+		council_motion(2, dex(moonbeam::Call::set_market_status(MarketStatus::Paused)));
+		assert_eq!(Moonbeam::market_status(), MarketStatus::Paused);
+	});
+}
+
+#[test]
+fn council_minority_can_not_make_dex_admin_calls() {
+	new_test_ext().execute_with(|| {
+		council_motion(1, dex(moonbeam::Call::set_market_status(MarketStatus::Paused)));
+		assert_eq!(Moonbeam::market_status(), MarketStatus::Open);
+
+		assert!(Moonbeam::set_market_status(Origin::signed(account(1)), MarketStatus::Paused).is_err());
+		assert!(Moonbeam::set_glmr_balance(Origin::signed(account(1)), account(1), INITIAL_BALANCE).is_err());
+		assert_eq!(Moonbeam::glmr_balance_of(&account(1)), 0);
+	});
+}
+
+/// Has the council table `call` as a fast tracked majority referendum, votes
+/// it through and ends it, which enacts it.
+fn referendum(call: Call) {
+	let voter = account(10);
+	let _ = Balances::deposit_creating(&voter, 1_000 * DOLLARS);
+	let preimage = call.encode();
+	let hash = BlakeTwo256::hash(&preimage[..]);
+	assert_ok!(Democracy::note_preimage(Origin::signed(voter.clone()), preimage));
+
+	council_motion(3, Call::Democracy(democracy::Call::external_propose_majority(hash)));
+	council_motion(3, Call::Democracy(democracy::Call::fast_track(hash, EmergencyVotingPeriod::get(), 0)));
+	let index = Democracy::referendum_count() - 1;
+	let aye = democracy::Vote { aye: true, conviction: democracy::Conviction::Locked1x };
+	assert_ok!(Democracy::vote(Origin::signed(voter), index, aye));
+
+	let end = system::Module::<Runtime>::block_number() + EmergencyVotingPeriod::get();
+	system::Module::<Runtime>::set_block_number(end);
+	<Democracy as OnInitialize<BlockNumber>>::on_initialize(end);
+}
+
+#[test]
+fn referenda_can_make_dex_admin_calls() {
+	new_test_ext().execute_with(|| {
+		referendum(dex(moonbeam::Call::set_market_status(MarketStatus::Delisted)));
+		assert_eq!(Moonbeam::market_status(), MarketStatus::Delisted);
+
+		referendum(dex(moonbeam::Call::set_trading_fee(Permill::zero())));
+		assert_eq!(Moonbeam::trading_fee(), Permill::zero());
+	});
+}
+
+#[test]
+fn referenda_can_change_the_oracle_price_sources() {
+	new_test_ext().execute_with(|| {
+		let source = b"https://prices.example.com/glmr".to_vec();
+		assert!(Oracle::set_glmr_price_source(Origin::signed(account(1)), source.clone()).is_err());
+
+		referendum(Call::Oracle(oracle::Call::set_price_source(source.clone())));
+		assert_eq!(Oracle::price_source(), source);
+
+		referendum(Call::Oracle(oracle::Call::set_glmr_price_source(source.clone())));
+		assert_eq!(Oracle::glmr_price_source(), source);
+
+		// as can a council majority
+		let source = b"https://prices.example.com/token".to_vec();
+		council_motion(2, Call::Oracle(oracle::Call::set_price_source(source.clone())));
+		assert_eq!(Oracle::price_source(), source);
+	});
+}
+
+#[test]
+fn block_author_and_treasury_take_shares_of_transaction_fees() {
+	new_test_ext().execute_with(|| {
+		let author = account(10);
+		<Rewards as authorship::EventHandler<AccountId, BlockNumber>>::note_author(author.clone());
+
+		DealWithFees::on_unbalanced(balances::NegativeImbalance::new(1_000 * DOLLARS));
+		assert_eq!(Treasury::pot(), 200 * DOLLARS);
+		assert_eq!(Balances::free_balance(&author), 300 * DOLLARS);
+		assert_eq!(Rewards::author_stats(&author).fees, 300 * DOLLARS);
+	});
+}
+
+#[test]
+fn dex_protocol_fees_are_paid_into_the_treasury() {
+	new_test_ext().execute_with(|| {
+		let (provider, trader) = (account(10), account(11));
+		let root = || -> Origin { system::RawOrigin::Root.into() };
+		for who in &[provider.clone(), trader.clone()] {
+			let _ = Balances::deposit_creating(who, 2_000_000 * DOLLARS);
+			assert_ok!(Moonbeam::deposit_glmr(Origin::signed(who.clone()), 1_000_000 * DOLLARS));
+			assert_ok!(Moonbeam::set_token_balance(root(), who.clone(), 1_000_000 * DOLLARS));
+		}
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(provider), 100_000 * DOLLARS, 400_000 * DOLLARS));
+		// native glmr backing the glmr seeded into the pool
+		let _ = Balances::deposit_creating(&Moonbeam::account_id(), 100_000 * DOLLARS);
+		assert_eq!(Treasury::pot(), 0);
+		let issuance = Balances::total_issuance();
+
+		// a fifth of the 0.3% fee on a glmr trade is the protocol's
+		assert_ok!(Moonbeam::trade_glmr_to_token(Origin::signed(trader.clone()), 10_000 * DOLLARS, None));
+		<Moonbeam as OnFinalize<BlockNumber>>::on_finalize(1);
+		assert_eq!(Treasury::pot(), 6 * DOLLARS);
+
+		// token fees are sold for glmr first
+		assert_ok!(Moonbeam::trade_token_to_glmr(Origin::signed(trader), 10_000 * DOLLARS, None));
+		<Moonbeam as OnFinalize<BlockNumber>>::on_finalize(2);
+		assert!(Treasury::pot() > 6 * DOLLARS);
+		assert_eq!(Moonbeam::protocol_fees(), (0, 0));
+
+		// the fees come out of the glmr backing the pool, not the deposits
+		assert_eq!(Balances::free_balance(&Moonbeam::account_id()), 2_100_000 * DOLLARS - Treasury::pot());
+		assert_eq!(Moonbeam::native_reserve(), 100_000 * DOLLARS - Treasury::pot());
+		assert_eq!(Balances::total_issuance(), issuance);
+	});
+}
+
+#[test]
+fn dex_protocol_fees_on_genesis_state_are_paid_into_the_treasury() {
+	let (provider, trader) = (account(10), account(11));
+	let mut t = system::GenesisConfig::default().build_storage::<Runtime>().unwrap();
+	balances::GenesisConfig::<Runtime> {
+		balances: vec![(provider.clone(), 1_000 * DOLLARS), (trader.clone(), 1_000 * DOLLARS)],
+		vesting: vec![],
+	}.assimilate_storage(&mut t).unwrap();
+	// as in the chain spec, the dex balances are only in its ledger and the
+	// pool is seeded at genesis
+	moonbeam::GenesisConfig::<Runtime> {
+		glmr_balances: vec![(provider.clone(), 1_000_000 * DOLLARS), (trader.clone(), 1_000_000 * DOLLARS)],
+		token_balances: vec![(provider.clone(), 1_000_000 * DOLLARS), (trader.clone(), 1_000_000 * DOLLARS)],
+		liquidity: vec![(provider, 100_000 * DOLLARS, 400_000 * DOLLARS)],
+	}.assimilate_storage(&mut t).unwrap();
+
+	sp_io::TestExternalities::from(t).execute_with(|| {
+		assert_eq!(Moonbeam::native_reserve(), 100_000 * DOLLARS);
+		assert_eq!(Treasury::pot(), 0);
+
+		assert_ok!(Moonbeam::trade_glmr_to_token(Origin::signed(trader.clone()), 10_000 * DOLLARS, None));
+		<Moonbeam as OnFinalize<BlockNumber>>::on_finalize(1);
+		assert_eq!(Treasury::pot(), 6 * DOLLARS);
+
+		assert_ok!(Moonbeam::trade_token_to_glmr(Origin::signed(trader), 10_000 * DOLLARS, None));
+		<Moonbeam as OnFinalize<BlockNumber>>::on_finalize(2);
+		assert!(Treasury::pot() > 6 * DOLLARS);
+		assert_eq!(Moonbeam::protocol_fees(), (0, 0));
+		assert_eq!(Moonbeam::native_reserve(), 100_000 * DOLLARS - Treasury::pot());
+	});
+}
+
+#[test]
+fn council_approves_treasury_spending() {
+	new_test_ext().execute_with(|| {
+		let (proposer, beneficiary) = (account(10), account(11));
+		let _ = Balances::deposit_creating(&Treasury::account_id(), 1_000 * DOLLARS);
+		let _ = Balances::deposit_creating(&proposer, 10 * DOLLARS);
+
+		assert_ok!(Treasury::propose_spend(Origin::signed(proposer.clone()), 100 * DOLLARS, beneficiary.clone().into()));
+		assert!(Treasury::approve_proposal(Origin::signed(proposer), 0).is_err());
+		council_motion(2, Call::Treasury(treasury::Call::approve_proposal(0)));
+
+		<Treasury as OnFinalize<BlockNumber>>::on_finalize(SpendPeriod::get());
+		assert_eq!(Balances::free_balance(&beneficiary), 100 * DOLLARS);
+		assert_eq!(Treasury::pot(), 900 * DOLLARS);
+	});
+}
+
+/// The account backing an evm address.
+fn evm_account(address: H160) -> AccountId {
+	<evm::HashedAddressMapping as evm::AddressMapping<AccountId>>::into_account_id(address)
+}
+
+fn abi_call(signature: &str, arguments: &[U256]) -> Vec<u8> {
+	let mut input = evm::precompiles::selector(signature).to_vec();
+	input.extend_from_slice(&evm::precompiles::encode_words(arguments));
+	input
+}
+
+fn address_word(address: H160) -> U256 {
+	U256::from_big_endian(address.as_bytes())
+}
+
+const PRECOMPILE_GAS_LIMIT: u32 = 100_000;
+
+/// Sends a transaction from `source` straight to the precompile at `index`.
+fn transact_with_precompile(source: H160, index: u64, input: Vec<u8>) -> ExecutionInfo<Vec<u8>> {
+	let target = H160::from_low_u64_be(index);
+	Evm::execute_call(source, target, input, U256::zero(), PRECOMPILE_GAS_LIMIT, U256::from(MinGasPrice::get())).unwrap()
+}
+
+/// Deploys a contract that calls the precompile at `index` with its input,
+/// and returns or reverts with what the precompile returned.
+fn deploy_forwarder(index: u64) -> H160 {
+	deploy_caller_of(index, &[
+		0x60, 0x33, 0x57, // jump to the return if the call succeeded
+		0x3d, 0x60, 0x00, 0xfd, // revert(0, returndatasize)
+		0x5b, 0x3d, 0x60, 0x00, 0xf3, // return(0, returndatasize)
+	])
+}
+
+/// Deploys a contract that calls the precompile at `index` with its input,
+/// and then reverts with what the precompile returned, whatever it did.
+fn deploy_reverting_forwarder(index: u64) -> H160 {
+	deploy_caller_of(index, &[
+		0x50, // drop whether the call succeeded
+		0x3d, 0x60, 0x00, 0xfd, // revert(0, returndatasize)
+	])
+}
+
+/// Deploys a contract that calls the precompile at `index` with its input,
+/// copies what the precompile returned to memory and goes on with `tail`.
+fn deploy_caller_of(index: u64, tail: &[u8]) -> H160 {
+	let deployer = H160::repeat_byte(0xfe);
+	let _ = Balances::deposit_creating(&evm_account(deployer), 10 * DOLLARS);
+
+	let mut code = vec![
+		0x36, 0x60, 0x00, 0x60, 0x00, 0x37, // calldatacopy(0, 0, calldatasize)
+		0x60, 0x00, 0x60, 0x00, 0x36, 0x60, 0x00, 0x60, 0x00, // no output, the input, no value
+		0x73, // push the precompile address
+	];
+	code.extend_from_slice(H160::from_low_u64_be(index).as_bytes());
+	code.extend_from_slice(&[
+		0x5a, 0xf1, // call with all the gas
+		0x3d, 0x60, 0x00, 0x60, 0x00, 0x3e, // returndatacopy(0, 0, returndatasize)
+	]);
+	code.extend_from_slice(tail);
+	let mut init = vec![
+		0x60, code.len() as u8, 0x80, // push the code size twice
+		0x60, 0x0b, 0x60, 0x00, 0x39, // codecopy(0, 11, size)
+		0x60, 0x00, 0xf3, // return(0, size)
+	];
+	init.extend_from_slice(&code);
+
+	let info = Evm::execute_create(deployer, init, U256::zero(), PRECOMPILE_GAS_LIMIT, U256::from(MinGasPrice::get())).unwrap();
+	assert_eq!(info.status, ExitStatus::Succeeded);
+	info.value
+}
+
+/// Calls `contract` from `source` in a transaction.
+fn call_contract(source: H160, contract: H160, input: Vec<u8>) -> ExecutionInfo<Vec<u8>> {
+	Evm::execute_call(source, contract, input, U256::zero(), PRECOMPILE_GAS_LIMIT, U256::from(MinGasPrice::get())).unwrap()
+}
+
+/// Dry runs a call of the precompile at `index` from a contract, which it acts
+/// for.
+fn call_precompile_from_evm(source: H160, index: u64, input: Vec<u8>) -> ExecutionInfo<Vec<u8>> {
+	let forwarder = deploy_forwarder(index);
+	Evm::dry_run_call(source, forwarder, input, U256::zero(), PRECOMPILE_GAS_LIMIT, U256::from(MinGasPrice::get()))
+}
+
+#[test]
+fn glmr_is_transferred_through_its_precompile() {
+	new_test_ext().execute_with(|| {
+		let (alice, bob) = (H160::repeat_byte(1), H160::repeat_byte(2));
+		let _ = Balances::deposit_creating(&evm_account(alice), 10 * DOLLARS);
+
+		let transfer = abi_call("transfer(address,uint256)", &[address_word(bob), U256::from(3 * DOLLARS)]);
+		let info = transact_with_precompile(alice, GLMR_PRECOMPILE, transfer.clone());
+		assert_eq!(info.status, ExitStatus::Succeeded);
+		assert_eq!(info.value, evm::precompiles::encode_words(&[U256::one()]));
+		assert_eq!(info.used_gas, U256::from(30_000));
+		assert_eq!(info.logs[0].topics[1], H256::from(alice));
+		assert_eq!(info.logs[0].topics[2], H256::from(bob));
+		assert_eq!(Balances::free_balance(&evm_account(bob)), 3 * DOLLARS);
+		assert_eq!(Balances::free_balance(&evm_account(alice)), 7 * DOLLARS - 30_000 * MinGasPrice::get());
+
+		let info = call_precompile_from_evm(alice, GLMR_PRECOMPILE, abi_call("balanceOf(address)", &[address_word(bob)]));
+		assert_eq!(info.value, evm::precompiles::encode_words(&[U256::from(3 * DOLLARS)]));
+
+		// a contract moves its own glmr, not that of whoever sent the transaction
+		assert_eq!(call_precompile_from_evm(alice, GLMR_PRECOMPILE, transfer.clone()).status, ExitStatus::Reverted);
+
+		// a dry run straight to the precompile runs it as its source, so it
+		// estimates the gas of the transaction
+		let info = Evm::dry_run_call(
+			alice,
+			H160::from_low_u64_be(GLMR_PRECOMPILE),
+			transfer,
+			U256::zero(),
+			PRECOMPILE_GAS_LIMIT,
+			U256::from(MinGasPrice::get()),
+		);
+		assert_eq!(info.status, ExitStatus::Succeeded);
+		assert_eq!(info.used_gas, U256::from(30_000));
+	});
+}
+
+#[test]
+fn dex_trades_through_its_precompile_respect_the_minimum_bought() {
+	new_test_ext().execute_with(|| {
+		let (provider, trader) = (account(10), H160::repeat_byte(1));
+		let _ = Balances::deposit_creating(&evm_account(trader), 10 * DOLLARS);
+		council_motion(2, dex(moonbeam::Call::set_glmr_balance(provider.clone(), INITIAL_BALANCE)));
+		council_motion(2, dex(moonbeam::Call::set_token_balance(provider.clone(), INITIAL_BALANCE)));
+		council_motion(2, dex(moonbeam::Call::set_glmr_balance(evm_account(trader), 1_000)));
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(provider), 10_000, 40_000));
+
+		let info = call_precompile_from_evm(trader, DEX_PRECOMPILE, abi_call("quoteGlmrToToken(uint256)", &[U256::from(1_000)]));
+		let quote = U256::from_big_endian(&info.value);
+		assert!(!quote.is_zero());
+
+		let info = transact_with_precompile(trader, DEX_PRECOMPILE, abi_call("tradeGlmrToToken(uint256,uint256)", &[U256::from(1_000), quote + 1]));
+		assert_eq!(info.status, ExitStatus::Failed);
+		assert_eq!(Moonbeam::glmr_balance_of(evm_account(trader)), 1_000);
+
+		let trade = abi_call("tradeGlmrToToken(uint256,uint256)", &[U256::from(1_000), quote]);
+		assert_eq!(call_precompile_from_evm(trader, DEX_PRECOMPILE, trade.clone()).status, ExitStatus::Reverted);
+		let info = transact_with_precompile(trader, DEX_PRECOMPILE, trade);
+		assert_eq!(info.status, ExitStatus::Succeeded);
+		assert_eq!(info.value, evm::precompiles::encode_words(&[quote]));
+		assert_eq!(Moonbeam::glmr_balance_of(evm_account(trader)), 0);
+		assert_eq!(U256::from(Moonbeam::token_balance_of(evm_account(trader))), quote);
+
+		let info = call_precompile_from_evm(trader, DEX_PRECOMPILE, abi_call("getReserves()", &[]));
+		assert_eq!(info.value, evm::precompiles::encode_words(&[
+			U256::from(Moonbeam::glmr_pool_balance()),
+			U256::from(Moonbeam::token_pool_balance()),
+		]));
+	});
+}
+
+#[test]
+fn dex_assets_are_erc20_contracts_over_the_dex_balances() {
+	new_test_ext().execute_with(|| {
+		let (alice, bob, carol) = (H160::repeat_byte(1), H160::repeat_byte(2), H160::repeat_byte(3));
+		for who in &[alice, bob] {
+			let _ = Balances::deposit_creating(&evm_account(*who), 10 * DOLLARS);
+		}
+		council_motion(2, dex(moonbeam::Call::set_token_balance(evm_account(alice), 1_000)));
+
+		let info = call_precompile_from_evm(alice, TOKEN_PRECOMPILE, abi_call("totalSupply()", &[]));
+		assert_eq!(info.value, evm::precompiles::encode_words(&[U256::from(1_000)]));
+
+		let approve = abi_call("approve(address,uint256)", &[address_word(bob), U256::from(400)]);
+		let info = transact_with_precompile(alice, TOKEN_PRECOMPILE, approve);
+		assert_eq!(info.status, ExitStatus::Succeeded);
+		assert_eq!(info.logs[0].topics[0], H256::from(sp_io::hashing::keccak_256(b"Approval(address,address,uint256)")));
+
+		let allowance = abi_call("allowance(address,address)", &[address_word(alice), address_word(bob)]);
+		let info = call_precompile_from_evm(carol, TOKEN_PRECOMPILE, allowance);
+		assert_eq!(info.value, evm::precompiles::encode_words(&[U256::from(400)]));
+
+		let transfer_from = |value| abi_call(
+			"transferFrom(address,address,uint256)",
+			&[address_word(alice), address_word(carol), U256::from(value)],
+		);
+		assert_eq!(transact_with_precompile(bob, TOKEN_PRECOMPILE, transfer_from(500)).status, ExitStatus::Failed);
+		let info = transact_with_precompile(bob, TOKEN_PRECOMPILE, transfer_from(300));
+		assert_eq!(info.status, ExitStatus::Succeeded);
+		assert_eq!(info.logs[0].address, H160::from_low_u64_be(TOKEN_PRECOMPILE));
+		assert_eq!(info.logs[0].topics[1], H256::from(alice));
+		assert_eq!(info.logs[0].topics[2], H256::from(carol));
+		assert_eq!(Moonbeam::token_balance_of(evm_account(alice)), 700);
+		assert_eq!(Moonbeam::token_balance_of(evm_account(carol)), 300);
+
+		let info = call_precompile_from_evm(carol, TOKEN_PRECOMPILE, abi_call("balanceOf(address)", &[address_word(carol)]));
+		assert_eq!(info.value, evm::precompiles::encode_words(&[U256::from(300)]));
+		assert_eq!(
+			call_precompile_from_evm(carol, LIQUID_PRECOMPILE, abi_call("totalSupply()", &[])).value,
+			evm::precompiles::encode_words(&[U256::zero()]),
+		);
+
+		// amounts above the largest balance are clamped to it
+		let approve = abi_call("approve(address,uint256)", &[address_word(carol), U256::max_value()]);
+		assert_eq!(transact_with_precompile(bob, TOKEN_PRECOMPILE, approve).status, ExitStatus::Succeeded);
+		assert_eq!(Moonbeam::allowance(&evm_account(bob), &(Asset::Token, evm_account(carol))), Balance::max_value());
+	});
+}
+
+#[test]
+fn contracts_spend_the_allowances_they_are_given() {
+	new_test_ext().execute_with(|| {
+		let (alice, bob, carol) = (H160::repeat_byte(1), H160::repeat_byte(2), H160::repeat_byte(3));
+		for who in &[alice, bob] {
+			let _ = Balances::deposit_creating(&evm_account(*who), 10 * DOLLARS);
+		}
+		council_motion(2, dex(moonbeam::Call::set_token_balance(evm_account(alice), 1_000)));
+		let spender = deploy_forwarder(TOKEN_PRECOMPILE);
+		let approve = abi_call("approve(address,uint256)", &[address_word(spender), U256::from(400)]);
+		assert_eq!(transact_with_precompile(alice, TOKEN_PRECOMPILE, approve).status, ExitStatus::Succeeded);
+
+		let transfer_from = |value| abi_call(
+			"transferFrom(address,address,uint256)",
+			&[address_word(alice), address_word(carol), U256::from(value)],
+		);
+		let info = call_contract(bob, spender, transfer_from(300));
+		assert_eq!(info.status, ExitStatus::Succeeded);
+		assert_eq!(info.logs[0].address, H160::from_low_u64_be(TOKEN_PRECOMPILE));
+		assert_eq!(info.logs[0].topics[1], H256::from(alice));
+		assert_eq!(info.logs[0].topics[2], H256::from(carol));
+		assert_eq!(Moonbeam::token_balance_of(evm_account(alice)), 700);
+		assert_eq!(Moonbeam::token_balance_of(evm_account(carol)), 300);
+		assert_eq!(Moonbeam::allowance(&evm_account(alice), &(Asset::Token, evm_account(spender))), 100);
+
+		// the allowance is the contract's, not the sender's
+		assert_eq!(call_contract(bob, spender, transfer_from(200)).status, ExitStatus::Reverted);
+		assert_eq!(Moonbeam::allowance(&evm_account(alice), &(Asset::Token, evm_account(bob))), 0);
+		assert_eq!(Moonbeam::token_balance_of(evm_account(alice)), 700);
+
+		// and a contract that reverts spends none of it
+		let reverting = deploy_reverting_forwarder(TOKEN_PRECOMPILE);
+		let approve = abi_call("approve(address,uint256)", &[address_word(reverting), U256::from(400)]);
+		assert_eq!(transact_with_precompile(alice, TOKEN_PRECOMPILE, approve).status, ExitStatus::Succeeded);
+		assert_eq!(call_contract(bob, reverting, transfer_from(300)).status, ExitStatus::Reverted);
+		assert_eq!(Moonbeam::allowance(&evm_account(alice), &(Asset::Token, evm_account(reverting))), 400);
+		assert_eq!(Moonbeam::token_balance_of(evm_account(alice)), 700);
+		assert_eq!(Moonbeam::token_balance_of(evm_account(carol)), 300);
+	});
+}
+
+#[test]
+fn contracts_trade_and_move_glmr_through_the_precompiles() {
+	new_test_ext().execute_with(|| {
+		let (provider, trader, bob) = (account(10), H160::repeat_byte(1), H160::repeat_byte(2));
+		let _ = Balances::deposit_creating(&evm_account(trader), 10 * DOLLARS);
+		council_motion(2, dex(moonbeam::Call::set_glmr_balance(provider.clone(), INITIAL_BALANCE)));
+		council_motion(2, dex(moonbeam::Call::set_token_balance(provider.clone(), INITIAL_BALANCE)));
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(provider), 10_000, 40_000));
+
+		// a contract trades the dex glmr of the account backing it
+		let trading = deploy_forwarder(DEX_PRECOMPILE);
+		council_motion(2, dex(moonbeam::Call::set_glmr_balance(evm_account(trading), 1_000)));
+		let quote = Moonbeam::quote_glmr_to_token(1_000).unwrap();
+		let info = call_contract(trader, trading, abi_call("tradeGlmrToToken(uint256,uint256)", &[U256::from(1_000), U256::from(quote)]));
+		assert_eq!(info.status, ExitStatus::Succeeded);
+		assert_eq!(info.value, evm::precompiles::encode_words(&[U256::from(quote)]));
+		assert_eq!(Moonbeam::glmr_balance_of(evm_account(trading)), 0);
+		assert_eq!(Moonbeam::token_balance_of(evm_account(trading)), quote);
+		assert_eq!(Moonbeam::token_balance_of(evm_account(trader)), 0);
+
+		// and transfers its own glmr, which moves through the evm
+		let paying = deploy_forwarder(GLMR_PRECOMPILE);
+		let _ = Balances::deposit_creating(&evm_account(paying), 5 * DOLLARS);
+		let transfer = abi_call("transfer(address,uint256)", &[address_word(bob), U256::from(3 * DOLLARS)]);
+		let info = call_contract(trader, paying, transfer.clone());
+		assert_eq!(info.status, ExitStatus::Succeeded);
+		assert_eq!(info.logs[0].address, H160::from_low_u64_be(GLMR_PRECOMPILE));
+		assert_eq!(info.logs[0].topics[1], H256::from(paying));
+		assert_eq!(info.logs[0].topics[2], H256::from(bob));
+		assert_eq!(Balances::free_balance(&evm_account(paying)), 2 * DOLLARS);
+		assert_eq!(Balances::free_balance(&evm_account(bob)), 3 * DOLLARS);
+
+		// no more than it holds
+		assert_eq!(call_contract(trader, paying, transfer).status, ExitStatus::Reverted);
+		assert_eq!(Balances::free_balance(&evm_account(paying)), 2 * DOLLARS);
+		assert_eq!(Balances::free_balance(&evm_account(bob)), 3 * DOLLARS);
+	});
+}
+
+#[test]
+fn what_precompiles_wrote_for_a_contract_that_reverts_is_put_back() {
+	new_test_ext().execute_with(|| {
+		let (provider, trader) = (account(10), H160::repeat_byte(1));
+		let _ = Balances::deposit_creating(&evm_account(trader), 10 * DOLLARS);
+		council_motion(2, dex(moonbeam::Call::set_glmr_balance(provider.clone(), INITIAL_BALANCE)));
+		council_motion(2, dex(moonbeam::Call::set_token_balance(provider.clone(), INITIAL_BALANCE)));
+		assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(provider), 10_000, 40_000));
+
+		let reverting = deploy_reverting_forwarder(DEX_PRECOMPILE);
+		council_motion(2, dex(moonbeam::Call::set_glmr_balance(evm_account(reverting), 1_000)));
+		let pool = Moonbeam::pool();
+
+		let info = call_contract(trader, reverting, abi_call("tradeGlmrToToken(uint256,uint256)", &[U256::from(1_000), U256::zero()]));
+		assert_eq!(info.status, ExitStatus::Reverted);
+		// the contract saw the trade go through before it reverted
+		assert_eq!(info.value, evm::precompiles::encode_words(&[U256::from(Moonbeam::quote_glmr_to_token(1_000).unwrap())]));
+		assert_eq!(Moonbeam::glmr_balance_of(evm_account(reverting)), 1_000);
+		assert_eq!(Moonbeam::token_balance_of(evm_account(reverting)), 0);
+		assert_eq!(Moonbeam::pool(), pool);
+		// along with the event of the trade
+		assert!(!System::events().iter().any(|record| match record.event {
+			Event::moonbeam(moonbeam::RawEvent::TokenPurchase(..)) => true,
+			_ => false,
+		}));
+	});
+}
+
+/// Session keys made from `seed`.
+fn session_keys(seed: u8) -> opaque::SessionKeys {
+	opaque::SessionKeys {
+		aura: sp_core::sr25519::Public::from_raw([seed; 32]).into(),
+		grandpa: sp_core::ed25519::Public::from_raw([seed; 32]).into(),
+		im_online: sp_core::sr25519::Public::from_raw([seed; 32]).into(),
+		authority_discovery: sp_core::sr25519::Public::from_raw([seed; 32]).into(),
+		oracle: sp_core::sr25519::Public::from_raw([seed; 32]).into(),
+	}
+}
+
+/// Test externalities in which `account(20)`, controlled by `account(21)`, is
+/// the only validator, and accounts 20 to 23 hold glmr.
+fn new_staking_test_ext() -> sp_io::TestExternalities {
+	let (stash, controller) = (account(20), account(21));
+	let mut t = system::GenesisConfig::default().build_storage::<Runtime>().unwrap();
+	balances::GenesisConfig::<Runtime> {
+		balances: (20..=23).map(|seed| (account(seed), 1_000 * DOLLARS)).collect(),
+		vesting: vec![],
+	}.assimilate_storage(&mut t).unwrap();
+	staking::GenesisConfig::<Runtime> {
+		current_era: 0,
+		validator_count: 2,
+		minimum_validator_count: 1,
+		stakers: vec![(stash.clone(), controller, 100 * DOLLARS, StakerStatus::Validator)],
+		invulnerables: vec![stash.clone()],
+		slash_reward_fraction: Perbill::from_percent(10),
+		.. Default::default()
+	}.assimilate_storage(&mut t).unwrap();
+	session::GenesisConfig::<Runtime> {
+		keys: vec![(stash, session_keys(20))],
+	}.assimilate_storage(&mut t).unwrap();
+	t.into()
+}
+
+#[test]
+fn bonded_validators_join_the_authorities_at_the_next_era() {
+	new_staking_test_ext().execute_with(|| {
+		assert_eq!(Session::validators(), vec![account(20)]);
+		assert_eq!(Aura::authorities(), vec![session_keys(20).aura]);
+
+		let (stash, controller) = (account(22), account(23));
+		assert_ok!(Staking::bond(
+			Origin::signed(stash.clone()),
+			controller.clone().into(),
+			100 * DOLLARS,
+			staking::RewardDestination::Staked,
+		));
+		assert_ok!(Staking::validate(Origin::signed(controller.clone()), staking::ValidatorPrefs::default()));
+		assert_ok!(Session::set_keys(Origin::signed(controller), session_keys(22), vec![]));
+
+		// the election at the end of the era is queued for a session first
+		for _ in 0..SessionsPerEra::get() + 2 {
+			Session::rotate_session();
+		}
+		assert!(Session::validators().contains(&stash));
+		assert!(Aura::authorities().contains(&session_keys(22).aura));
+		assert!(ImOnline::keys().contains(&session_keys(22).im_online));
+		assert!(AuthorityDiscovery::authorities().contains(&session_keys(22).authority_discovery));
+		assert!(Oracle::authorities().contains(&session_keys(22).oracle));
+	});
+}
+
+#[test]
+fn glmr_is_staked_through_its_precompile() {
+	new_staking_test_ext().execute_with(|| {
+		let nominator = H160::repeat_byte(1);
+		let _ = Balances::deposit_creating(&evm_account(nominator), 100 * DOLLARS);
+
+		let bond = abi_call("bond(uint256)", &[U256::from(50 * DOLLARS)]);
+		assert_eq!(transact_with_precompile(nominator, STAKING_PRECOMPILE, bond.clone()).status, ExitStatus::Succeeded);
+		let bonded = abi_call("bonded(address)", &[address_word(nominator)]);
+		let info = call_precompile_from_evm(nominator, STAKING_PRECOMPILE, bonded.clone());
+		assert_eq!(info.value, evm::precompiles::encode_words(&[U256::from(50 * DOLLARS)]));
+
+		// the offset of the array, its length and the validator's account id
+		let validator = U256::from_big_endian(account(20).as_ref());
+		let nominate = abi_call("nominate(bytes32[])", &[U256::from(32), U256::one(), validator]);
+		assert_eq!(transact_with_precompile(nominator, STAKING_PRECOMPILE, nominate).status, ExitStatus::Succeeded);
+		for _ in 0..SessionsPerEra::get() + 2 {
+			Session::rotate_session();
+		}
+		assert!(Staking::stakers(account(20)).others.iter().any(|exposure| exposure.who == evm_account(nominator)));
+
+		let unbond = abi_call("unbond(uint256)", &[U256::from(20 * DOLLARS)]);
+		assert_eq!(transact_with_precompile(nominator, STAKING_PRECOMPILE, unbond).status, ExitStatus::Succeeded);
+		let info = call_precompile_from_evm(nominator, STAKING_PRECOMPILE, bonded);
+		assert_eq!(info.value, evm::precompiles::encode_words(&[U256::from(30 * DOLLARS)]));
+
+		// a contract bonds its own glmr once its execution has succeeded
+		let staking = deploy_forwarder(STAKING_PRECOMPILE);
+		let _ = Balances::deposit_creating(&evm_account(staking), 100 * DOLLARS);
+		assert_eq!(call_contract(nominator, staking, bond).status, ExitStatus::Succeeded);
+		let dispatched = Event::evm(evm::Event::Dispatched(staking, true));
+		assert!(System::events().iter().any(|record| record.event == dispatched));
+		let bonded = abi_call("bonded(address)", &[address_word(staking)]);
+		let info = call_precompile_from_evm(nominator, STAKING_PRECOMPILE, bonded);
+		assert_eq!(info.value, evm::precompiles::encode_words(&[U256::from(50 * DOLLARS)]));
+	});
+}
+
+#[test]
+fn session_keys_set_before_the_upgrade_are_upgraded() {
+	new_staking_test_ext().execute_with(|| {
+		// the validator's keys as a chain from before the upgrade stored them
+		let old_keys = OldSessionKeys { aura: session_keys(30).aura, grandpa: session_keys(30).grandpa };
+		<session_upgrade::old::NextKeys<Runtime>>::insert(b":session:keys".to_vec(), account(20), old_keys.clone());
+		<session_upgrade::old::QueuedKeys<Runtime>>::put(vec![(account(20), old_keys)]);
+		session_upgrade::Upgraded::put(false);
+
+		<SessionUpgrade as OnInitialize<BlockNumber>>::on_initialize(1);
+		assert!(SessionUpgrade::upgraded());
+
+		// every session handler takes the upgraded keys at the next session
+		Session::rotate_session();
+		assert_eq!(Aura::authorities(), vec![session_keys(30).aura]);
+		assert_eq!(ImOnline::keys(), vec![session_keys(30).im_online]);
+		assert_eq!(AuthorityDiscovery::authorities(), vec![session_keys(30).authority_discovery]);
+		assert_eq!(Oracle::authorities(), vec![session_keys(30).oracle]);
+	});
+}
+
+#[test]
+fn silent_validators_are_reported_to_staking() {
+	new_staking_test_ext().execute_with(|| {
+		let (stash, controller) = (account(22), account(23));
+		assert_ok!(Staking::bond(
+			Origin::signed(stash.clone()),
+			controller.clone().into(),
+			100 * DOLLARS,
+			staking::RewardDestination::Staked,
+		));
+		assert_ok!(Staking::validate(Origin::signed(controller.clone()), staking::ValidatorPrefs::default()));
+		assert_ok!(Session::set_keys(Origin::signed(controller), session_keys(22), vec![]));
+		assert_ok!(Staking::set_invulnerables(system::RawOrigin::Root.into(), vec![account(20), stash.clone()]));
+		for _ in 0..SessionsPerEra::get() + 2 {
+			Session::rotate_session();
+		}
+		assert_eq!(Session::validators().len(), 2);
+		assert_ok!(Staking::set_invulnerables(system::RawOrigin::Root.into(), vec![account(20)]));
+
+		// neither validator sends a heartbeat or authors a block this session
+		let session = Session::current_index();
+		Session::rotate_session();
+		let offence = Event::offences(offences::Event::Offence(*b"im-online:offlin", session.encode()));
+		assert!(System::events().iter().any(|record| record.event == offence));
+
+		// staking chills the validator that is not invulnerable
+		assert!(!<staking::Validators<Runtime>>::exists(&stash));
+		assert!(<staking::Validators<Runtime>>::exists(&account(20)));
+	});
+}
+
+/// A Wasm contract told what to do by the first byte of its input: 0
+/// dispatches the rest as a runtime call, 1 deposits it as an event and 2
+/// traps.  Dex bridge replies start with their selector, so it takes the
+/// replies to selector 1 and refuses those to selector 2.
+const DEX_CONTRACT: &str = r#"
+(module
+	(import "env" "ext_scratch_size" (func $ext_scratch_size (result i32)))
+	(import "env" "ext_scratch_read" (func $ext_scratch_read (param i32 i32 i32)))
+	(import "env" "ext_dispatch_call" (func $ext_dispatch_call (param i32 i32)))
+	(import "env" "ext_deposit_event" (func $ext_deposit_event (param i32 i32 i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	(func (export "deploy"))
+
+	(func (export "call")
+		(local $size i32)
+		(set_local $size (call $ext_scratch_size))
+		(call $ext_scratch_read (i32.const 0) (i32.const 0) (get_local $size))
+		(if (i32.eq (i32.load8_u (i32.const 0)) (i32.const 2))
+			(then unreachable)
+		)
+		(if (i32.eqz (i32.load8_u (i32.const 0)))
+			(then (call $ext_dispatch_call (i32.const 1) (i32.sub (get_local $size) (i32.const 1))))
+			(else (call $ext_deposit_event (i32.const 0) (i32.const 0) (i32.const 1) (i32.sub (get_local $size) (i32.const 1))))
+		)
+	)
+)
+"#;
+
+const CONTRACT_GAS_LIMIT: ContractsGas = 100_000;
+
+fn new_contracts_test_ext() -> sp_io::TestExternalities {
+	let mut t = system::GenesisConfig::default().build_storage::<Runtime>().unwrap();
+	contracts::GenesisConfig::<Runtime> {
+		current_schedule: Default::default(),
+		gas_price: 1 * MILLICENTS,
+	}.assimilate_storage(&mut t).unwrap();
+	t.into()
+}
+
+fn bridge(call: dex_bridge::Call<Runtime>) -> Call {
+	Call::DexBridge(call)
+}
+
+/// Deploys `DEX_CONTRACT` and gives it dex balances to trade with, along with
+/// a pool to trade on.
+fn deploy_dex_contract(owner: &AccountId) -> AccountId {
+	let code = wabt::wat2wasm(DEX_CONTRACT).unwrap();
+	let code_hash = BlakeTwo256::hash(&code);
+	let _ = Balances::deposit_creating(owner, 1_000 * DOLLARS);
+	assert_ok!(Contracts::put_code(Origin::signed(owner.clone()), CONTRACT_GAS_LIMIT, code));
+	assert_ok!(Contracts::instantiate(Origin::signed(owner.clone()), 100 * DOLLARS, CONTRACT_GAS_LIMIT, code_hash, vec![]));
+	let contract = <Runtime as contracts::Trait>::DetermineContractAddress::contract_address_for(&code_hash, &[], owner);
+
+	let provider = account(11);
+	let root = || -> Origin { system::RawOrigin::Root.into() };
+	for who in &[provider.clone(), contract.clone()] {
+		assert_ok!(Moonbeam::set_glmr_balance(root(), who.clone(), INITIAL_BALANCE));
+		assert_ok!(Moonbeam::set_token_balance(root(), who.clone(), INITIAL_BALANCE));
+	}
+	assert_ok!(Moonbeam::deposit_liquidity(Origin::signed(provider), 100_000, 400_000));
+
+	contract
+}
+
+/// Has `contract` dispatch `call`, told to by `owner`.
+fn dispatch_from_contract(owner: &AccountId, contract: &AccountId, call: Call) {
+	let mut input = vec![0];
+	call.encode_to(&mut input);
+	assert_ok!(Contracts::call(Origin::signed(owner.clone()), contract.clone().into(), 0, CONTRACT_GAS_LIMIT, input));
+}
+
+/// The data of the events `contract` deposited.
+fn contract_events(contract: &AccountId) -> Vec<Vec<u8>> {
+	System::events().into_iter().filter_map(|record| match record.event {
+		Event::contracts(contracts::RawEvent::ContractExecution(who, data)) if &who == contract => Some(data),
+		_ => None,
+	}).collect()
+}
+
+#[test]
+fn contracts_quote_and_trade_through_the_dex_bridge() {
+	new_contracts_test_ext().execute_with(|| {
+		let owner = account(10);
+		let contract = deploy_dex_contract(&owner);
+
+		let quoted = Moonbeam::quote_glmr_to_token(1_000);
+		dispatch_from_contract(&owner, &contract, bridge(dex_bridge::Call::quote(
+			dex_bridge::Quote::GlmrToToken { glmr_value: 1_000 },
+			vec![1],
+			CONTRACT_GAS_LIMIT,
+		)));
+		assert_eq!(contract_events(&contract), vec![quoted.encode()]);
+
+		dispatch_from_contract(&owner, &contract, bridge(dex_bridge::Call::execute(
+			moonbeam::Operation::TradeGlmrToToken { glmr_value: 1_000 },
+			vec![1],
+			CONTRACT_GAS_LIMIT,
+		)));
+		let tokens_bought = quoted.unwrap();
+		let outcome = moonbeam::Outcome::TokenPurchase { glmr_sold: 1_000, tokens_bought };
+		assert_eq!(contract_events(&contract), vec![quoted.encode(), outcome.encode()]);
+		assert_eq!(Moonbeam::token_balance_of(&contract), INITIAL_BALANCE + tokens_bought);
+	});
+}
+
+#[test]
+fn contracts_move_liquidity_through_the_dex_bridge() {
+	new_contracts_test_ext().execute_with(|| {
+		let owner = account(10);
+		let contract = deploy_dex_contract(&owner);
+
+		dispatch_from_contract(&owner, &contract, bridge(dex_bridge::Call::execute(
+			moonbeam::Operation::DepositLiquidity { glmr_value: 1_000, token_value: 4_000 },
+			vec![1],
+			CONTRACT_GAS_LIMIT,
+		)));
+		dispatch_from_contract(&owner, &contract, bridge(dex_bridge::Call::execute(
+			moonbeam::Operation::WithdrawLiquidity { liquid_value: 1_000 },
+			vec![1],
+			CONTRACT_GAS_LIMIT,
+		)));
+
+		assert_eq!(contract_events(&contract), vec![
+			moonbeam::Outcome::DepositLiquidity { glmr_deposited: 1_000, tokens_deposited: 4_000, liquid_minted: 1_000 }.encode(),
+			moonbeam::Outcome::WithdrawLiquidity { liquid_burned: 1_000, glmr_received: 1_000, tokens_received: 4_000 }.encode(),
+		]);
+		assert_eq!(Moonbeam::glmr_balance_of(&contract), INITIAL_BALANCE);
+		assert_eq!(Moonbeam::token_balance_of(&contract), INITIAL_BALANCE);
+	});
+}
+
+#[test]
+fn dex_operations_whose_reply_fails_are_undone() {
+	new_contracts_test_ext().execute_with(|| {
+		let owner = account(10);
+		let contract = deploy_dex_contract(&owner);
+		let pool = Moonbeam::pool();
+
+		dispatch_from_contract(&owner, &contract, bridge(dex_bridge::Call::execute(
+			moonbeam::Operation::TradeGlmrToToken { glmr_value: 1_000 },
+			vec![2],
+			CONTRACT_GAS_LIMIT,
+		)));
+
+		let refused = Event::contracts(contracts::RawEvent::Dispatched(contract.clone(), false));
+		assert!(System::events().iter().any(|record| record.event == refused));
+		assert_eq!(Moonbeam::pool(), pool);
+		assert_eq!(Moonbeam::glmr_balance_of(&contract), INITIAL_BALANCE);
+		assert_eq!(Moonbeam::token_balance_of(&contract), INITIAL_BALANCE);
+		assert!(contract_events(&contract).is_empty());
+	});
+}
+
+#[test]
+fn contracts_pay_for_the_gas_of_dex_bridge_replies() {
+	new_contracts_test_ext().execute_with(|| {
+		let owner = account(10);
+		let contract = deploy_dex_contract(&owner);
+		let balance = Balances::free_balance(&contract);
+
+		dispatch_from_contract(&owner, &contract, bridge(dex_bridge::Call::quote(
+			dex_bridge::Quote::GlmrToToken { glmr_value: 1_000 },
+			vec![1],
+			CONTRACT_GAS_LIMIT,
+		)));
+		// the gas is bought up front and what is left refunded
+		let paid = balance - Balances::free_balance(&contract);
+		assert!(paid > 0);
+		assert!(paid < Balance::from(CONTRACT_GAS_LIMIT) * MILLICENTS);
+
+		// replies get no more than `MaxReplyGas`
+		dispatch_from_contract(&owner, &contract, bridge(dex_bridge::Call::quote(
+			dex_bridge::Quote::GlmrToToken { glmr_value: 1_000 },
+			vec![1],
+			MaxReplyGas::get() + 1,
+		)));
+		let refused = Event::contracts(contracts::RawEvent::Dispatched(contract.clone(), false));
+		assert!(System::events().iter().any(|record| record.event == refused));
+		assert_eq!(contract_events(&contract).len(), 1);
+	});
+}
+
+#[test]
+fn dex_bridge_calls_weigh_their_reply_gas() {
+	let quote = |gas_limit| dex_bridge::Call::<Runtime>::quote(
+		dex_bridge::Quote::GlmrToToken { glmr_value: 1_000 },
+		vec![1],
+		gas_limit,
+	).get_dispatch_info().weight;
+	assert!(quote(0) > 0);
+	assert!(quote(MaxReplyGas::get()) > quote(CONTRACT_GAS_LIMIT));
+
+	let execute = dex_bridge::Call::<Runtime>::execute(
+		moonbeam::Operation::TradeGlmrToToken { glmr_value: 1_000 },
+		vec![1],
+		MaxReplyGas::get(),
+	).get_dispatch_info().weight;
+	assert_eq!(execute, quote(MaxReplyGas::get()));
+}
@@ -1,8 +1,10 @@
 use sp_core::{Pair, Public, sr25519};
 use moonbeam_runtime::{
 	AccountId, AuraConfig, BalancesConfig, GenesisConfig, GrandpaConfig,
-	SudoConfig, IndicesConfig, SystemConfig, WASM_BINARY, Signature
+	IndicesConfig, SystemConfig, WASM_BINARY, Signature
 };
+#[cfg(not(feature = "no-sudo"))]
+use moonbeam_runtime::SudoConfig;
 use sp_consensus_aura::sr25519::{AuthorityId as AuraId};
 use grandpa_primitives::{AuthorityId as GrandpaId};
 use sc_service;
@@ -11,12 +13,48 @@ use sp_runtime::traits::{Verify, IdentifyAccount};
 // added for contracts pallet
 use moonbeam_runtime::{ContractsConfig, MILLICENTS};
 
+// added for the moonbeam dex
+use moonbeam_runtime::{MoonbeamConfig, Balance, DOLLARS};
+
+// added for the oracle
+use moonbeam_runtime::{OracleConfig, OracleId};
+
+// added for governance
+use moonbeam_runtime::{CouncilConfig, DemocracyConfig};
+
+// added for staking
+use moonbeam_runtime::{
+	SessionConfig, SessionUpgradeConfig, StakingConfig, StakerStatus, Perbill, opaque::SessionKeys,
+};
+
+// added for block rewards
+use moonbeam_runtime::RewardsConfig;
+
+// added for heartbeats and authority discovery
+use moonbeam_runtime::{AuthorityDiscoveryConfig, AuthorityDiscoveryId, ImOnlineConfig, ImOnlineId};
+
 // added to change token symbol
 use serde_json;
 
 // Note this is the URL for the telemetry server
 //const STAGING_TELEMETRY_URL: &str = "wss://telemetry.polkadot.io/submit/";
 
+/// Dex glmr and token balance of each endowed account at genesis.
+const DEX_ENDOWMENT: Balance = 1_000_000 * DOLLARS;
+/// Glmr seeded into the dex pool at genesis by the root account.
+const DEX_POOL_GLMR: Balance = 100_000 * DOLLARS;
+/// Tokens seeded into the dex pool at genesis by the root account.
+const DEX_POOL_TOKENS: Balance = 1_000_000 * DOLLARS;
+
+/// Number of endowed accounts that make up the council at genesis.
+const INITIAL_COUNCIL_SIZE: usize = 3;
+
+/// Glmr each initial authority's stash bonds at genesis.
+const INITIAL_STAKE: Balance = 1_000 * DOLLARS;
+
+/// Glmr minted for the author of every block at genesis.
+const BLOCK_REWARD: Balance = 1 * DOLLARS;
+
 /// Specialized `ChainSpec`. This is a specialization of the general Substrate ChainSpec type.
 pub type ChainSpec = sc_service::ChainSpec<GenesisConfig>;
 
@@ -47,11 +85,19 @@ pub fn get_account_id_from_seed<TPublic: Public>(seed: &str) -> AccountId where
 	AccountPublic::from(get_from_seed::<TPublic>(seed)).into_account()
 }
 
-/// Helper function to generate an authority key for Aura
-pub fn get_authority_keys_from_seed(s: &str) -> (AuraId, GrandpaId) {
+/// Helper function to generate the stash and controller accounts of an
+/// authority, and its session and oracle keys
+pub fn get_authority_keys_from_seed(s: &str)
+	-> (AccountId, AccountId, AuraId, GrandpaId, OracleId, ImOnlineId, AuthorityDiscoveryId)
+{
 	(
+		get_account_id_from_seed::<sr25519::Public>(&format!("{}//stash", s)),
+		get_account_id_from_seed::<sr25519::Public>(s),
 		get_from_seed::<AuraId>(s),
 		get_from_seed::<GrandpaId>(s),
+		get_from_seed::<OracleId>(s),
+		get_from_seed::<ImOnlineId>(s),
+		get_from_seed::<AuthorityDiscoveryId>(s),
 	)
 }
 
@@ -127,7 +173,7 @@ impl Alternative {
 	}
 }
 
-fn testnet_genesis(initial_authorities: Vec<(AuraId, GrandpaId)>,
+fn testnet_genesis(initial_authorities: Vec<(AccountId, AccountId, AuraId, GrandpaId, OracleId, ImOnlineId, AuthorityDiscoveryId)>,
 	root_key: AccountId,
 	endowed_accounts: Vec<AccountId>,
 	enable_println: bool) -> GenesisConfig {
@@ -151,16 +197,76 @@ fn testnet_genesis(initial_authorities: Vec<(AuraId, GrandpaId)>,
 			balances: endowed_accounts.iter().cloned().map(|k|(k, 1 << 60)).collect(),
 			vesting: vec![],
 		}),
+		#[cfg(not(feature = "no-sudo"))]
 		sudo: Some(SudoConfig {
-			key: root_key,
+			key: root_key.clone(),
 		}),
+		// the aura and grandpa authorities are the validators session starts with
 		aura: Some(AuraConfig {
-			authorities: initial_authorities.iter().map(|x| (x.0.clone())).collect(),
+			authorities: vec![],
 		}),
 		grandpa: Some(GrandpaConfig {
-			authorities: initial_authorities.iter().map(|x| (x.1.clone(), 1)).collect(),
+			authorities: vec![],
+		}),
+		// added for staking
+		staking: Some(StakingConfig {
+			current_era: 0,
+			validator_count: initial_authorities.len() as u32 * 2,
+			minimum_validator_count: initial_authorities.len() as u32,
+			stakers: initial_authorities.iter()
+				.map(|x| (x.0.clone(), x.1.clone(), INITIAL_STAKE, StakerStatus::Validator))
+				.collect(),
+			invulnerables: initial_authorities.iter().map(|x| x.0.clone()).collect(),
+			slash_reward_fraction: Perbill::from_percent(10),
+			.. Default::default()
+		}),
+		session: Some(SessionConfig {
+			keys: initial_authorities.iter()
+				.map(|x| (x.0.clone(), SessionKeys {
+					aura: x.2.clone(),
+					grandpa: x.3.clone(),
+					im_online: x.5.clone(),
+					authority_discovery: x.6.clone(),
+					oracle: x.4.clone(),
+				}))
+				.collect(),
+		}),
+		// the session keys of a new chain need no upgrade
+		session_upgrade: Some(SessionUpgradeConfig::default()),
+		// the heartbeat and authority discovery keys are also set by session
+		im_online: Some(ImOnlineConfig {
+			keys: vec![],
+		}),
+		authority_discovery: Some(AuthorityDiscoveryConfig {
+			keys: vec![],
+		}),
+		// added for block rewards
+		rewards: Some(RewardsConfig {
+			block_reward: BLOCK_REWARD,
 		}),
 		// added for contracts pallet
 		contracts: Some(contracts_config),
+		// added for the moonbeam dex
+		moonbeam: Some(MoonbeamConfig {
+			glmr_balances: endowed_accounts.iter().cloned().map(|k| (k, DEX_ENDOWMENT)).collect(),
+			token_balances: endowed_accounts.iter().cloned().map(|k| (k, DEX_ENDOWMENT)).collect(),
+			liquidity: vec![(root_key, DEX_POOL_GLMR, DEX_POOL_TOKENS)],
+		}),
+		// added for the oracle
+		oracle: Some(OracleConfig {
+			// the oracle authorities are set by session
+			authorities: vec![],
+			// no market quotes the token or glmr yet, so no price is fetched and
+			// no reference price published until governance sets both sources
+			// with `set_price_source` and `set_glmr_price_source`
+			price_source: vec![],
+			glmr_price_source: vec![],
+		}),
+		// added for governance
+		democracy: Some(DemocracyConfig::default()),
+		collective_Instance1: Some(CouncilConfig {
+			members: endowed_accounts.iter().take(INITIAL_COUNCIL_SIZE).cloned().collect(),
+			phantom: Default::default(),
+		}),
 	}
 }
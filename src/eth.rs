@@ -0,0 +1,761 @@
+//! Ethereum JSON-RPC, the `eth_` namespace, so wallets and tools built for
+//! Ethereum can talk to the node.
+//!
+//! Ethereum blocks are the ones the runtime's ethereum module keeps for each
+//! substrate block, and have the same number.  Balances and calls are those of
+//! the runtime's evm module.  Evm transactions are the `evm::call`,
+//! `evm::create` and `evm::transact` extrinsics.  Ethereum signed
+//! transactions, which are submitted as `evm::transact`, are identified by
+//! their Ethereum hash and the other extrinsics by their extrinsic hash.
+//!
+//! Ethereum block and transaction hashes are looked up in the `mapping` the
+//! node keeps of them.
+
+use std::sync::Arc;
+use codec::{Decode, Encode};
+use ethbloom::{Bloom, Input as BloomInput};
+use jsonrpc_core::{Error, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use serde::{Deserialize, Deserializer, Serialize, de::Error as _};
+use sp_api::ProvideRuntimeApi;
+use sp_core::{H160, H256, U256, Bytes, hashing::keccak_256};
+use sp_runtime::{OpaqueExtrinsic, generic::BlockId, traits::Header as _};
+use sp_transaction_pool::TransactionPool;
+use moonbeam_runtime::{
+	Call, EthereumApi, EthereumBlock, EthereumReceipt, EvmApi, EvmCall, EvmTransaction,
+	ExitStatus, UncheckedExtrinsic, opaque::Block,
+};
+use crate::service::FullClient;
+
+pub mod mapping;
+
+/// The most blocks a single `eth_getLogs` request may search.
+const MAX_LOG_BLOCKS: u32 = 1_000;
+
+/// A block as named in requests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockNumber {
+	/// The genesis block.
+	Earliest,
+	/// The best block.
+	Latest,
+	/// The best block, as the node has no pending block.
+	Pending,
+	/// The block with this number.
+	Number(u32),
+}
+
+impl<'de> Deserialize<'de> for BlockNumber {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+		let value = String::deserialize(deserializer)?;
+		match value.as_str() {
+			"earliest" => Ok(BlockNumber::Earliest),
+			"latest" => Ok(BlockNumber::Latest),
+			"pending" => Ok(BlockNumber::Pending),
+			number if number.starts_with("0x") => u32::from_str_radix(&number[2..], 16)
+				.map(BlockNumber::Number)
+				.map_err(D::Error::custom),
+			_ => Err(D::Error::custom("block number must be a tag or a hex quantity")),
+		}
+	}
+}
+
+/// A single value or a list of values, any of which matches.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum ValueOrArray<T> {
+	/// Only this value matches.
+	Value(T),
+	/// Any of these values matches.
+	Array(Vec<T>),
+}
+
+impl<T: PartialEq> ValueOrArray<T> {
+	fn values(&self) -> &[T] {
+		match self {
+			ValueOrArray::Value(value) => std::slice::from_ref(value),
+			ValueOrArray::Array(values) => values,
+		}
+	}
+
+	fn contains(&self, value: &T) -> bool {
+		self.values().contains(value)
+	}
+}
+
+/// The parameters of `eth_call` and `eth_estimateGas`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallRequest {
+	/// The caller, the zero address if not given.
+	pub from: Option<H160>,
+	/// The contract called, or none to create a contract.
+	pub to: Option<H160>,
+	/// The gas limit, no limit if not given.
+	pub gas: Option<U256>,
+	/// The gas price, zero if not given.
+	pub gas_price: Option<U256>,
+	/// The glmr sent along.
+	pub value: Option<U256>,
+	/// The input of a call or the init code of a create.
+	pub data: Option<Bytes>,
+}
+
+/// Which logs `eth_getLogs` returns.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Filter {
+	/// The first block searched, the best block if not given.
+	pub from_block: Option<BlockNumber>,
+	/// The last block searched, the best block if not given.
+	pub to_block: Option<BlockNumber>,
+	/// Search only the block with this Ethereum hash, instead of a range.
+	pub block_hash: Option<H256>,
+	/// The contracts whose logs match.
+	pub address: Option<ValueOrArray<H160>>,
+	/// The topics that match at each position, where none matches anything.
+	pub topics: Option<Vec<Option<ValueOrArray<H256>>>>,
+}
+
+impl Filter {
+	/// Whether a block with this logs bloom may hold matching logs.
+	fn may_match(&self, bloom: &Bloom) -> bool {
+		let contains = |bytes: &[u8]| bloom.contains_input(BloomInput::Raw(bytes));
+
+		if let Some(address) = &self.address {
+			if !address.values().iter().any(|address| contains(address.as_bytes())) {
+				return false
+			}
+		}
+
+		if let Some(topics) = &self.topics {
+			for topic in topics.iter().flatten() {
+				if !topic.values().iter().any(|topic| contains(topic.as_bytes())) {
+					return false
+				}
+			}
+		}
+
+		true
+	}
+
+	fn matches(&self, log: &Log) -> bool {
+		if let Some(address) = &self.address {
+			if !address.contains(&log.address) {
+				return false
+			}
+		}
+
+		if let Some(topics) = &self.topics {
+			for (position, topic) in topics.iter().enumerate() {
+				match (topic, log.topics.get(position)) {
+					(None, _) => {},
+					(Some(topic), Some(value)) if topic.contains(value) => {},
+					_ => return false,
+				}
+			}
+		}
+
+		true
+	}
+}
+
+/// A log emitted by a contract.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Log {
+	/// The contract that emitted the log.
+	pub address: H160,
+	/// The indexed topics.
+	pub topics: Vec<H256>,
+	/// The unindexed data.
+	pub data: Bytes,
+	/// Ethereum hash of the block the log was emitted in.
+	pub block_hash: H256,
+	/// Number of the block the log was emitted in.
+	pub block_number: U256,
+	/// Hash of the transaction that emitted the log.
+	pub transaction_hash: H256,
+	/// Index of the transaction that emitted the log in its block.
+	pub transaction_index: U256,
+	/// Index of the log in its block.
+	pub log_index: U256,
+	/// Always false, logs of retracted blocks are not reported.
+	pub removed: bool,
+}
+
+/// An Ethereum block.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcBlock {
+	/// The Ethereum hash of the block.
+	pub hash: H256,
+	/// The Ethereum hash of the block's parent.
+	pub parent_hash: H256,
+	/// The number of the block.
+	pub number: U256,
+	/// Always empty, there is no proof of work.
+	pub nonce: Bytes,
+	/// The hash of an empty list, there are no ommers.
+	pub sha3_uncles: H256,
+	/// The bloom of every log emitted in the block.
+	pub logs_bloom: Bloom,
+	/// The root of the block's transactions.
+	pub transactions_root: H256,
+	/// The state root of the substrate block.
+	pub state_root: H256,
+	/// The root of the block's receipts.
+	pub receipts_root: H256,
+	/// Always the zero address.
+	pub miner: H160,
+	/// Always zero.
+	pub difficulty: U256,
+	/// Always zero.
+	pub total_difficulty: U256,
+	/// Always empty.
+	pub extra_data: Bytes,
+	/// The most gas the block's executions may use together.
+	pub gas_limit: U256,
+	/// The gas used by the block's transactions.
+	pub gas_used: U256,
+	/// Seconds since the epoch.
+	pub timestamp: U256,
+	/// The block's transactions, in full or as hashes.
+	pub transactions: BlockTransactions,
+	/// Always empty.
+	pub uncles: Vec<H256>,
+}
+
+/// The transactions of a block, as requested.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum BlockTransactions {
+	/// The hashes of the transactions.
+	Hashes(Vec<H256>),
+	/// The transactions themselves.
+	Full(Vec<Transaction>),
+}
+
+/// An evm transaction.  The `evm::call` and `evm::create` extrinsics are not
+/// Ethereum signed, and have a zero nonce and signature.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transaction {
+	/// Hash of the transaction.
+	pub hash: H256,
+	/// The nonce of an Ethereum signed transaction.
+	pub nonce: U256,
+	/// Ethereum hash of the block the transaction is in.
+	pub block_hash: H256,
+	/// Number of the block the transaction is in.
+	pub block_number: U256,
+	/// Index of the transaction in its block.
+	pub transaction_index: U256,
+	/// The address that sent the transaction.
+	pub from: H160,
+	/// The contract called, none for a create.
+	pub to: Option<H160>,
+	/// The glmr sent along.
+	pub value: U256,
+	pub gas_price: U256,
+	/// The gas limit.
+	pub gas: U256,
+	/// The input of a call or the init code of a create.
+	pub input: Bytes,
+	pub v: U256,
+	pub r: U256,
+	pub s: U256,
+}
+
+/// The outcome of an evm transaction.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Receipt {
+	/// Hash of the transaction.
+	pub transaction_hash: H256,
+	/// Index of the transaction in its block.
+	pub transaction_index: U256,
+	/// Ethereum hash of the block the transaction is in.
+	pub block_hash: H256,
+	/// Number of the block the transaction is in.
+	pub block_number: U256,
+	/// The address that sent the transaction.
+	pub from: H160,
+	/// The contract called, if this was a call.
+	pub to: Option<H160>,
+	/// The gas used by the evm transactions of the block up to and including
+	/// this one.
+	pub cumulative_gas_used: U256,
+	/// The gas used by this transaction.
+	pub gas_used: U256,
+	/// The contract created, if this was a create.
+	pub contract_address: Option<H160>,
+	/// The logs the transaction emitted.
+	pub logs: Vec<Log>,
+	/// The bloom of the logs.
+	pub logs_bloom: Bloom,
+	/// 1 if the transaction succeeded, 0 if it failed.
+	pub status: U256,
+}
+
+/// The `eth_` methods served by the node.
+#[rpc]
+pub trait EthApi {
+	/// The chain id signed transactions have to be for.
+	#[rpc(name = "eth_chainId")]
+	fn chain_id(&self) -> Result<U256>;
+
+	/// The number of the best block.
+	#[rpc(name = "eth_blockNumber")]
+	fn block_number(&self) -> Result<U256>;
+
+	/// The block with this Ethereum hash, with its transactions in full if
+	/// `full`.
+	#[rpc(name = "eth_getBlockByHash")]
+	fn block_by_hash(&self, hash: H256, full: bool) -> Result<Option<RpcBlock>>;
+
+	/// The block with this number, with its transactions in full if `full`.
+	#[rpc(name = "eth_getBlockByNumber")]
+	fn block_by_number(&self, number: BlockNumber, full: bool) -> Result<Option<RpcBlock>>;
+
+	/// The glmr balance of an address.
+	#[rpc(name = "eth_getBalance")]
+	fn balance(&self, address: H160, number: Option<BlockNumber>) -> Result<U256>;
+
+	/// The nonce of an address, which its next signed transaction must have.
+	#[rpc(name = "eth_getTransactionCount")]
+	fn transaction_count(&self, address: H160, number: Option<BlockNumber>) -> Result<U256>;
+
+	/// The output of calling a contract, without sending a transaction.
+	#[rpc(name = "eth_call")]
+	fn call(&self, request: CallRequest, number: Option<BlockNumber>) -> Result<Bytes>;
+
+	/// The gas a call or create would use.
+	#[rpc(name = "eth_estimateGas")]
+	fn estimate_gas(&self, request: CallRequest, number: Option<BlockNumber>) -> Result<U256>;
+
+	/// Submits an Ethereum signed transaction.
+	#[rpc(name = "eth_sendRawTransaction")]
+	fn send_raw_transaction(&self, transaction: Bytes) -> Result<H256>;
+
+	/// The receipt of an evm transaction.
+	#[rpc(name = "eth_getTransactionReceipt")]
+	fn transaction_receipt(&self, hash: H256) -> Result<Option<Receipt>>;
+
+	/// The logs matching a filter.
+	#[rpc(name = "eth_getLogs")]
+	fn logs(&self, filter: Filter) -> Result<Vec<Log>>;
+}
+
+/// Serves the `eth_` methods from a full client, submitting transactions to
+/// its transaction pool.
+pub struct Eth<P> {
+	client: Arc<FullClient>,
+	pool: Arc<P>,
+}
+
+impl<P> Eth<P> {
+	/// Serves the `eth_` methods from `client` and `pool`.
+	pub fn new(client: Arc<FullClient>, pool: Arc<P>) -> Self {
+		Eth { client, pool }
+	}
+
+	fn best_number(&self) -> u32 {
+		self.client.info().chain.best_number
+	}
+
+	fn number_of(&self, number: BlockNumber) -> u32 {
+		match number {
+			BlockNumber::Earliest => 0,
+			BlockNumber::Number(number) => number,
+			BlockNumber::Latest | BlockNumber::Pending => self.best_number(),
+		}
+	}
+
+	fn block_id(&self, number: Option<BlockNumber>) -> BlockId<Block> {
+		match number.unwrap_or(BlockNumber::Latest) {
+			BlockNumber::Latest | BlockNumber::Pending => BlockId::Hash(self.client.info().chain.best_hash),
+			number => BlockId::Number(self.number_of(number)),
+		}
+	}
+
+	/// The gas a dry run at `id` may use: the gas requested, up to the block
+	/// gas limit, which is also what it may use if it does not say.
+	fn gas_limit(&self, id: &BlockId<Block>, gas: Option<U256>) -> Result<u32> {
+		let block_gas_limit = self.client.runtime_api().block_gas_limit(id).map_err(internal_err)?;
+		Ok(match gas {
+			Some(gas) if gas < U256::from(block_gas_limit) => gas.low_u32(),
+			_ => block_gas_limit,
+		})
+	}
+
+	/// The hash of the substrate block with this number.
+	fn substrate_hash(&self, number: BlockNumber) -> Result<Option<H256>> {
+		match number {
+			BlockNumber::Latest | BlockNumber::Pending => Ok(Some(self.client.info().chain.best_hash)),
+			number => self.client.block_hash(self.number_of(number)).map_err(internal_err),
+		}
+	}
+
+	/// The Ethereum block of a substrate block.
+	fn ethereum_block(&self, hash: H256) -> Result<Option<EthereumBlock>> {
+		self.client.runtime_api().current_block(&BlockId::Hash(hash)).map_err(internal_err)
+	}
+
+	/// The receipts of a substrate block.
+	fn receipts(&self, hash: H256) -> Result<Vec<EthereumReceipt>> {
+		self.client.runtime_api().current_receipts(&BlockId::Hash(hash)).map_err(internal_err)
+	}
+
+	/// The evm transactions of a substrate block, read from the extrinsics
+	/// their receipts point at.
+	fn transactions(&self, hash: H256, block: &EthereumBlock) -> Result<Vec<Transaction>> {
+		let extrinsics = match self.client.block(&BlockId::Hash(hash)).map_err(internal_err)? {
+			Some(signed) => signed.block.extrinsics,
+			None => return Ok(vec![]),
+		};
+
+		self.receipts(hash)?.into_iter().map(|receipt| {
+			let extrinsic = extrinsics.get(receipt.transaction_index as usize)
+				.ok_or_else(|| internal_err("Receipt of a missing extrinsic"))?;
+			let extrinsic = UncheckedExtrinsic::decode(&mut &extrinsic.encode()[..]).map_err(internal_err)?;
+			let mut transaction = Transaction {
+				hash: receipt.transaction_hash,
+				nonce: U256::zero(),
+				block_hash: block.hash,
+				block_number: block.number,
+				transaction_index: U256::from(receipt.transaction_index),
+				from: receipt.from,
+				to: receipt.to,
+				value: U256::zero(),
+				gas_price: U256::zero(),
+				gas: U256::zero(),
+				input: Bytes(vec![]),
+				v: U256::zero(),
+				r: U256::zero(),
+				s: U256::zero(),
+			};
+			match extrinsic.function {
+				Call::Evm(EvmCall::call(_, input, value, gas_limit, gas_price)) |
+				Call::Evm(EvmCall::create(input, value, gas_limit, gas_price)) => {
+					transaction.value = value;
+					transaction.gas_price = gas_price;
+					transaction.gas = U256::from(gas_limit);
+					transaction.input = Bytes(input);
+				},
+				Call::Evm(EvmCall::transact(bytes)) => {
+					let signed = EvmTransaction::decode(&bytes).map_err(internal_err)?;
+					transaction.nonce = signed.nonce;
+					transaction.value = signed.value;
+					transaction.gas_price = signed.gas_price;
+					transaction.gas = signed.gas_limit;
+					transaction.input = Bytes(signed.input);
+					transaction.v = U256::from(signed.v);
+					transaction.r = signed.r;
+					transaction.s = signed.s;
+				},
+				_ => {},
+			}
+			Ok(transaction)
+		}).collect()
+	}
+
+	fn rpc_block(&self, hash: H256, full: bool) -> Result<Option<RpcBlock>> {
+		let (block, header) = match (self.ethereum_block(hash)?, self.client.header(&BlockId::Hash(hash))) {
+			(Some(block), Ok(Some(header))) => (block, header),
+			_ => return Ok(None),
+		};
+		let transactions = if full {
+			BlockTransactions::Full(self.transactions(hash, &block)?)
+		} else {
+			BlockTransactions::Hashes(block.transactions.clone())
+		};
+
+		Ok(Some(RpcBlock {
+			hash: block.hash,
+			parent_hash: block.parent_hash,
+			number: block.number,
+			nonce: Bytes(vec![0; 8]),
+			sha3_uncles: H256::from(keccak_256(&[0xc0])),
+			logs_bloom: block.logs_bloom,
+			transactions_root: block.transactions_root,
+			state_root: *header.state_root(),
+			receipts_root: block.receipts_root,
+			miner: H160::zero(),
+			difficulty: U256::zero(),
+			total_difficulty: U256::zero(),
+			extra_data: Bytes(vec![]),
+			gas_limit: block.gas_limit,
+			gas_used: block.gas_used,
+			timestamp: U256::from(block.timestamp),
+			transactions,
+			uncles: vec![],
+		}))
+	}
+}
+
+impl<P> EthApi for Eth<P> where P: TransactionPool<Block = Block> + 'static {
+	fn chain_id(&self) -> Result<U256> {
+		let best = BlockId::Number(self.best_number());
+		let chain_id = self.client.runtime_api().chain_id(&best).map_err(internal_err)?;
+		Ok(U256::from(chain_id))
+	}
+
+	fn block_number(&self) -> Result<U256> {
+		Ok(U256::from(self.best_number()))
+	}
+
+	fn block_by_hash(&self, hash: H256, full: bool) -> Result<Option<RpcBlock>> {
+		match mapping::block(&self.client, hash).map_err(internal_err)? {
+			Some(substrate_hash) => self.rpc_block(substrate_hash, full),
+			None => Ok(None),
+		}
+	}
+
+	fn block_by_number(&self, number: BlockNumber, full: bool) -> Result<Option<RpcBlock>> {
+		match self.substrate_hash(number)? {
+			Some(substrate_hash) => self.rpc_block(substrate_hash, full),
+			None => Ok(None),
+		}
+	}
+
+	fn balance(&self, address: H160, number: Option<BlockNumber>) -> Result<U256> {
+		self.client.runtime_api().balance_of(&self.block_id(number), address).map_err(internal_err)
+	}
+
+	fn transaction_count(&self, address: H160, number: Option<BlockNumber>) -> Result<U256> {
+		self.client.runtime_api().nonce_of(&self.block_id(number), address).map_err(internal_err)
+	}
+
+	fn call(&self, request: CallRequest, number: Option<BlockNumber>) -> Result<Bytes> {
+		let target = request.to.ok_or_else(|| Error::invalid_params("`to` is required"))?;
+		let id = self.block_id(number);
+		let info = self.client.runtime_api().call(
+			&id,
+			request.from.unwrap_or_default(),
+			target,
+			request.data.map(|data| data.0).unwrap_or_default(),
+			request.value.unwrap_or_default(),
+			self.gas_limit(&id, request.gas)?,
+			request.gas_price.unwrap_or_default(),
+		).map_err(internal_err)?;
+		check_status(info.status, &info.value)?;
+
+		Ok(Bytes(info.value))
+	}
+
+	fn estimate_gas(&self, request: CallRequest, number: Option<BlockNumber>) -> Result<U256> {
+		let id = self.block_id(number);
+		let api = self.client.runtime_api();
+		let source = request.from.unwrap_or_default();
+		let data = request.data.map(|data| data.0).unwrap_or_default();
+		let value = request.value.unwrap_or_default();
+		let gas_price = request.gas_price.unwrap_or_default();
+		let gas_limit = self.gas_limit(&id, request.gas)?;
+
+		match request.to {
+			Some(target) => {
+				let info = api.call(&id, source, target, data, value, gas_limit, gas_price)
+					.map_err(internal_err)?;
+				check_status(info.status, &info.value)?;
+				Ok(info.used_gas)
+			},
+			None => {
+				let info = api.create(&id, source, data, value, gas_limit, gas_price)
+					.map_err(internal_err)?;
+				check_status(info.status, &[])?;
+				Ok(info.used_gas)
+			},
+		}
+	}
+
+	fn send_raw_transaction(&self, transaction: Bytes) -> Result<H256> {
+		let hash = H256::from(keccak_256(&transaction.0));
+		let extrinsic = UncheckedExtrinsic::new_unsigned(Call::Evm(EvmCall::transact(transaction.0)));
+		let extrinsic = OpaqueExtrinsic::decode(&mut &extrinsic.encode()[..]).map_err(internal_err)?;
+
+		let best = BlockId::Hash(self.client.info().chain.best_hash);
+		futures::executor::block_on(self.pool.submit_one(&best, extrinsic)).map_err(|error| Error {
+			code: ErrorCode::ServerError(-32000),
+			message: format!("Transaction rejected: {:?}", error),
+			data: None,
+		})?;
+
+		Ok(hash)
+	}
+
+	fn transaction_receipt(&self, hash: H256) -> Result<Option<Receipt>> {
+		let (substrate_hash, index) = match mapping::transaction(&self.client, hash).map_err(internal_err)? {
+			Some(found) => found,
+			None => return Ok(None),
+		};
+		let block = match self.ethereum_block(substrate_hash)? {
+			Some(block) => block,
+			None => return Ok(None),
+		};
+		let receipts = self.receipts(substrate_hash)?;
+		let receipt = match receipts.get(index as usize) {
+			Some(receipt) if receipt.transaction_hash == hash => receipt,
+			_ => return Ok(None),
+		};
+		let logs = block_logs(&block, &receipts).into_iter()
+			.filter(|log| log.transaction_hash == hash)
+			.collect();
+
+		Ok(Some(Receipt {
+			transaction_hash: hash,
+			transaction_index: U256::from(receipt.transaction_index),
+			block_hash: block.hash,
+			block_number: block.number,
+			from: receipt.from,
+			to: receipt.to,
+			cumulative_gas_used: receipt.cumulative_gas_used,
+			gas_used: receipt.used_gas,
+			contract_address: receipt.contract_address,
+			logs,
+			logs_bloom: receipt.logs_bloom,
+			status: if receipt.status == ExitStatus::Succeeded { U256::one() } else { U256::zero() },
+		}))
+	}
+
+	fn logs(&self, filter: Filter) -> Result<Vec<Log>> {
+		let blocks = match filter.block_hash {
+			Some(hash) => mapping::block(&self.client, hash).map_err(internal_err)?.into_iter().collect(),
+			None => {
+				let from = self.number_of(filter.from_block.unwrap_or(BlockNumber::Latest));
+				let to = self.number_of(filter.to_block.unwrap_or(BlockNumber::Latest)).min(self.best_number());
+				if to.saturating_sub(from) >= MAX_LOG_BLOCKS {
+					return Err(Error::invalid_params(format!("Can not search more than {} blocks", MAX_LOG_BLOCKS)))
+				}
+				let mut blocks = Vec::new();
+				for number in from..=to {
+					blocks.extend(self.client.block_hash(number).map_err(internal_err)?);
+				}
+				blocks
+			},
+		};
+
+		let mut logs = Vec::new();
+		for hash in blocks {
+			let block = match self.ethereum_block(hash)? {
+				Some(block) => block,
+				None => continue,
+			};
+			if filter.may_match(&block.logs_bloom) {
+				let receipts = self.receipts(hash)?;
+				logs.extend(block_logs(&block, &receipts).into_iter().filter(|log| filter.matches(log)));
+			}
+		}
+
+		Ok(logs)
+	}
+}
+
+/// The logs emitted in a block.
+fn block_logs(block: &EthereumBlock, receipts: &[EthereumReceipt]) -> Vec<Log> {
+	let mut logs = Vec::new();
+	for receipt in receipts {
+		for log in &receipt.logs {
+			logs.push(Log {
+				address: log.address,
+				topics: log.topics.clone(),
+				data: Bytes(log.data.clone()),
+				block_hash: block.hash,
+				block_number: block.number,
+				transaction_hash: receipt.transaction_hash,
+				transaction_index: U256::from(receipt.transaction_index),
+				log_index: U256::from(logs.len()),
+				removed: false,
+			});
+		}
+	}
+	logs
+}
+
+/// Turns an unsuccessful execution into the error Ethereum clients expect.
+fn check_status(status: ExitStatus, output: &[u8]) -> Result<()> {
+	match status {
+		ExitStatus::Succeeded => Ok(()),
+		ExitStatus::Reverted => Err(Error {
+			code: ErrorCode::ServerError(3),
+			message: "execution reverted".into(),
+			data: serde_json::to_value(Bytes(output.to_vec())).ok(),
+		}),
+		ExitStatus::Failed => Err(Error {
+			code: ErrorCode::ServerError(-32000),
+			message: "execution failed".into(),
+			data: None,
+		}),
+	}
+}
+
+fn internal_err<T: std::fmt::Debug>(error: T) -> Error {
+	Error {
+		code: ErrorCode::InternalError,
+		message: format!("{:?}", error),
+		data: None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn log(address: u64, topics: &[u64]) -> Log {
+		Log {
+			address: H160::from_low_u64_be(address),
+			topics: topics.iter().map(|topic| H256::from_low_u64_be(*topic)).collect(),
+			data: Bytes(vec![]),
+			block_hash: H256::zero(),
+			block_number: U256::zero(),
+			transaction_hash: H256::zero(),
+			transaction_index: U256::zero(),
+			log_index: U256::zero(),
+			removed: false,
+		}
+	}
+
+	#[test]
+	fn block_numbers_are_tags_or_hex() {
+		let parse = |json: &str| serde_json::from_str::<BlockNumber>(json);
+		assert_eq!(parse(r#""latest""#).unwrap(), BlockNumber::Latest);
+		assert_eq!(parse(r#""earliest""#).unwrap(), BlockNumber::Earliest);
+		assert_eq!(parse(r#""pending""#).unwrap(), BlockNumber::Pending);
+		assert_eq!(parse(r#""0x1f""#).unwrap(), BlockNumber::Number(31));
+		assert!(parse(r#""31""#).is_err());
+		assert!(parse(r#""0x1000000000""#).is_err());
+	}
+
+	#[test]
+	fn filters_match_address_and_topics_by_position() {
+		let filter: Filter = serde_json::from_str(r#"{
+			"address": ["0x0000000000000000000000000000000000000001", "0x0000000000000000000000000000000000000002"],
+			"topics": [null, "0x0000000000000000000000000000000000000000000000000000000000000007"]
+		}"#).unwrap();
+
+		assert!(filter.matches(&log(1, &[5, 7])));
+		assert!(filter.matches(&log(2, &[6, 7, 8])));
+		assert!(!filter.matches(&log(3, &[5, 7])));
+		assert!(!filter.matches(&log(1, &[7, 5])));
+		assert!(!filter.matches(&log(1, &[5])));
+		assert!(Filter::default().matches(&log(3, &[])));
+	}
+
+	#[test]
+	fn blocks_are_skipped_when_their_bloom_can_not_match() {
+		let mut bloom = Bloom::default();
+		bloom.accrue(BloomInput::Raw(H160::from_low_u64_be(1).as_bytes()));
+		bloom.accrue(BloomInput::Raw(H256::from_low_u64_be(7).as_bytes()));
+
+		let filter = |json: &str| serde_json::from_str::<Filter>(json).unwrap();
+		assert!(Filter::default().may_match(&bloom));
+		assert!(filter(r#"{
+			"address": ["0x0000000000000000000000000000000000000001", "0x0000000000000000000000000000000000000002"],
+			"topics": [null, "0x0000000000000000000000000000000000000000000000000000000000000007"]
+		}"#).may_match(&bloom));
+		assert!(!filter(r#"{"address": "0x0000000000000000000000000000000000000002"}"#).may_match(&bloom));
+		assert!(!filter(r#"{
+			"topics": ["0x0000000000000000000000000000000000000000000000000000000000000008"]
+		}"#).may_match(&bloom));
+	}
+}
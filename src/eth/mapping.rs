@@ -0,0 +1,83 @@
+//! Maps Ethereum block and transaction hashes to the substrate blocks that hold
+//! them, in the client's auxiliary storage.
+//!
+//! Blocks are indexed as they are imported, from the Ethereum block and
+//! receipts the runtime keeps for them.  Blocks imported without a
+//! notification, as during a major sync, are caught up on along the best
+//! chain.  A transaction included in several forks maps to the block that was
+//! indexed last.
+
+use std::sync::Arc;
+use codec::{Decode, Encode};
+use futures::{future, Future, StreamExt};
+use sc_client_api::{AuxStore, BlockchainEvents};
+use sp_api::ProvideRuntimeApi;
+use sp_core::H256;
+use sp_runtime::generic::BlockId;
+use moonbeam_runtime::EthereumApi;
+use crate::service::FullClient;
+
+const BLOCK_PREFIX: &[u8] = b"ethereum:block:";
+const TRANSACTION_PREFIX: &[u8] = b"ethereum:transaction:";
+/// The number of the last block of the best chain that was indexed.
+const INDEXED_KEY: &[u8] = b"ethereum:indexed";
+
+fn key(prefix: &[u8], hash: &H256) -> Vec<u8> {
+	[prefix, hash.as_bytes()].concat()
+}
+
+fn read<T: Decode>(client: &FullClient, key: &[u8]) -> sp_blockchain::Result<Option<T>> {
+	Ok(client.get_aux(key)?.and_then(|value| T::decode(&mut &value[..]).ok()))
+}
+
+/// The hash of the substrate block whose Ethereum block has this hash.
+pub fn block(client: &FullClient, hash: H256) -> sp_blockchain::Result<Option<H256>> {
+	read(client, &key(BLOCK_PREFIX, &hash))
+}
+
+/// The hash of the substrate block holding the transaction with this hash,
+/// and the index of the transaction in its Ethereum block.
+pub fn transaction(client: &FullClient, hash: H256) -> sp_blockchain::Result<Option<(H256, u32)>> {
+	read(client, &key(TRANSACTION_PREFIX, &hash))
+}
+
+/// Indexes the Ethereum block of the substrate block with this hash.
+fn index_block(client: &FullClient, hash: H256) -> sp_blockchain::Result<()> {
+	let block = match client.runtime_api().current_block(&BlockId::Hash(hash))? {
+		Some(block) => block,
+		None => return Ok(()),
+	};
+
+	let mut entries = vec![(key(BLOCK_PREFIX, &block.hash), hash.encode())];
+	for (index, transaction) in block.transactions.iter().enumerate() {
+		entries.push((key(TRANSACTION_PREFIX, transaction), (hash, index as u32).encode()));
+	}
+	let entries: Vec<(&[u8], &[u8])> = entries.iter().map(|(key, value)| (&key[..], &value[..])).collect();
+	client.insert_aux(&entries, &[])
+}
+
+/// Indexes the blocks of the best chain that have not been yet.
+fn catch_up(client: &FullClient) -> sp_blockchain::Result<()> {
+	let best = client.info().chain.best_number;
+	let mut next = read::<u32>(client, INDEXED_KEY)?.map_or(1, |indexed| indexed + 1);
+	while next <= best {
+		if let Some(hash) = client.block_hash(next)? {
+			index_block(client, hash)?;
+		}
+		client.insert_aux(&[(INDEXED_KEY, &next.encode()[..])], &[])?;
+		next += 1;
+	}
+
+	Ok(())
+}
+
+/// Keeps the mapping up to date with the blocks the client imports.
+pub fn index_imported_blocks(client: Arc<FullClient>) -> impl Future<Output = ()> {
+	client.import_notification_stream().for_each(move |notification| {
+		let result = index_block(&client, notification.hash).and_then(|_| catch_up(&client));
+		if let Err(error) = result {
+			log::warn!("Failed to index Ethereum blocks: {:?}", error);
+		}
+		future::ready(())
+	})
+}
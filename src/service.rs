@@ -2,11 +2,12 @@
 
 use std::sync::Arc;
 use std::time::Duration;
+use futures::channel::mpsc;
 use sc_client::LongestChain;
 use moonbeam_runtime::{self, GenesisConfig, opaque::Block, RuntimeApi};
 use sc_service::{error::{Error as ServiceError}, AbstractService, Configuration, ServiceBuilder};
 use sp_inherents::InherentDataProviders;
-use sc_network::{construct_simple_protocol};
+use sc_network::{construct_simple_protocol, DhtEvent};
 use sc_executor::native_executor_instance;
 pub use sc_executor::NativeExecutor;
 use sp_consensus_aura::sr25519::{AuthorityPair as AuraPair};
@@ -20,6 +21,9 @@ native_executor_instance!(
 	moonbeam_runtime::native_version,
 );
 
+/// The client of a full node.
+pub type FullClient = sc_service::TFullClient<Block, RuntimeApi, Executor>;
+
 construct_simple_protocol! {
 	/// Demo protocol attachment for substrate.
 	pub struct NodeProtocol where Block = Block { }
@@ -74,12 +78,14 @@ macro_rules! new_full_start {
 				Ok(import_queue)
 			})?
 			// added for contracts pallet
-			.with_rpc_extensions(|client, _pool, _backend, _, _| -> Result<RpcExtension, _> {
+			.with_rpc_extensions(|client, pool, _backend, _, _| -> Result<RpcExtension, _> {
                 use pallet_contracts_rpc::{Contracts, ContractsApi};
+                use crate::eth::{Eth, EthApi};
                 let mut io = jsonrpc_core::IoHandler::default();
                 io.extend_with(
                 ContractsApi::to_delegate(Contracts::new(client.clone()))
                 );
+                io.extend_with(EthApi::to_delegate(Eth::new(client.clone(), pool)));
                 Ok(io)
             })?;
 
@@ -101,18 +107,28 @@ pub fn new_full<C: Send + Default + 'static>(config: Configuration<C, GenesisCon
 	// never actively participate in any consensus process.
 	let participates_in_consensus = is_authority && !config.sentry_mode;
 
+	let sentry_nodes = config.network.sentry_nodes.clone();
+
 	let (builder, mut import_setup, inherent_data_providers) = new_full_start!(config);
 
 	let (block_import, grandpa_link) =
 		import_setup.take()
 			.expect("Link Half and Block Import are present for Full Services or setup failed before. qed");
 
+	// the network passes the DHT events authority discovery looks for, with
+	// room for one per authority
+	let (dht_event_tx, dht_event_rx) = mpsc::channel::<DhtEvent>(10_000);
+
 	let service = builder.with_network_protocol(|_| Ok(NodeProtocol::new()))?
 		.with_finality_proof_provider(|client, backend|
 			Ok(Arc::new(GrandpaFinalityProofProvider::new(backend, client)) as _)
 		)?
+		.with_dht_event_tx(dht_event_tx)?
 		.build()?;
 
+	// index Ethereum block and transaction hashes for the `eth_` RPC
+	service.spawn_task(crate::eth::mapping::index_imported_blocks(service.client()));
+
 	if participates_in_consensus {
 		let proposer = sc_basic_authority::ProposerFactory {
 			client: service.client(),
@@ -142,6 +158,17 @@ pub fn new_full<C: Send + Default + 'static>(config: Configuration<C, GenesisCon
 		// the AURA authoring task is considered essential, i.e. if it
 		// fails we take down the service with it.
 		service.spawn_essential_task(aura);
+
+		// publishes the addresses of this validator, or of its sentry nodes,
+		// under its authority discovery key, and finds the other validators
+		let authority_discovery = sc_authority_discovery::AuthorityDiscovery::new(
+			service.client(),
+			service.network(),
+			sentry_nodes,
+			service.keystore(),
+			dht_event_rx,
+		);
+		service.spawn_task(authority_discovery);
 	}
 
 	// if the node isn't actively participating in consensus then it doesn't